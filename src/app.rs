@@ -1,25 +1,97 @@
-use anyhow::Result;
-use serde::Serialize;
-use std::collections::HashSet;
-use std::io::{BufRead, BufReader, Read, Write};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, IsTerminal, Write};
 use std::net::Shutdown;
+use std::os::fd::{FromRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::PathBuf;
-use std::process::exit;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
 use std::{env, fs};
+use tokio::sync::oneshot;
 
+use crate::archive;
+use crate::badges::Badges;
+use crate::client::Client;
+use crate::config::{Config, SingleMonitor, UnknownCommands, WorkspaceSelector};
+use crate::dim::Dimmer;
+use crate::edges;
+use crate::fifo::FifoSink;
+use crate::focus::{self, FocusSession};
+use crate::hooks;
 use crate::hyprland;
-use crate::hyprland::Workspace;
 use crate::hyprland::hyprctl_dispatch as hyprctl;
+use crate::hyprland::{ClientInfo, MonitorInfo, Workspace};
+use crate::layouts::LayoutMemory;
+use crate::progress::{self, Reporter};
+use crate::protocol::{self, Command, Progress};
+use crate::reconcile;
+use crate::rules::{self, Rule, RuleRequest};
+use crate::schedule::{self, Scheduler};
+use crate::session;
+use crate::simulate;
+use crate::sockets::{self, Broadcast, Subscription};
 use crate::state::{
-    DEFAULT_GROUP_ID, DEFAULT_VISIBLE_WORKSPACE, FIRST_INTERNAL_WORKSPACE_ID, GroupId,
-    PersistedState, Slot, SlotId, State, VISIBLE_WORKSPACES_PER_SLOT, VisibleWorkspace,
+    DEFAULT_GROUP_ID, DEFAULT_VISIBLE_WORKSPACE, Group, GroupId, IdLayout, PersistedState, Slot,
+    SlotId, State, VisibleWorkspace, WorkspaceKey,
 };
+use crate::systemd;
+use crate::templates;
+use crate::thumbnails::{self, Thumbnail, Thumbnails};
+use crate::watchdog;
 
 const COMMAND_SOCKET: &str = ".hywoma-commands.sock";
 const EVENT_SOCKET: &str = ".hywoma-events.sock";
+const STATE_SOCKET: &str = ".hywoma-state.sock";
+// Long enough to tell a bar or set a wallpaper, short of what a supervisor waits before it kills.
+const SHUTDOWN_HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+// How long followers and clients waiting for a reply get to see a shutdown before the daemon exits.
+const CLOSE_CONNECTIONS_GRACE: Duration = Duration::from_secs(1);
+
+thread_local! {
+    // Why the message being handled did nothing. Helpers deep in a command record it here instead
+    // of threading it back up, and the main loop passes it on to the client that sent the command.
+    static REJECTION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// Logs a command failure and keeps the first one for the reply.
+fn reject(reason: impl Into<String>) {
+    let reason = reason.into();
+    tracing::warn!("{reason}");
+    REJECTION.with_borrow_mut(|rejection| {
+        rejection.get_or_insert(reason);
+    });
+}
+
+fn take_rejection() -> Option<String> {
+    REJECTION.with_borrow_mut(Option::take)
+}
+
+// Of two group switches sent at once, the later one arrived before the other changed the group and
+// would undo it instead of racing it. Relative switches step from whatever is active and do not
+// conflict. Switching to the group that won is what the client wanted anyway.
+fn superseded_by_group_change(
+    msg: &Message,
+    arrived_at: Instant,
+    group_changed_at: Option<Instant>,
+    active_group: GroupId,
+) -> bool {
+    if group_changed_at.is_none_or(|changed_at| arrived_at >= changed_at) {
+        return false;
+    }
+    match msg {
+        Message::SwitchGroup(group) | Message::SwitchGroupOccupiedOnly(group) => {
+            *group != active_group
+        }
+        Message::EnterSubgroup(_) | Message::LeaveSubgroup => true,
+        _ => false,
+    }
+}
 
 #[derive(Debug)]
 pub enum Message {
@@ -34,30 +106,373 @@ pub enum Message {
         workspace_id: u64,
     },
     MonitorTopologyChanged,
-    Status(mpsc::Sender<String>),
-    TmpSlots(mpsc::Sender<String>),
-    TmpSwapWithSlot(SlotId, mpsc::Sender<String>),
-    SelectWorkspace(VisibleWorkspace),
+    Reconcile,
+    // `hywoma sync`: rebuild everything hywoma caches about Hyprland from fresh queries.
+    Sync,
+    ScheduleTick,
+    WindowOpened {
+        address: String,
+        class: String,
+    },
+    ActiveWindowChanged {
+        address: String,
+    },
+    WindowClosed {
+        address: String,
+    },
+    WindowMoved {
+        address: String,
+        workspace_id: Option<u64>,
+    },
+    // A special workspace shown over the regular one of an output, or `None` once it is hidden.
+    SpecialWorkspaceChanged {
+        monitor_name: String,
+        name: Option<String>,
+    },
+    Status(oneshot::Sender<Result<String, String>>),
+    // Answered by the main loop itself, so a daemon stuck on Hyprland does not pass for alive.
+    Ping(oneshot::Sender<Result<String, String>>),
+    TmpSlots(oneshot::Sender<Result<String, String>>),
+    TmpSwapWithSlot(SlotId, oneshot::Sender<Result<String, String>>),
+    Rule(RuleRequest, oneshot::Sender<Result<String, String>>),
+    SwitchProfile(String, oneshot::Sender<Result<String, String>>),
+    ListProfiles(oneshot::Sender<Result<String, String>>),
+    SaveSession(oneshot::Sender<Result<String, String>>),
+    PlanGroupSwitch(GroupId, oneshot::Sender<Result<String, String>>),
+    // Whether to retile every group, not just the active one.
+    Retile(bool, oneshot::Sender<Result<String, String>>),
+    // Pattern, whether to focus the first match.
+    Where(String, bool, oneshot::Sender<Result<String, String>>),
+    // Whether to include monitors, workspaces and windows, and whether to answer in JSON.
+    ListGroups(bool, bool, oneshot::Sender<Result<String, String>>),
+    // `hywoma hyprctl` arguments, and whether to skip the safety checks.
+    Hyprctl(Vec<String>, bool, oneshot::Sender<Result<String, String>>),
+    RestoreSession(oneshot::Sender<Result<String, String>>),
+    ArchiveGroup(GroupId, oneshot::Sender<Result<String, String>>),
+    RestoreGroup(GroupId, oneshot::Sender<Result<String, String>>),
+    // Carries the command socket's listening FD, which the successor daemon inherits.
+    RestartServer(RawFd, oneshot::Sender<Result<u32>>),
+    // With `--monitor`, the slot to act on instead of the focused one.
+    SelectWorkspace(VisibleWorkspace, Option<SlotTarget>),
     SelectWorkspaceDelta(i64),
-    MoveToWorkspace(VisibleWorkspace),
+    // `next`/`prev`, relative to the focused slot's visible workspace whether occupied or not.
+    SelectWorkspaceRelative(i64),
+    // With `--follow`, focus goes along with the window even without `follow_moves`.
+    MoveToWorkspace(VisibleWorkspace, Option<SlotTarget>, bool),
+    MoveToWorkspaceRelative(i64, bool),
+    // Back to the focused slot's previous workspace in the active group, like i3's
+    // `workspace back_and_forth`.
+    ToggleWorkspace,
+    MoveToEmpty,
+    Compact,
+    // A label for the focused workspace of the active group, or none to remove it.
+    RenameWorkspace(Option<String>),
+    // `layout <name>` and `layoutmsg <message>`, remembered for the focused workspace.
+    SetLayout(String),
+    LayoutMsg(String),
     SwitchGroup(GroupId),
-    CreateGroup(String),
+    SwitchGroupRelative(i64),
+    SwitchGroupOccupiedOnly(GroupId),
+    // Name and optional template.
+    CreateGroup(String, Option<String>),
     RenameGroup(GroupId, String),
     DeleteGroup(GroupId),
+    // A new sub-group of the active group, optionally named, and back to the parent.
+    EnterSubgroup(Option<String>),
+    LeaveSubgroup,
     MoveToGroup(GroupId),
-    SelectSlot(u64),
-    MoveToSlot(u64),
-    SwapSlot(u64),
-    SubscribeEvents(UnixStream),
+    SelectSlot(SlotTarget),
+    // With `--follow`, focus goes along with the window even without `follow_moves`.
+    MoveToSlot(SlotTarget, bool),
+    // The focused workspace's windows, to the same workspace number on another slot if it is free.
+    MoveWorkspaceToSlot(SlotTarget, bool),
+    // Every window of the active group onto the focused workspace.
+    Gather,
+    SwapSlot(SlotTarget),
+    // Without monitors, the leftmost and the rightmost one.
+    SwapMonitors(Option<(SlotTarget, SlotTarget)>),
+    SwapWithWorkspace(VisibleWorkspace),
+    // Without a monitor, onto the outermost one other than the focused one.
+    MirrorGroup(GroupId, Option<SlotTarget>),
+    // Hyprland's scratchpads, the unnamed one without a name.
+    ToggleSpecial(Option<String>),
+    MoveToSpecial(Option<String>),
+    StopMirror,
+    // Rejected or forwarded to `hyprctl dispatch`, depending on `unknown_commands`.
+    Unknown(Vec<String>),
+    // Minutes, or the configured default.
+    Snooze(Option<u64>),
+    Idle,
+    Resume,
+    // Alt-tab through the active group's windows on every monitor; `--reverse` goes backwards.
+    CycleWindow(bool),
+    LockWindow(WindowTarget),
+    // `select add|clear|move_to_workspace N`: windows marked one by one, then moved together.
+    MarkWindow(WindowTarget),
+    ClearMarks,
+    MoveMarkedToWorkspace(VisibleWorkspace),
+    UnlockWindow(WindowTarget),
+    SubscribeEvents(oneshot::Sender<Subscription>),
+    // A `hywoma watch` client, following focus changes until it goes away.
+    Watch(oneshot::Sender<Subscription>),
+    Shutdown(ShutdownReason),
+    // From the watchdog, whenever Hyprland stops or resumes answering.
+    BackendHealth(bool),
+    // Seconds to hold the active group for.
+    FocusStart(u64),
+    FocusStop,
+    // Not sent by anyone: the main loop's receive timing out at the end of a focus session.
+    FocusExpired,
+    // Goes through a focus session, from `--force`.
+    Forced(Box<Message>),
+    // A group message for the group with this name, filled in by the main loop.
+    NamedGroup(String, Box<Message>),
+    // A command whose client waits to hear whether it worked, with when it arrived.
+    Replying(Box<Message>, oneshot::Sender<Result<(), String>>, Instant),
+    // A command whose client is shown the progress of its bulk dispatches.
+    Reporting(Box<Message>, Reporter),
+}
+
+// Why the daemon stopped, with an exit code per reason so a supervisor or wrapper script can tell
+// a compositor that went away from a broken socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    HyprlandEvents,
+    CommandSocket,
+    EventSocket,
+    StateSocket,
+    // Every sender is gone without any of them reporting why.
+    Disconnected,
+    // SIGTERM or SIGINT, the one shutdown that is not a failure.
+    Signal,
+}
+
+impl ShutdownReason {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ShutdownReason::HyprlandEvents => 1,
+            ShutdownReason::CommandSocket => 2,
+            ShutdownReason::EventSocket => 3,
+            ShutdownReason::Disconnected => 4,
+            ShutdownReason::StateSocket => 5,
+            ShutdownReason::Signal => 0,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            ShutdownReason::HyprlandEvents => "Hyprland event reader stopped",
+            ShutdownReason::CommandSocket => "hywoma command socket reader stopped",
+            ShutdownReason::EventSocket => "hywoma event socket reader stopped",
+            ShutdownReason::StateSocket => "hywoma state socket reader stopped",
+            ShutdownReason::Disconnected => "all message senders disconnected",
+            ShutdownReason::Signal => "asked to terminate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowTarget {
+    Focused,
+    // Without the 0x prefix, like the addresses in Hyprland events.
+    Address(String),
+}
+
+impl WindowTarget {
+    pub fn parse(arg: &str) -> Self {
+        if arg == "focused" {
+            return WindowTarget::Focused;
+        }
+        let address = arg.strip_prefix("address:").unwrap_or(arg);
+        WindowTarget::Address(address.trim_start_matches("0x").to_string())
+    }
+
+    fn resolve(&self) -> Result<Option<String>> {
+        match self {
+            WindowTarget::Focused => hyprland::get_active_window_address(),
+            WindowTarget::Address(address) => Ok(Some(address.clone())),
+        }
+    }
+}
+
+// The window a lock applies to and the workspace it is currently on, which it will be kept on.
+fn window_lock(target: &WindowTarget) -> Result<(String, u64)> {
+    let address = target
+        .resolve()?
+        .ok_or_else(|| anyhow!("No window is focused"))?;
+    let workspace_id = *hyprland::get_window_workspace_ids()?
+        .get(&address)
+        .ok_or_else(|| anyhow!("No window {address} on a regular workspace"))?;
+    Ok((address, workspace_id))
+}
+
+// Slot commands accept either the slot number or the name of the output attached to it, so
+// scripts keep working when slots are reassigned. `left`/`right` and `-1`/`+1` step from the
+// focused slot through the monitors from left to right, for one binding per direction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotTarget {
+    Slot(SlotId),
+    Output(String),
+    Relative(i64),
+    // With `--clamp`: a slot past the attached monitors means the nearest attached one, and a
+    // relative target stops at the outermost monitor.
+    Clamped(Box<SlotTarget>),
+}
+
+impl SlotTarget {
+    pub fn parse(arg: &str) -> Self {
+        match arg {
+            "left" => return SlotTarget::Relative(-1),
+            "right" => return SlotTarget::Relative(1),
+            _ => {}
+        }
+        if arg.starts_with(['+', '-'])
+            && let Ok(delta) = arg.parse()
+        {
+            return SlotTarget::Relative(delta);
+        }
+        arg.parse()
+            .map(SlotTarget::Slot)
+            .unwrap_or_else(|_| SlotTarget::Output(arg.to_string()))
+    }
+
+    pub fn clamped(self, clamp: bool) -> Self {
+        if clamp {
+            SlotTarget::Clamped(Box::new(self))
+        } else {
+            self
+        }
+    }
+}
+
+// `monitors` is the x-sorted monitor list relative targets step through.
+fn try_resolve_slot_target(
+    state: &State,
+    focused_slot: SlotId,
+    monitors: &[MonitorInfo],
+    target: &SlotTarget,
+    clamp: bool,
+) -> Result<SlotId> {
+    match target {
+        SlotTarget::Clamped(target) => {
+            try_resolve_slot_target(state, focused_slot, monitors, target, true)
+        }
+        SlotTarget::Slot(slot) if clamp && state.runtime_monitor_id_for_slot(*slot).is_none() => {
+            state
+                .slots
+                .values()
+                .filter(|candidate| candidate.runtime_monitor_id.is_some())
+                .map(|candidate| candidate.id)
+                .min_by_key(|candidate| (candidate.abs_diff(*slot), *candidate))
+                .ok_or_else(|| anyhow!("No slot is attached to a monitor"))
+        }
+        SlotTarget::Slot(slot) if slot_to_monitor_pos(*slot).is_some() => Ok(*slot),
+        SlotTarget::Slot(slot) => Err(anyhow!("Slot numbers start at 1, got {slot}")),
+        SlotTarget::Output(output) => state
+            .slot_for_output_name(output)
+            .ok_or_else(|| anyhow!("No slot is attached to output {output}")),
+        SlotTarget::Relative(delta) => {
+            let order: Vec<SlotId> = monitors
+                .iter()
+                .filter_map(|monitor| state.slot_for_monitor_id(monitor.id))
+                .collect();
+            let position = order
+                .iter()
+                .position(|slot| *slot == focused_slot)
+                .ok_or_else(|| anyhow!("The focused slot {focused_slot} is not attached"))?;
+            let target = position.checked_add_signed(*delta as isize);
+            let target = if clamp {
+                Some(target.unwrap_or(0).min(order.len() - 1))
+            } else {
+                target
+            };
+            target
+                .and_then(|position| order.get(position))
+                .copied()
+                .ok_or_else(|| anyhow!("No monitor {delta:+} from the focused slot {focused_slot}"))
+        }
+    }
+}
+
+// The slots on the leftmost and the rightmost monitor.
+fn outermost_slots(state: &State, monitors: &[MonitorInfo]) -> Option<(SlotId, SlotId)> {
+    let mut order = monitors
+        .iter()
+        .filter_map(|monitor| state.slot_for_monitor_id(monitor.id));
+    let first = order.next();
+    let last = order.next_back();
+    match first.zip(last) {
+        Some(slots) => Some(slots),
+        None => {
+            reject("Swapping monitors needs at least two attached monitors");
+            None
+        }
+    }
+}
+
+// With a single monitor attached, slot commands have nothing to act on unless they target the
+// attached slot itself, which `--clamp` always does.
+fn needs_another_monitor(
+    state: &State,
+    focused_slot: SlotId,
+    monitors: &[MonitorInfo],
+    msg: &Message,
+) -> bool {
+    let mut attached = state
+        .slots
+        .values()
+        .filter(|slot| slot.runtime_monitor_id.is_some());
+    let (Some(only), None) = (attached.next(), attached.next()) else {
+        return false;
+    };
+    let targets = match msg {
+        Message::SelectSlot(target)
+        | Message::MoveToSlot(target, _)
+        | Message::MoveWorkspaceToSlot(target, _)
+        | Message::SwapSlot(target) => {
+            vec![target]
+        }
+        Message::SwapMonitors(Some((a, b))) => vec![a, b],
+        Message::SwapMonitors(None) | Message::MirrorGroup(_, None) => return true,
+        Message::MirrorGroup(_, Some(target)) => vec![target],
+        _ => return false,
+    };
+    targets.into_iter().any(|target| {
+        try_resolve_slot_target(state, focused_slot, monitors, target, false).ok() != Some(only.id)
+    })
+}
+
+// Commands that cannot be resolved to a slot are logged and otherwise ignored.
+fn resolve_slot_target(
+    state: &State,
+    focused_slot: SlotId,
+    monitors: &[MonitorInfo],
+    target: &SlotTarget,
+) -> Option<SlotId> {
+    try_resolve_slot_target(state, focused_slot, monitors, target, false)
+        .map_err(|err| reject(err.to_string()))
+        .ok()
 }
 
 #[derive(Debug, Serialize)]
 struct StatusSnapshot {
+    // Hyprland's ID of the focused workspace, and what it is in the model.
     active_workspace_id: u64,
+    active_workspace: Option<WorkspaceKey>,
     focused_slot: SlotId,
+    focused_monitor_id: Option<u64>,
+    // Hyprland monitor IDs from left to right, the order `left`/`right` slot targets step through.
+    monitor_ids: Vec<u64>,
     present_workspace_ids: Vec<u64>,
     detached_slots: Vec<SlotWorkspaceSummary>,
     state: crate::state::StateSnapshot,
+    // The name `select_group` and friends also take in place of the number.
+    active_group_name: String,
+    // False while the watchdog finds Hyprland unresponsive and commands are queued.
+    backend_healthy: bool,
+    // Only present when thumbnails are enabled and something has been captured.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    thumbnails: Vec<Thumbnail>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -73,8 +488,8 @@ fn slot_to_monitor_pos(slot: u64) -> Option<u64> {
     slot.checked_sub(1)
 }
 
-fn default_state() -> State {
-    State::new(default_slots())
+fn default_state(id_layout: IdLayout) -> State {
+    State::with_id_layout(id_layout, default_slots())
 }
 
 fn default_slots() -> [Slot; 3] {
@@ -91,7 +506,28 @@ fn hostname() -> Option<String> {
         .map(|hostname| hostname.trim().to_string())
 }
 
-fn attach_monitors_for_host(state: &mut State, monitors: &[hyprland::MonitorInfo]) {
+fn attach_monitors_for_host(
+    config: &Config,
+    state: &mut State,
+    monitors: &[hyprland::MonitorInfo],
+) {
+    if !config.monitors.is_empty() {
+        // A configured mapping (usually per profile) replaces the built-in host policies below.
+        let output_slots: Vec<(&str, SlotId)> = config
+            .monitors
+            .iter()
+            .filter(|(output, slot)| {
+                let known = state.slots.contains_key(slot);
+                if !known {
+                    tracing::warn!("Ignoring monitor mapping {output} -> unknown slot {slot}");
+                }
+                known
+            })
+            .map(|(output, slot)| (output.as_str(), *slot))
+            .collect();
+        state.attach_monitors_fixed_outputs(monitors, &output_slots);
+        return;
+    }
     match hostname().as_deref() {
         Some("pavellt") => {
             // Laptop muscle memory: the built-in panel is the main/default slot on Win+i. Hotplugged
@@ -110,6 +546,482 @@ fn attach_monitors_for_host(state: &mut State, monitors: &[hyprland::MonitorInfo
     }
 }
 
+// Names are looked up among the workspaces present, so a named workspace created later is picked up
+// once it exists.
+fn refresh_unmanaged_workspaces(config: &Config, state: &mut State) {
+    let mut workspace_ids = HashSet::new();
+    let mut names = Vec::new();
+    for selector in &config.unmanaged_workspaces {
+        match selector {
+            WorkspaceSelector::Id(workspace_id) => {
+                workspace_ids.insert(*workspace_id);
+            }
+            WorkspaceSelector::Name(name) => names.push(name.as_str()),
+        }
+    }
+    if !names.is_empty() {
+        match hyprland::get_workspaces() {
+            Ok(workspaces) => workspace_ids.extend(
+                workspaces
+                    .into_iter()
+                    .filter(|workspace| names.contains(&workspace.name.as_str()))
+                    .map(|workspace| workspace.id),
+            ),
+            Err(err) => tracing::warn!("Failed to look up unmanaged workspaces: {err:?}"),
+        }
+    }
+    state.set_unmanaged_workspaces(workspace_ids);
+}
+
+fn apply_group_names(config: &Config, state: &mut State) {
+    for group in &config.groups {
+        if let Some(name) = &group.name {
+            state.ensure_group(group.id, name.clone());
+            state.rename_group(group.id, name.clone());
+        }
+    }
+}
+
+// Floating windows that should tile again, typically after hotplug or adoption moved them between
+// monitors. Only managed workspaces count: special workspaces are meant to float.
+fn windows_to_retile<'a>(
+    config: &Config,
+    state: &State,
+    clients: &'a [ClientInfo],
+    all: bool,
+) -> Vec<&'a ClientInfo> {
+    clients
+        .iter()
+        .filter(|client| client.floating)
+        .filter(|client| !config.retile.keep_floating.contains(&client.class))
+        .filter(|client| {
+            state
+                .key_for_workspace_id(client.workspace_id)
+                .is_some_and(|key| all || key.group == state.active_group)
+        })
+        .collect()
+}
+
+fn retile(config: &Config, state: &State, all: bool) -> Result<usize> {
+    let clients = hyprland::get_clients()?;
+    let windows = windows_to_retile(config, state, &clients, all);
+    let dispatches: Vec<String> = windows
+        .iter()
+        .map(|window| format!("dispatch settiled address:0x{}", window.address))
+        .collect();
+    hyprland::hyprctl_batch(config, &dispatches)?;
+    Ok(windows.len())
+}
+
+// Windows on the active group's other workspaces, on any slot. Locked windows stay where they are,
+// that being what the lock is for.
+fn windows_to_gather<'a>(
+    state: &State,
+    clients: &'a [ClientInfo],
+    workspace_id: u64,
+    locked_windows: &HashMap<String, u64>,
+) -> Vec<&'a ClientInfo> {
+    clients
+        .iter()
+        .filter(|client| client.workspace_id != workspace_id)
+        .filter(|client| !locked_windows.contains_key(&client.address))
+        .filter(|client| {
+            state
+                .key_for_workspace_id(client.workspace_id)
+                .is_some_and(|key| key.group == state.active_group)
+        })
+        .collect()
+}
+
+// Case-insensitive substring match on class or title, in Hyprland's window order.
+fn matching_windows<'a>(clients: &'a [ClientInfo], pattern: &str) -> Vec<&'a ClientInfo> {
+    let pattern = pattern.to_lowercase();
+    clients
+        .iter()
+        .filter(|client| {
+            client.class.to_lowercase().contains(&pattern)
+                || client.title.to_lowercase().contains(&pattern)
+        })
+        .collect()
+}
+
+// The window after (or before) the focused one among the active group's windows, ordered by slot
+// and workspace. Within a workspace Hyprland's client order is kept, so the cycle is stable.
+fn cycle_target<'a>(
+    state: &State,
+    clients: &'a [ClientInfo],
+    current: Option<&str>,
+    reverse: bool,
+) -> Option<&'a ClientInfo> {
+    let mut windows: Vec<(WorkspaceKey, &ClientInfo)> = clients
+        .iter()
+        .filter_map(|client| {
+            let key = state.key_for_workspace_id(client.workspace_id)?;
+            (key.group == state.active_group).then_some((key, client))
+        })
+        .collect();
+    if windows.is_empty() {
+        return None;
+    }
+    windows.sort_by_key(|(key, _)| (key.slot, key.visible));
+    let position = current.and_then(|address| {
+        windows
+            .iter()
+            .position(|(_, window)| window.address == address)
+    });
+    let count = windows.len();
+    let index = match (position, reverse) {
+        (Some(position), false) => (position + 1) % count,
+        (Some(position), true) => (position + count - 1) % count,
+        (None, false) => 0,
+        (None, true) => count - 1,
+    };
+    Some(windows[index].1)
+}
+
+// `list_groups --tree`: group -> monitor -> workspace -> windows. Each slot lists the group's
+// current workspace there and every workspace holding windows, so empty ones do not pad the tree.
+#[derive(Debug, Serialize)]
+struct GroupNode {
+    id: GroupId,
+    name: String,
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<GroupId>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    monitors: Vec<MonitorNode>,
+}
+
+#[derive(Debug, Serialize)]
+struct MonitorNode {
+    slot: SlotId,
+    label: String,
+    output: Option<String>,
+    workspaces: Vec<WorkspaceNode>,
+}
+
+#[derive(Debug, Serialize)]
+struct WorkspaceNode {
+    workspace: VisibleWorkspace,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    workspace_id: Option<u64>,
+    current: bool,
+    windows: Vec<WindowNode>,
+}
+
+#[derive(Debug, Serialize)]
+struct WindowNode {
+    address: String,
+    class: String,
+    title: String,
+}
+
+// Without clients, just the groups.
+fn group_tree(state: &State, clients: Option<&[ClientInfo]>) -> Vec<GroupNode> {
+    let mut groups: Vec<&Group> = state.groups.values().collect();
+    groups.sort_by_key(|group| group.id);
+    let mut slots: Vec<&Slot> = state.slots.values().collect();
+    slots.sort_by_key(|slot| slot.id);
+    groups
+        .into_iter()
+        .map(|group| GroupNode {
+            id: group.id,
+            name: group.name.clone(),
+            active: group.id == state.active_group,
+            parent: group.parent,
+            monitors: clients
+                .map(|clients| {
+                    slots
+                        .iter()
+                        .map(|slot| monitor_node(state, clients, group.id, slot))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn monitor_node(state: &State, clients: &[ClientInfo], group: GroupId, slot: &Slot) -> MonitorNode {
+    let current = state.active_visible_in_group(group, slot.id);
+    let mut windows_by_workspace: BTreeMap<VisibleWorkspace, Vec<WindowNode>> = BTreeMap::new();
+    windows_by_workspace.insert(current, Vec::new());
+    for client in clients {
+        if let Some(key) = state.key_for_workspace_id(client.workspace_id)
+            && key.group == group
+            && key.slot == slot.id
+        {
+            windows_by_workspace
+                .entry(key.visible)
+                .or_default()
+                .push(WindowNode {
+                    address: client.address.clone(),
+                    class: client.class.clone(),
+                    title: client.title.clone(),
+                });
+        }
+    }
+    MonitorNode {
+        slot: slot.id,
+        label: slot.label.clone(),
+        output: slot.attached_output.clone(),
+        workspaces: windows_by_workspace
+            .into_iter()
+            .map(|(workspace, windows)| WorkspaceNode {
+                workspace,
+                label: state
+                    .workspace_label(WorkspaceKey {
+                        group,
+                        slot: slot.id,
+                        visible: workspace,
+                    })
+                    .map(str::to_string),
+                workspace_id: state.known_workspace_id(group, slot.id, workspace),
+                current: workspace == current,
+                windows,
+            })
+            .collect(),
+    }
+}
+
+// Two spaces per level, so fzf and friends can show it as is and still match on any part.
+fn describe_group_tree(groups: &[GroupNode]) -> String {
+    let mut lines = Vec::new();
+    for group in groups {
+        let mut line = format!("{} (group {}", group.name, group.id);
+        if let Some(parent) = group.parent {
+            line.push_str(&format!(", sub-group of {parent}"));
+        }
+        if group.active {
+            line.push_str(", active");
+        }
+        lines.push(line + ")");
+        for monitor in &group.monitors {
+            let output = monitor
+                .output
+                .as_deref()
+                .map_or_else(|| "detached".to_string(), str::to_string);
+            lines.push(format!(
+                "  {} monitor (slot {}, {output})",
+                monitor.label, monitor.slot
+            ));
+            for workspace in &monitor.workspaces {
+                let label = workspace
+                    .label
+                    .as_ref()
+                    .map_or_else(String::new, |label| format!(" {label:?}"));
+                let current = if workspace.current { ", current" } else { "" };
+                lines.push(format!(
+                    "    workspace {}{label} ({} window(s){current})",
+                    workspace.workspace,
+                    workspace.windows.len()
+                ));
+                for window in &workspace.windows {
+                    lines.push(format!(
+                        "      {} {:?} 0x{}",
+                        window.class, window.title, window.address
+                    ));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+fn describe_windows(state: &State, windows: &[&ClientInfo]) -> String {
+    if windows.is_empty() {
+        return "No matching windows".to_string();
+    }
+    windows
+        .iter()
+        .map(|window| {
+            let location = match state.key_for_workspace_id(window.workspace_id) {
+                Some(key) => {
+                    let group = state.groups.get(&key.group).map_or_else(
+                        || format!("group {}", key.group),
+                        |group| group.name.clone(),
+                    );
+                    let slot = state
+                        .slots
+                        .get(&key.slot)
+                        .map_or_else(|| format!("slot {}", key.slot), |slot| slot.label.clone());
+                    format!("{group}, {slot} monitor, workspace {}", key.visible)
+                }
+                None => format!("unmanaged workspace {}", window.workspace_id),
+            };
+            format!("{} {:?}: {location}", window.class, window.title)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Looks up the group of a `NamedGroup` message, also under `--force`. Messages answered over their
+// own channel get the error there too.
+fn resolve_group_name(state: &State, msg: Message) -> Result<Message, String> {
+    match msg {
+        Message::Forced(msg) => Ok(Message::Forced(Box::new(resolve_group_name(state, *msg)?))),
+        Message::NamedGroup(name, msg) => match state.group_named(&name) {
+            Some(group) => Ok(with_group(*msg, group)),
+            None => {
+                let text =
+                    format!("No group named {name:?}, create it with `hywoma create-group {name}`");
+                if let Message::PlanGroupSwitch(_, tx)
+                | Message::ArchiveGroup(_, tx)
+                | Message::RestoreGroup(_, tx) = *msg
+                {
+                    let _ = tx.send(Err(text.clone()));
+                }
+                Err(text)
+            }
+        },
+        msg => Ok(msg),
+    }
+}
+
+fn with_group(msg: Message, group: GroupId) -> Message {
+    match msg {
+        Message::SwitchGroup(_) => Message::SwitchGroup(group),
+        Message::SwitchGroupOccupiedOnly(_) => Message::SwitchGroupOccupiedOnly(group),
+        Message::PlanGroupSwitch(_, tx) => Message::PlanGroupSwitch(group, tx),
+        Message::RenameGroup(_, name) => Message::RenameGroup(group, name),
+        Message::DeleteGroup(_) => Message::DeleteGroup(group),
+        Message::MoveToGroup(_) => Message::MoveToGroup(group),
+        Message::MirrorGroup(_, target) => Message::MirrorGroup(group, target),
+        Message::ArchiveGroup(_, tx) => Message::ArchiveGroup(group, tx),
+        Message::RestoreGroup(_, tx) => Message::RestoreGroup(group, tx),
+        msg => msg,
+    }
+}
+
+// Commands that talk to Hyprland. Responses about the model alone, like `status`, still work
+// while it hangs, and events only arrive once it is back anyway.
+fn pauses_while_unhealthy(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::SelectWorkspace(..)
+            | Message::SelectWorkspaceDelta(_)
+            | Message::SelectWorkspaceRelative(_)
+            | Message::MoveToWorkspace(..)
+            | Message::MoveToWorkspaceRelative(..)
+            | Message::ToggleWorkspace
+            | Message::MoveToEmpty
+            | Message::Compact
+            | Message::SetLayout(_)
+            | Message::LayoutMsg(_)
+            | Message::RenameWorkspace(_)
+            | Message::SwitchGroup(_)
+            | Message::SwitchGroupRelative(_)
+            | Message::SwitchGroupOccupiedOnly(_)
+            | Message::EnterSubgroup(_)
+            | Message::LeaveSubgroup
+            | Message::MoveToGroup(_)
+            | Message::SelectSlot(_)
+            | Message::MoveToSlot(..)
+            | Message::MoveWorkspaceToSlot(..)
+            | Message::Gather
+            | Message::SwapSlot(_)
+            | Message::SwapMonitors(_)
+            | Message::SwapWithWorkspace(_)
+            | Message::MirrorGroup(..)
+            | Message::StopMirror
+            | Message::Unknown(_)
+            | Message::ToggleSpecial(_)
+            | Message::MoveToSpecial(_)
+            | Message::LockWindow(_)
+            | Message::UnlockWindow(_)
+            | Message::MarkWindow(_)
+            | Message::MoveMarkedToWorkspace(_)
+            | Message::Resume
+            | Message::Sync
+            | Message::Retile(..)
+            | Message::Where(..)
+            | Message::ListGroups(..)
+            | Message::Hyprctl(..)
+            | Message::CycleWindow(_)
+            | Message::SaveSession(_)
+            | Message::RestoreSession(_)
+            | Message::ArchiveGroup(..)
+            | Message::RestoreGroup(..)
+    )
+}
+
+// Dispatchers that place workspaces, which hywoma does itself. Sent behind its back they leave
+// workspaces where no group expects them.
+const MANAGED_DISPATCHERS: &[&str] = &[
+    "workspace",
+    "movetoworkspace",
+    "movetoworkspacesilent",
+    "moveworkspacetomonitor",
+    "movecurrentworkspacetomonitor",
+    "focusworkspaceoncurrentmonitor",
+    "swapactiveworkspaces",
+    "renameworkspace",
+];
+
+// Why a dispatcher is not sent to Hyprland on a client's behalf, if it is not.
+fn dispatch_refusal(dispatcher: &str) -> Option<&'static str> {
+    if dispatcher == "exit" {
+        Some("it would end the Hyprland session")
+    } else if MANAGED_DISPATCHERS.contains(&dispatcher) {
+        Some("hywoma places workspaces itself, use its own commands")
+    } else {
+        None
+    }
+}
+
+// The socket request for `hywoma hyprctl` arguments, as hyprctl would send it: `-j clients`
+// becomes `-j/clients`. Refuses, with the reason, what would quit the session or bypass hywoma's
+// workspace placement unless `unchecked`. Flags come before the request, later words like the `-10`
+// of `dispatch resizeactive -10 0` are passed on as they are.
+fn passthrough_request(args: &[String], unchecked: bool) -> Result<String, String> {
+    let flag_count = args.iter().take_while(|arg| arg.starts_with('-')).count();
+    let (flags, words) = args.split_at(flag_count);
+    let request = words.join(" ");
+    if request.is_empty() {
+        return Err("Nothing to send to Hyprland".to_string());
+    }
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| !matches!(flag.as_str(), "-j" | "--json"))
+    {
+        return Err(format!(
+            "Unsupported hyprctl flag {flag}, only -j is passed on"
+        ));
+    }
+    if !unchecked && let Some(("dispatch", dispatch)) = request.split_once(' ') {
+        let dispatcher = dispatch.split_whitespace().next().unwrap_or_default();
+        if let Some(reason) = dispatch_refusal(dispatcher) {
+            return Err(format!(
+                "Refusing `dispatch {dispatcher}`: {reason}. Pass --unchecked to send it anyway."
+            ));
+        }
+    }
+    Ok(if flags.is_empty() {
+        request
+    } else {
+        format!("-j/{request}")
+    })
+}
+
+fn list_profiles(base_config: &Config, config: &Config) -> String {
+    if base_config.profiles.is_empty() {
+        return "No profiles".to_string();
+    }
+    base_config
+        .profiles
+        .keys()
+        .map(|name| {
+            let active = if config.profile.as_ref() == Some(name) {
+                "*"
+            } else {
+                ""
+            };
+            format!("{name}{active}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn runtime_state_path() -> Result<PathBuf> {
     let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")?;
     // This is intentionally under XDG_RUNTIME_DIR, not XDG_STATE_HOME. It lets the daemon survive
@@ -119,19 +1031,23 @@ fn runtime_state_path() -> Result<PathBuf> {
         .join("state.json"))
 }
 
-fn load_runtime_state() -> Option<State> {
+fn load_runtime_state(id_layout: IdLayout) -> Option<State> {
+    // A simulated session must never pick up or clobber the state of a live daemon.
+    if simulate::is_active() {
+        return None;
+    }
     let path = runtime_state_path().ok()?;
     let data = fs::read_to_string(&path).ok()?;
     let persisted: PersistedState = match serde_json::from_str(&data) {
         Ok(persisted) => persisted,
         Err(err) => {
-            eprintln!("Ignoring invalid hywoma runtime state {path:?}: {err:?}");
+            tracing::warn!("Ignoring invalid hywoma runtime state {path:?}: {err:?}");
             return None;
         }
     };
     // If the schema version does not match, State::from_persisted returns None and the daemon
     // starts from a clean state. That is safer than trying to interpret stale mappings.
-    State::from_persisted(default_slots(), persisted)
+    State::from_persisted(id_layout, default_slots(), persisted)
 }
 
 fn save_runtime_state(state: &State) -> Result<()> {
@@ -150,29 +1066,60 @@ fn save_runtime_state(state: &State) -> Result<()> {
 }
 
 fn persist_runtime_state(state: &State) {
+    if simulate::is_active() {
+        return;
+    }
     if let Err(err) = save_runtime_state(state) {
-        eprintln!("Failed to save hywoma runtime state: {err:?}");
+        tracing::warn!("Failed to save hywoma runtime state: {err:?}");
+    }
+}
+
+// A simulated session has no real windows and must not touch the recorded one.
+fn save_session(state: &State) -> Result<usize> {
+    if simulate::is_active() {
+        return Err(anyhow!("Sessions are not recorded in simulation"));
+    }
+    session::save(state)
+}
+
+fn restore_session(config: &Config, state: &mut State) -> Result<usize> {
+    if simulate::is_active() {
+        return Err(anyhow!("Sessions are not restored in simulation"));
     }
+    session::restore(config, state)
 }
 
 fn status_snapshot(
     active_workspace_id: u64,
     focused_slot: SlotId,
     present_workspace_ids: &HashSet<u64>,
+    monitors: &[MonitorInfo],
     state: &State,
+    thumbnails: &Thumbnails,
+    backend_healthy: bool,
 ) -> StatusSnapshot {
     let mut present_workspace_id_list: Vec<u64> = present_workspace_ids.iter().copied().collect();
     present_workspace_id_list.sort_unstable();
 
     StatusSnapshot {
         active_workspace_id,
+        active_workspace: state.key_for_workspace_id(active_workspace_id),
         focused_slot,
+        focused_monitor_id: state.runtime_monitor_id_for_slot(focused_slot),
+        monitor_ids: monitors.iter().map(|monitor| monitor.id).collect(),
         present_workspace_ids: present_workspace_id_list,
         detached_slots: slot_workspace_summaries(state, present_workspace_ids)
             .into_iter()
             .filter(|summary| summary.detached)
             .collect(),
         state: state.snapshot(),
+        active_group_name: state
+            .groups
+            .get(&state.active_group)
+            .map(|group| group.name.clone())
+            .unwrap_or_default(),
+        backend_healthy,
+        thumbnails: thumbnails.list(),
     }
 }
 
@@ -228,53 +1175,63 @@ fn tmp_slots_response(state: &State, present_workspace_ids: &HashSet<u64>) -> St
         .join("\n")
 }
 
-fn write_event_snapshot(
-    stream: &mut UnixStream,
-    active_workspace_id: u64,
-    focused_slot: SlotId,
-    present_workspace_ids: &HashSet<u64>,
-    state: &State,
-) -> Result<()> {
-    // Event clients get the same full snapshot as `hywoma status`, but compact and newline
-    // delimited. Full snapshots keep AGS simple and avoid ordering dependencies between fine
-    // grained events.
-    let status = status_snapshot(
-        active_workspace_id,
-        focused_slot,
-        present_workspace_ids,
-        state,
-    );
-    let mut response = serde_json::to_string(&status)?;
-    response.push('\n');
-    stream.write_all(response.as_bytes())?;
-    stream.flush()?;
-    Ok(())
+// Workspaces a command is about to take off their monitors, as (workspace ID, output name).
+// Switches made directly in Hyprland are only seen after the fact and cannot be captured.
+fn leaving_workspaces(state: &State, focused_slot: SlotId, msg: &Message) -> Vec<(u64, String)> {
+    let slots: Vec<SlotId> = match msg {
+        Message::SelectWorkspace(_, None)
+        | Message::SelectWorkspaceDelta(_)
+        | Message::SelectWorkspaceRelative(_)
+        | Message::ToggleWorkspace
+        | Message::SwapWithWorkspace(_) => vec![focused_slot],
+        Message::SelectWorkspace(_, Some(SlotTarget::Slot(slot))) => vec![*slot],
+        Message::SelectWorkspace(_, Some(SlotTarget::Output(output))) => {
+            state.slot_for_output_name(output).into_iter().collect()
+        }
+        Message::SwitchGroup(_)
+        | Message::SwitchGroupRelative(_)
+        | Message::SwitchGroupOccupiedOnly(_)
+        | Message::CreateGroup(..)
+        | Message::EnterSubgroup(_)
+        | Message::LeaveSubgroup => state.slots.keys().copied().collect(),
+        _ => return Vec::new(),
+    };
+    slots
+        .into_iter()
+        .filter_map(|slot| {
+            let output = state.slots.get(&slot)?.attached_output.clone()?;
+            let workspace_id =
+                state.known_workspace_id(state.active_group, slot, state.active_visible(slot))?;
+            Some((workspace_id, output))
+        })
+        .collect()
 }
 
-fn broadcast_event_snapshot(
-    subscribers: &mut Vec<UnixStream>,
-    active_workspace_id: u64,
-    focused_slot: SlotId,
-    present_workspace_ids: &HashSet<u64>,
-    state: &State,
-) {
-    // Broadcast is best-effort. AGS or any diagnostic client must never block workspace switching,
-    // so a failed write simply removes that subscriber.
-    subscribers.retain_mut(|stream| {
-        match write_event_snapshot(
-            stream,
-            active_workspace_id,
-            focused_slot,
-            present_workspace_ids,
-            state,
-        ) {
-            Ok(()) => true,
-            Err(err) => {
-                eprintln!("Dropping hywoma event subscriber after write failure: {err:?}");
-                false
-            }
-        }
-    });
+// What `hywoma watch` reports: one line per change of the focused workspace, monitor or group,
+// for scripts that do not want the full snapshot on every event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FocusEvent {
+    pub group: GroupId,
+    pub group_name: String,
+    pub slot: SlotId,
+    pub monitor_id: Option<u64>,
+    pub workspace: VisibleWorkspace,
+    pub workspace_id: Option<u64>,
+}
+
+fn focus_event(state: &State, view: WorkspaceKey) -> FocusEvent {
+    FocusEvent {
+        group: view.group,
+        group_name: state
+            .groups
+            .get(&view.group)
+            .map(|group| group.name.clone())
+            .unwrap_or_default(),
+        slot: view.slot,
+        monitor_id: state.runtime_monitor_id_for_slot(view.slot),
+        workspace: view.visible,
+        workspace_id: state.known_workspace_id(view.group, view.slot, view.visible),
+    }
 }
 
 fn sync_old_workspace(
@@ -307,6 +1264,14 @@ fn sync_active_workspace_id(
     workspace_id: u64,
     monitor_name: Option<&str>,
 ) {
+    // The focus moved to that monitor, but its slot keeps the workspace it had.
+    if state.is_unmanaged(workspace_id) {
+        if let Some(slot) = monitor_name.and_then(|name| state.slot_for_output_name(name)) {
+            *focused_slot = slot;
+        }
+        tracing::debug!("Workspace update: unmanaged workspace {workspace_id}");
+        return;
+    }
     // Opaque IDs identify the slot and visible label for Hyprland events, but Hyprland events are
     // not allowed to change the global active group. Hotplug can report a stale workspace from an
     // old group on the reattached monitor; group changes must come from hywoma commands only.
@@ -316,18 +1281,23 @@ fn sync_active_workspace_id(
         if key.group == state.active_group {
             state.set_active_visible(key.slot, key.visible);
         }
-        println!("Workspace update: opaque {key:?}, id {workspace_id}");
+        tracing::debug!("Workspace update: opaque {key:?}, id {workspace_id}");
     } else {
         // Temporary compatibility path for old encoded workspace IDs. This is kept while the config
         // still has fallback binds and while external/manual workspace changes can produce old IDs.
-        let workspace = sync_old_workspace(state, Workspace::from_id(workspace_id), monitor_name);
+        let workspace = sync_old_workspace(
+            state,
+            Workspace::from_id(workspace_id, state.id_layout()),
+            monitor_name,
+        );
         *active_workspace = Some(workspace);
         *focused_slot = workspace.monitor;
-        println!("Workspace update: {workspace:?}");
+        tracing::debug!("Workspace update: {workspace:?}");
     }
 }
 
 fn select_workspace(
+    config: &Config,
     state: &mut State,
     focused_slot: SlotId,
     visible: VisibleWorkspace,
@@ -336,30 +1306,134 @@ fn select_workspace(
     // event arrives. Without this optimistic update AGS can briefly render a new workspace with the
     // previous active highlight.
     let workspace_id = state.select_workspace(focused_slot, visible);
-    hyprctl(&format!("dispatch workspace {workspace_id}"))?;
+    hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
     Ok(workspace_id)
 }
 
-fn select_workspace_delta(
+// Switches another slot's workspace and hands focus back, for scripts that set up every display
+// from one place.
+fn select_workspace_on_slot(
+    config: &Config,
     state: &mut State,
-    present_workspace_ids: &HashSet<u64>,
+    slot: SlotId,
     focused_slot: SlotId,
-    delta: i64,
+    visible: VisibleWorkspace,
 ) -> Result<Option<u64>> {
-    if delta == 0 {
+    let Some(monitor_id) = state.runtime_monitor_id_for_slot(slot) else {
+        reject(format!("Cannot select a workspace on detached slot {slot}"));
+        return Ok(None);
+    };
+    let workspace_id = state.select_workspace(slot, visible);
+    hyprctl(config, &format!("dispatch focusmonitor {monitor_id}"))?;
+    hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
+    if let Some(focused_monitor_id) = state.runtime_monitor_id_for_slot(focused_slot) {
+        hyprctl(
+            config,
+            &format!("dispatch focusmonitor {focused_monitor_id}"),
+        )?;
+    }
+    Ok(Some(workspace_id))
+}
+
+// Trades the windows of the focused slot's current workspace with those of another visible
+// workspace of the same slot. Like swap_slot, the labels stay put and the IDs underneath them are
+// swapped, so the monitor only has to show the other ID.
+fn swap_with_workspace(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    visible: VisibleWorkspace,
+) -> Result<u64> {
+    let current = state.active_visible(focused_slot);
+    let target_id = state.workspace_id_for(state.active_group, focused_slot, visible);
+    hyprctl(config, &format!("dispatch workspace {target_id}"))?;
+    let (current_id, _) = state.swap_visible_workspace_ids(focused_slot, current, visible);
+    tracing::info!(
+        "Swapped state mapping: slot {focused_slot} visible {current} workspace {current_id} <-> visible {visible} workspace {target_id}"
+    );
+    let key = |visible| WorkspaceKey {
+        group: state.active_group,
+        slot: focused_slot,
+        visible,
+    };
+    relabel_swapped(config, state, key(current), key(visible));
+    Ok(target_id)
+}
+
+// Hyprland shows workspace names in bars, so labels are mirrored with `renameworkspace`. Without a
+// label a workspace gets its ID back as its name.
+fn mirror_workspace_label(config: &Config, state: &State, key: WorkspaceKey) {
+    let Some(workspace_id) = state.known_workspace_id(key.group, key.slot, key.visible) else {
+        return;
+    };
+    let name = state
+        .workspace_label(key)
+        .map_or_else(|| workspace_id.to_string(), str::to_string);
+    if let Err(err) = hyprctl(
+        config,
+        &format!("dispatch renameworkspace {workspace_id} {name}"),
+    ) {
+        reject(format!(
+            "Failed to rename workspace {workspace_id}: {err:?}"
+        ));
+    }
+}
+
+// A Hyprland name sticks to the workspace ID, while a label stays with its triple. After two IDs
+// trade places both names are out of date.
+fn relabel_swapped(config: &Config, state: &State, a: WorkspaceKey, b: WorkspaceKey) {
+    if state.workspace_label(a).is_some() || state.workspace_label(b).is_some() {
+        mirror_workspace_label(config, state, a);
+        mirror_workspace_label(config, state, b);
+    }
+}
+
+// Unlike select_workspace_delta this does not skip empty workspaces. Without wrapping, stepping
+// past either end stays where it is.
+fn relative_workspace(
+    state: &State,
+    slot: SlotId,
+    delta: i64,
+    wrap: bool,
+) -> Option<VisibleWorkspace> {
+    let count = state.workspace_count(slot) as i64;
+    let target = state.active_visible(slot) as i64 + delta;
+    if wrap {
+        return Some((target - 1).rem_euclid(count) as VisibleWorkspace + 1);
+    }
+    if !(1..=count).contains(&target) {
+        reject(format!(
+            "Cannot select workspace {target}: visible workspaces are 1..={count}"
+        ));
+        return None;
+    }
+    Some(target as VisibleWorkspace)
+}
+
+fn select_workspace_delta(
+    config: &Config,
+    state: &mut State,
+    present_workspace_ids: &HashSet<u64>,
+    focused_slot: SlotId,
+    delta: i64,
+) -> Result<Option<u64>> {
+    if delta == 0 {
         return Ok(None);
     }
 
     let mut target = state.active_visible(focused_slot);
     loop {
         let Some(next_target) = target.checked_add_signed(delta) else {
-            eprintln!("Cannot select workspace {target} + {delta}: out of visible range");
+            reject(format!(
+                "Cannot select workspace {target} + {delta}: out of visible range"
+            ));
             return Ok(None);
         };
-        if !(1..=VISIBLE_WORKSPACES_PER_SLOT).contains(&next_target) {
-            eprintln!(
-                "Cannot select workspace {next_target}: visible workspaces are 1..={VISIBLE_WORKSPACES_PER_SLOT}"
-            );
+        let workspace_count = state.workspace_count(focused_slot);
+        if !(1..=workspace_count).contains(&next_target) {
+            reject(format!(
+                "Cannot select workspace {next_target}: visible workspaces are 1..={workspace_count}"
+            ));
             return Ok(None);
         }
         target = next_target;
@@ -368,60 +1442,321 @@ fn select_workspace_delta(
             state.known_workspace_id(state.active_group, focused_slot, target)
             && present_workspace_ids.contains(&workspace_id)
         {
-            return select_workspace(state, focused_slot, target).map(Some);
+            return select_workspace(config, state, focused_slot, target).map(Some);
         }
     }
 }
 
+// The slot's limit from orientation defaults, or the whole width of `workspace_ids`.
+fn workspace_in_range(state: &State, slot: SlotId, visible: VisibleWorkspace) -> bool {
+    let workspace_count = state.workspace_count(slot);
+    if !(1..=workspace_count).contains(&visible) {
+        reject(format!(
+            "Cannot use workspace {visible} on slot {slot}: visible workspaces are 1..={workspace_count}"
+        ));
+        return false;
+    }
+    true
+}
+
+// The dispatch moving the focused window; `movetoworkspace` takes the focus along with it.
+fn move_dispatch(follow: bool) -> &'static str {
+    if follow {
+        "movetoworkspace"
+    } else {
+        "movetoworkspacesilent"
+    }
+}
+
+// Returns the workspace the window went to. Following it makes that workspace the slot's active
+// one, as Hyprland switches to it.
 fn move_to_workspace(
+    config: &Config,
     state: &mut State,
-    focused_slot: SlotId,
+    slot: SlotId,
     visible: VisibleWorkspace,
-) -> Result<()> {
-    let workspace_id = state.workspace_id_for(state.active_group, focused_slot, visible);
-    hyprctl(&format!("dispatch movetoworkspacesilent {workspace_id}"))?;
+    follow: bool,
+) -> Result<u64> {
+    let workspace_id = if follow {
+        state.select_workspace(slot, visible)
+    } else {
+        state.workspace_id_for(state.active_group, slot, visible)
+    };
+    hyprctl(
+        config,
+        &format!("dispatch {} {workspace_id}", move_dispatch(follow)),
+    )?;
+    Ok(workspace_id)
+}
+
+// The first regular workspace of the slot without windows, or a new overflow workspace past the
+// visible range when all of them are taken, so this never fails for lack of room.
+fn empty_workspace(
+    state: &State,
+    slot: SlotId,
+    window_counts: &HashMap<u64, u64>,
+) -> VisibleWorkspace {
+    let is_empty = |visible| {
+        state
+            .known_workspace_id(state.active_group, slot, visible)
+            .is_none_or(|id| window_counts.get(&id).copied().unwrap_or(0) == 0)
+    };
+    (1..)
+        .find(|visible| *visible != state.active_visible(slot) && is_empty(*visible))
+        .expect("visible workspace numbers ran out")
+}
+
+fn move_to_empty(config: &Config, state: &mut State, focused_slot: SlotId) -> Result<()> {
+    let window_counts = hyprland::get_workspace_window_counts()?;
+    let visible = empty_workspace(state, focused_slot, &window_counts);
+    if visible > state.workspace_count(focused_slot) {
+        tracing::info!("Slot {focused_slot} is full, using overflow workspace {visible}");
+    }
+    move_to_workspace(config, state, focused_slot, visible, false)?;
     Ok(())
 }
 
-fn move_to_slot(state: &mut State, slot: SlotId) -> Result<()> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Compaction {
+    from: WorkspaceKey,
+    // None for an overflow workspace that is already empty and only needs forgetting.
+    to: Option<VisibleWorkspace>,
+}
+
+// Folds overflow workspaces back into free regular workspaces of the same group and slot. Ones
+// shown on a monitor stay: the user is looking at them.
+fn plan_compaction(
+    state: &State,
+    window_counts: &HashMap<u64, u64>,
+    shown: &HashSet<u64>,
+) -> Vec<Compaction> {
+    let windows = |key: WorkspaceKey| {
+        state
+            .known_workspace_id(key.group, key.slot, key.visible)
+            .and_then(|id| window_counts.get(&id).copied())
+            .unwrap_or(0)
+    };
+    let mut taken: HashSet<WorkspaceKey> = HashSet::new();
+    let mut compactions = Vec::new();
+    for from in state.overflow_keys() {
+        if state
+            .known_workspace_id(from.group, from.slot, from.visible)
+            .is_some_and(|id| shown.contains(&id))
+        {
+            continue;
+        }
+        if windows(from) == 0 {
+            compactions.push(Compaction { from, to: None });
+            continue;
+        }
+        let free = (1..=state.workspace_count(from.slot)).find(|visible| {
+            let key = WorkspaceKey {
+                visible: *visible,
+                ..from
+            };
+            windows(key) == 0 && !taken.contains(&key)
+        });
+        if let Some(visible) = free {
+            taken.insert(WorkspaceKey { visible, ..from });
+            compactions.push(Compaction {
+                from,
+                to: Some(visible),
+            });
+        }
+    }
+    compactions
+}
+
+fn compact(config: &Config, state: &mut State) -> Result<usize> {
+    let window_counts = hyprland::get_workspace_window_counts()?;
+    let shown: HashSet<u64> = hyprland::get_monitor_workspace_ids()?
+        .into_iter()
+        .map(|(_, workspace_id)| workspace_id)
+        .collect();
+    let compactions = plan_compaction(state, &window_counts, &shown);
+    let clients = hyprland::get_clients()?;
+    for compaction in &compactions {
+        let from = compaction.from;
+        if let Some(visible) = compaction.to {
+            let from_id = state.workspace_id_for(from.group, from.slot, from.visible);
+            let to_id = state.workspace_id_for(from.group, from.slot, visible);
+            let dispatches: Vec<String> = clients
+                .iter()
+                .filter(|client| client.workspace_id == from_id)
+                .map(|client| {
+                    format!(
+                        "dispatch movetoworkspacesilent {to_id},address:0x{}",
+                        client.address
+                    )
+                })
+                .collect();
+            hyprland::hyprctl_batch(config, &dispatches)?;
+        }
+        state.forget_workspace(from);
+    }
+    Ok(compactions.len())
+}
+
+// Returns the workspace the window went to.
+fn move_to_slot(
+    config: &Config,
+    state: &mut State,
+    slot: SlotId,
+    follow: bool,
+) -> Result<Option<u64>> {
     // Detached slots are intentionally not merged into any attached slot. If a monitor disappears,
     // the logical slot remains addressable but commands that need a real monitor become no-ops.
     if state.runtime_monitor_id_for_slot(slot).is_none() {
-        eprintln!("Cannot move window to detached slot {slot}");
-        return Ok(());
+        reject(format!("Cannot move window to detached slot {slot}"));
+        return Ok(None);
     }
 
     let visible = state.active_visible(slot);
     let workspace_id = state.workspace_id_for(state.active_group, slot, visible);
-    hyprctl(&format!("dispatch movetoworkspacesilent {workspace_id}"))?;
-    Ok(())
+    hyprctl(
+        config,
+        &format!("dispatch {} {workspace_id}", move_dispatch(follow)),
+    )?;
+    Ok(Some(workspace_id))
+}
+
+// Moves the windows of the focused workspace to another slot and shows them there, on the same
+// workspace number or the first empty one when that is taken. Locked windows stay. Returns the
+// workspace they went to.
+fn move_workspace_to_slot(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    slot: SlotId,
+    locked_windows: &HashMap<String, u64>,
+) -> Result<Option<u64>> {
+    if slot == focused_slot {
+        reject(format!("The focused workspace is already on slot {slot}"));
+        return Ok(None);
+    }
+    if state.runtime_monitor_id_for_slot(slot).is_none() {
+        reject(format!("Cannot move workspace to detached slot {slot}"));
+        return Ok(None);
+    }
+    let group = state.active_group;
+    let visible = state.active_visible(focused_slot);
+    let windows: Vec<String> = match state.known_workspace_id(group, focused_slot, visible) {
+        Some(workspace_id) => hyprland::get_clients()?
+            .into_iter()
+            .filter(|client| {
+                client.workspace_id == workspace_id && !locked_windows.contains_key(&client.address)
+            })
+            .map(|client| client.address)
+            .collect(),
+        None => Vec::new(),
+    };
+    if windows.is_empty() {
+        reject(format!(
+            "Workspace {visible} on slot {focused_slot} has no windows to move"
+        ));
+        return Ok(None);
+    }
+    let window_counts = hyprland::get_workspace_window_counts()?;
+    let Some(target_visible) = free_workspace(state, slot, visible, &window_counts) else {
+        reject(format!(
+            "Slot {slot} has no empty workspace to take workspace {visible}"
+        ));
+        return Ok(None);
+    };
+    // Shown first: moving windows to a workspace that does not exist yet would create it on the
+    // focused monitor instead.
+    let Some(target_workspace_id) =
+        select_workspace_on_slot(config, state, slot, focused_slot, target_visible)?
+    else {
+        return Ok(None);
+    };
+    let dispatches: Vec<String> = windows
+        .iter()
+        .map(|address| {
+            format!("dispatch movetoworkspacesilent {target_workspace_id},address:0x{address}")
+        })
+        .collect();
+    hyprland::hyprctl_batch(config, &dispatches)?;
+    // A label names what is on the workspace, so it goes along.
+    let source = WorkspaceKey {
+        group,
+        slot: focused_slot,
+        visible,
+    };
+    if let Some(label) = state.workspace_label(source).map(str::to_string) {
+        if let Some(group_state) = state.groups.get_mut(&group) {
+            group_state.set_workspace_label(focused_slot, visible, None);
+            group_state.set_workspace_label(slot, target_visible, Some(label));
+        }
+        mirror_workspace_label(config, state, source);
+        mirror_workspace_label(
+            config,
+            state,
+            WorkspaceKey {
+                group,
+                slot,
+                visible: target_visible,
+            },
+        );
+    }
+    tracing::info!(
+        "Moved {} window(s) of workspace {visible} on slot {focused_slot} to workspace {target_visible} on slot {slot}",
+        windows.len()
+    );
+    Ok(Some(target_workspace_id))
+}
+
+// `visible` itself if it has no windows on `slot`, otherwise the first visible workspace there
+// that has none.
+fn free_workspace(
+    state: &State,
+    slot: SlotId,
+    visible: VisibleWorkspace,
+    window_counts: &HashMap<u64, u64>,
+) -> Option<VisibleWorkspace> {
+    let workspace_count = state.workspace_count(slot);
+    std::iter::once(visible)
+        .chain(1..=workspace_count)
+        .filter(|visible| (1..=workspace_count).contains(visible))
+        .find(|visible| {
+            state
+                .known_workspace_id(state.active_group, slot, *visible)
+                .is_none_or(|workspace_id| {
+                    window_counts.get(&workspace_id).copied().unwrap_or(0) == 0
+                })
+        })
 }
 
-fn select_slot(state: &mut State, slot: SlotId) -> Result<Option<u64>> {
+fn select_slot(config: &Config, state: &mut State, slot: SlotId) -> Result<Option<u64>> {
     let Some(monitor_id) = state.runtime_monitor_id_for_slot(slot) else {
-        eprintln!("Cannot select detached slot {slot}");
+        reject(format!("Cannot select detached slot {slot}"));
         return Ok(None);
     };
 
     let visible = state.active_visible(slot);
     let workspace_id = state.workspace_id_for(state.active_group, slot, visible);
-    hyprctl(&format!("dispatch focusmonitor {monitor_id}"))?;
-    hyprctl(&format!("dispatch workspace {workspace_id}"))?;
+    hyprctl(config, &format!("dispatch focusmonitor {monitor_id}"))?;
+    hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
     Ok(Some(workspace_id))
 }
 
-fn swap_slot(state: &mut State, source_slot: SlotId, target_slot: SlotId) -> Result<()> {
+fn swap_slot(
+    config: &Config,
+    state: &mut State,
+    source_slot: SlotId,
+    target_slot: SlotId,
+) -> Result<()> {
     if source_slot == target_slot {
-        println!("Skipping swap of slot {source_slot} with itself");
+        tracing::info!("Skipping swap of slot {source_slot} with itself");
         return Ok(());
     }
 
     let Some(source_monitor_id) = state.runtime_monitor_id_for_slot(source_slot) else {
-        eprintln!("Cannot swap from detached slot {source_slot}");
+        reject(format!("Cannot swap from detached slot {source_slot}"));
         return Ok(());
     };
     let Some(target_monitor_id) = state.runtime_monitor_id_for_slot(target_slot) else {
-        eprintln!("Cannot swap with detached slot {target_slot}");
+        reject(format!("Cannot swap with detached slot {target_slot}"));
         return Ok(());
     };
 
@@ -432,34 +1767,47 @@ fn swap_slot(state: &mut State, source_slot: SlotId, target_slot: SlotId) -> Res
     let Some(source_workspace_id) =
         state.known_workspace_id(state.active_group, source_slot, source_visible)
     else {
-        eprintln!(
+        reject(format!(
             "Cannot swap slot {source_slot} visible {source_visible}: opaque workspace is not displayed yet"
-        );
+        ));
         return Ok(());
     };
     let Some(target_workspace_id) =
         state.known_workspace_id(state.active_group, target_slot, target_visible)
     else {
-        eprintln!(
+        reject(format!(
             "Cannot swap slot {target_slot} visible {target_visible}: opaque workspace is not displayed yet"
-        );
+        ));
         return Ok(());
     };
 
-    hyprctl(&format!(
-        "dispatch swapactiveworkspaces {source_monitor_id} {target_monitor_id}"
-    ))?;
+    hyprctl(
+        config,
+        &format!("dispatch swapactiveworkspaces {source_monitor_id} {target_monitor_id}"),
+    )?;
     // Hyprland swaps monitor contents. To keep visible labels pinned to logical slots, hywoma swaps
     // the internal IDs underneath those labels only after Hyprland accepted the dispatch.
     let swapped_ids = state.swap_active_workspace_ids(source_slot, target_slot);
     debug_assert_eq!(swapped_ids, (source_workspace_id, target_workspace_id));
-    println!(
+    tracing::info!(
         "Swapped state mapping: slot {source_slot} visible {source_visible} workspace {source_workspace_id} monitor {source_monitor_id} <-> slot {target_slot} visible {target_visible} workspace {target_workspace_id} monitor {target_monitor_id}"
     );
+    let key = |slot, visible| WorkspaceKey {
+        group: state.active_group,
+        slot,
+        visible,
+    };
+    relabel_swapped(
+        config,
+        state,
+        key(source_slot, source_visible),
+        key(target_slot, target_visible),
+    );
     Ok(())
 }
 
 fn tmp_swap_with_slot(
+    config: &Config,
     state: &mut State,
     present_workspace_ids: &mut HashSet<u64>,
     focused_slot: SlotId,
@@ -498,15 +1846,19 @@ fn tmp_swap_with_slot(
         .min_by_key(|(visible, _)| *visible);
 
     state.swap_slot_workspace_mappings(focused_slot, target_slot);
-    hyprctl(&format!("dispatch focusmonitor {source_monitor_id}"))?;
+    hyprctl(
+        config,
+        &format!("dispatch focusmonitor {source_monitor_id}"),
+    )?;
 
     if let Some((visible, workspace_id)) = target_present_in_active_group {
         state.set_active_visible(focused_slot, visible);
-        hyprctl(&format!("dispatch workspace {workspace_id}"))?;
+        hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
         *active_workspace_id = workspace_id;
         present_workspace_ids.insert(workspace_id);
     } else {
-        *active_workspace_id = select_workspace(state, focused_slot, DEFAULT_VISIBLE_WORKSPACE)?;
+        *active_workspace_id =
+            select_workspace(config, state, focused_slot, DEFAULT_VISIBLE_WORKSPACE)?;
         present_workspace_ids.insert(*active_workspace_id);
     }
 
@@ -517,12 +1869,243 @@ fn tmp_swap_with_slot(
     ))
 }
 
-fn switch_group(state: &mut State, focused_slot: SlotId, group: GroupId) -> Result<Option<u64>> {
+// What `mirror_group` changed, so `mirror_group --stop` can put it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mirror {
+    // The mirrored workspace, where the windows go back to.
+    source_workspace_id: u64,
+    // An overflow workspace of the group active at the time, shown on the presentation monitor.
+    target: WorkspaceKey,
+    target_workspace_id: u64,
+    // What the presentation monitor showed before.
+    previous_visible: VisibleWorkspace,
+    windows: Vec<String>,
+}
+
+// Without a target, the outermost monitor other than the focused one, where a projector usually
+// ends up.
+fn presentation_slot(
+    state: &State,
+    focused_slot: SlotId,
+    monitors: &[MonitorInfo],
+    target: Option<&SlotTarget>,
+) -> Option<SlotId> {
+    if let Some(target) = target {
+        return resolve_slot_target(state, focused_slot, monitors, target);
+    }
+    let (first, last) = outermost_slots(state, monitors)?;
+    Some(if last == focused_slot { first } else { last })
+}
+
+// Past the slot's visible range, so the mirror never lands among the group's own workspaces.
+fn spare_workspace(state: &State, slot: SlotId) -> VisibleWorkspace {
+    (state.workspace_count(slot) + 1..)
+        .find(|visible| {
+            state
+                .known_workspace_id(state.active_group, slot, *visible)
+                .is_none()
+        })
+        .expect("visible workspace numbers ran out")
+}
+
+// Moves the windows of the group's selected workspace on the focused slot to a spare workspace
+// shown on the presentation slot. Locked windows stay where they are kept.
+fn start_mirror(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    slot: SlotId,
+    group: GroupId,
+    locked_windows: &HashMap<String, u64>,
+) -> Result<Option<Mirror>> {
+    if !state.has_group(group) {
+        reject(format!("Cannot mirror unknown workspace group {group}"));
+        return Ok(None);
+    }
+    if slot == focused_slot {
+        reject(format!("Cannot mirror onto the focused slot {slot}"));
+        return Ok(None);
+    }
+    let visible = state.active_visible_in_group(group, focused_slot);
+    let windows: Vec<String> = match state.known_workspace_id(group, focused_slot, visible) {
+        Some(workspace_id) => hyprland::get_clients()?
+            .into_iter()
+            .filter(|client| {
+                client.workspace_id == workspace_id && !locked_windows.contains_key(&client.address)
+            })
+            .map(|client| client.address)
+            .collect(),
+        None => Vec::new(),
+    };
+    let Some(source_workspace_id) = state
+        .known_workspace_id(group, focused_slot, visible)
+        .filter(|_| !windows.is_empty())
+    else {
+        reject(format!(
+            "Workspace {visible} of group {group} has no windows to mirror"
+        ));
+        return Ok(None);
+    };
+
+    let previous_visible = state.active_visible(slot);
+    let target = WorkspaceKey {
+        group: state.active_group,
+        slot,
+        visible: spare_workspace(state, slot),
+    };
+    // Shown first: moving windows to a workspace that does not exist yet would create it on the
+    // focused monitor instead.
+    let Some(target_workspace_id) =
+        select_workspace_on_slot(config, state, slot, focused_slot, target.visible)?
+    else {
+        return Ok(None);
+    };
+    let dispatches: Vec<String> = windows
+        .iter()
+        .map(|address| {
+            format!("dispatch movetoworkspacesilent {target_workspace_id},address:0x{address}")
+        })
+        .collect();
+    hyprland::hyprctl_batch(config, &dispatches)?;
+    tracing::info!(
+        "Mirroring {} window(s) of group {group} workspace {visible} on slot {slot}",
+        windows.len()
+    );
+    Ok(Some(Mirror {
+        source_workspace_id,
+        target,
+        target_workspace_id,
+        previous_visible,
+        windows,
+    }))
+}
+
+// Windows closed in the meantime are skipped. If the group changed since, the presentation
+// monitor already shows the new one and only the old group's model is put back.
+fn stop_mirror(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    mirror: Mirror,
+) -> Result<Option<u64>> {
+    let present: HashSet<String> = hyprland::get_clients()?
+        .into_iter()
+        .map(|client| client.address)
+        .collect();
+    let dispatches: Vec<String> = mirror
+        .windows
+        .iter()
+        .filter(|address| present.contains(*address))
+        .map(|address| {
+            format!(
+                "dispatch movetoworkspacesilent {},address:0x{address}",
+                mirror.source_workspace_id
+            )
+        })
+        .collect();
+    hyprland::hyprctl_batch(config, &dispatches)?;
+    tracing::info!("Moved {} mirrored window(s) back", dispatches.len());
+
+    let Mirror {
+        target,
+        previous_visible,
+        ..
+    } = mirror;
+    let shown = state.active_visible_in_group(target.group, target.slot) == target.visible;
+    let mut workspace_id = None;
+    if shown && target.group == state.active_group {
+        workspace_id =
+            select_workspace_on_slot(config, state, target.slot, focused_slot, previous_visible)?;
+    } else if shown && let Some(group) = state.groups.get_mut(&target.group) {
+        group.set_active_visible(target.slot, previous_visible);
+    }
+    state.forget_workspace(target);
+    Ok(workspace_id)
+}
+
+// Always wraps around. From a sub-group, the cycle continues from its parent. With `max_groups`, a
+// group it steps onto that does not exist yet is created.
+fn relative_group(state: &mut State, delta: i64, max_groups: Option<GroupId>) -> GroupId {
+    let current = state.settings_group(state.active_group);
+    if let Some(count) = max_groups.filter(|count| *count > 0) {
+        let group = (current as i64 + delta).rem_euclid(count as i64) as GroupId;
+        state.ensure_group(group, format!("Group {group}"));
+        return group;
+    }
+    let mut groups: Vec<GroupId> = state
+        .groups
+        .values()
+        .filter(|group| group.parent.is_none())
+        .map(|group| group.id)
+        .collect();
+    groups.sort_unstable();
+    let Some(position) = groups.iter().position(|group| *group == current) else {
+        return current;
+    };
+    let target = (position as i64 + delta).rem_euclid(groups.len() as i64);
+    groups[target as usize]
+}
+
+fn switch_group(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    group: GroupId,
+) -> Result<Option<u64>> {
+    switch_group_where(config, state, focused_slot, group, |_, _| true)
+}
+
+// `flip` decides per side monitor (slot, target workspace ID) whether it follows the group
+// switch. The focused slot always does, otherwise the group switch would be invisible.
+fn switch_group_where(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    group: GroupId,
+    flip: impl FnMut(SlotId, u64) -> bool,
+) -> Result<Option<u64>> {
     if !state.has_group(group) {
-        eprintln!("Cannot switch to unknown workspace group {group}");
+        reject(format!("Cannot switch to unknown workspace group {group}"));
         return Ok(None);
     }
 
+    let mut focused_workspace_id = None;
+    let mut dispatches = Vec::new();
+    for step in plan_group_switch(state, focused_slot, group, flip) {
+        if step.slot == focused_slot {
+            focused_workspace_id = Some(step.workspace_id);
+        }
+        dispatches.extend(step.dispatches());
+    }
+    // One request for all monitors, so they flip together instead of one after the other.
+    hyprland::hyprctl_batch(config, &dispatches)?;
+
+    Ok(focused_workspace_id)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GroupSwitchStep {
+    slot: SlotId,
+    monitor_id: u64,
+    workspace_id: u64,
+}
+
+impl GroupSwitchStep {
+    fn dispatches(&self) -> [String; 2] {
+        [
+            format!("dispatch focusmonitor {}", self.monitor_id),
+            format!("dispatch workspace {}", self.workspace_id),
+        ]
+    }
+}
+
+// Switches the model to `group` and returns the monitors to move there, in dispatch order.
+fn plan_group_switch(
+    state: &mut State,
+    focused_slot: SlotId,
+    group: GroupId,
+    mut flip: impl FnMut(SlotId, u64) -> bool,
+) -> Vec<GroupSwitchStep> {
     state.switch_group(group);
 
     let mut slots: Vec<(SlotId, Option<u64>)> = state
@@ -535,23 +2118,89 @@ fn switch_group(state: &mut State, focused_slot: SlotId, group: GroupId) -> Resu
     // the user's current logical slot after all attached monitors have been moved into the group.
     slots.sort_unstable_by_key(|(slot, _)| (*slot == focused_slot, *slot));
 
-    let mut focused_workspace_id = None;
+    let mut steps = Vec::new();
     for (slot, monitor_id) in slots {
         if let Some(monitor_id) = monitor_id {
             let visible = state.active_visible(slot);
             let workspace_id = state.workspace_id_for(group, slot, visible);
-            if slot == focused_slot {
-                focused_workspace_id = Some(workspace_id);
+            if slot != focused_slot && !flip(slot, workspace_id) {
+                continue;
             }
-            hyprctl(&format!("dispatch focusmonitor {monitor_id}"))?;
-            hyprctl(&format!("dispatch workspace {workspace_id}"))?;
+            steps.push(GroupSwitchStep {
+                slot,
+                monitor_id,
+                workspace_id,
+            });
         }
     }
+    steps
+}
 
-    Ok(focused_workspace_id)
+// `select_group <n> --plan`: the dispatches a switch would run and what every monitor would show
+// afterwards. Planned on a copy, so nothing is allocated or switched for real.
+fn describe_group_switch_plan(state: &State, focused_slot: SlotId, group: GroupId) -> String {
+    if !state.has_group(group) {
+        return format!("Unknown workspace group {group}");
+    }
+    let mut planned = state.clone();
+    let steps = plan_group_switch(&mut planned, focused_slot, group, |_, _| true);
+    let mut lines: Vec<String> = steps.iter().flat_map(GroupSwitchStep::dispatches).collect();
+    lines.push(String::new());
+    let mut slots: Vec<(&SlotId, &Slot)> = planned.slots.iter().collect();
+    slots.sort_unstable_by_key(|(slot_id, _)| **slot_id);
+    for (slot_id, slot) in slots {
+        let Some(output) = &slot.attached_output else {
+            continue;
+        };
+        let visible = planned.active_visible(*slot_id);
+        match planned.known_workspace_id(group, *slot_id, visible) {
+            Some(workspace_id) => lines.push(format!(
+                "{output} (slot {slot_id}): workspace {workspace_id} (group {group}, workspace {visible})"
+            )),
+            None => lines.push(format!("{output} (slot {slot_id}): unchanged")),
+        }
+    }
+    lines.join("\n")
+}
+
+fn apply_orientation_defaults(config: &Config, state: &mut State) -> Result<()> {
+    if config.orientations.is_empty() && config.workspaces_per_slot.is_none() {
+        return Ok(());
+    }
+    let orientations = if config.orientations.is_empty() {
+        HashMap::new()
+    } else {
+        hyprland::get_monitor_orientations()?
+    };
+    let slots: Vec<SlotId> = state.slots.keys().copied().collect();
+    for slot in slots {
+        let defaults = state
+            .runtime_monitor_id_for_slot(slot)
+            .and_then(|monitor_id| orientations.get(&monitor_id))
+            .and_then(|orientation| config.orientations.defaults_for(*orientation));
+        state.set_slot_defaults(
+            slot,
+            defaults.and_then(|defaults| defaults.label.as_deref()),
+            defaults
+                .and_then(|defaults| defaults.workspaces)
+                .or(config.workspaces_per_slot),
+        );
+    }
+    Ok(())
+}
+
+fn warp_cursor_to_slot(config: &Config, state: &State, slot: SlotId) -> Result<()> {
+    let Some(monitor_id) = state.runtime_monitor_id_for_slot(slot) else {
+        return Ok(());
+    };
+    if let Some((x, y)) = hyprland::get_monitor_center(monitor_id)? {
+        hyprctl(config, &format!("dispatch movecursor {x} {y}"))?;
+    }
+    Ok(())
 }
 
 fn sync_attached_slots_to_active_group(
+    config: &Config,
     state: &mut State,
     focused_slot: SlotId,
 ) -> Result<Option<u64>> {
@@ -573,8 +2222,8 @@ fn sync_attached_slots_to_active_group(
             if slot == focused_slot {
                 focused_workspace_id = Some(workspace_id);
             }
-            hyprctl(&format!("dispatch focusmonitor {monitor_id}"))?;
-            hyprctl(&format!("dispatch workspace {workspace_id}"))?;
+            hyprctl(config, &format!("dispatch focusmonitor {monitor_id}"))?;
+            hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
         }
     }
 
@@ -597,15 +2246,15 @@ fn group_has_present_workspaces(
 
 fn delete_group(state: &mut State, present_workspace_ids: &HashSet<u64>, group: GroupId) -> bool {
     if group == state.active_group {
-        eprintln!("Cannot delete the active workspace group {group}");
+        reject(format!("Cannot delete the active workspace group {group}"));
         return false;
     }
     if !state.has_group(group) {
-        eprintln!("Cannot delete unknown workspace group {group}");
+        reject(format!("Cannot delete unknown workspace group {group}"));
         return false;
     }
     if group_has_present_workspaces(state, present_workspace_ids, group) {
-        eprintln!("Cannot delete non-empty workspace group {group}");
+        reject(format!("Cannot delete non-empty workspace group {group}"));
         return false;
     }
 
@@ -613,9 +2262,16 @@ fn delete_group(state: &mut State, present_workspace_ids: &HashSet<u64>, group:
     true
 }
 
-fn move_to_group(state: &mut State, focused_slot: SlotId, group: GroupId) -> Result<()> {
+fn move_to_group(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    group: GroupId,
+) -> Result<()> {
     if !state.has_group(group) {
-        eprintln!("Cannot move window to unknown workspace group {group}");
+        reject(format!(
+            "Cannot move window to unknown workspace group {group}"
+        ));
         return Ok(());
     }
 
@@ -623,79 +2279,178 @@ fn move_to_group(state: &mut State, focused_slot: SlotId, group: GroupId) -> Res
     // Move to the destination group's active visible workspace on the same logical slot. This keeps
     // the old behavior where a window moves to the corresponding monitor/slot in another group.
     let workspace_id = state.workspace_id_for(group, focused_slot, visible);
-    hyprctl(&format!("dispatch movetoworkspacesilent {workspace_id}"))?;
+    hyprctl(
+        config,
+        &format!("dispatch movetoworkspacesilent {workspace_id}"),
+    )?;
     Ok(())
 }
 
-fn process_command(command: Vec<String>, tx: &mpsc::Sender<Message>) -> Result<()> {
-    if command.first().map(|cmd| cmd.as_str()) == Some("create_group") && command.len() > 1 {
-        tx.send(Message::CreateGroup(command[1..].join(" ")))?;
-        return Ok(());
-    }
-    if command.first().map(|cmd| cmd.as_str()) == Some("rename_group") && command.len() > 2 {
-        tx.send(Message::RenameGroup(
-            command[1].parse()?,
-            command[2..].join(" "),
-        ))?;
+fn apply_rule(
+    config: &Config,
+    state: &mut State,
+    focused_slot: SlotId,
+    rule: &Rule,
+    address: &str,
+) -> Result<()> {
+    if !state.has_group(rule.group) {
+        tracing::warn!(
+            "Ignoring rule for class {:?}: unknown workspace group {}",
+            rule.class,
+            rule.group
+        );
         return Ok(());
     }
 
-    let command: Vec<&str> = command.iter().map(|s| s.as_str()).collect();
-    let msg: Message = match command.as_slice() {
-        ["select_workspace", workspace] => Message::SelectWorkspace(workspace.parse()?),
-        ["select_workspace_delta", delta] => Message::SelectWorkspaceDelta(delta.parse()?),
-        ["move_to_workspace", workspace] => Message::MoveToWorkspace(workspace.parse()?),
-        ["switch_group", group] => Message::SwitchGroup(group.parse()?),
-        ["delete_group", group] => Message::DeleteGroup(group.parse()?),
-        ["move_to_group", group] => Message::MoveToGroup(group.parse()?),
-        ["select_slot", slot] => Message::SelectSlot(slot.parse()?),
-        ["move_to_slot", slot] => Message::MoveToSlot(slot.parse()?),
-        ["swap_slot", slot] => Message::SwapSlot(slot.parse()?),
-        _ => return Ok(()),
+    // While a sub-group is active, its parent's rules place windows into the sub-group.
+    let group = if state.parent_of(state.active_group) == Some(rule.group) {
+        state.active_group
+    } else {
+        rule.group
     };
-    tx.send(msg)?;
+    let slot = rule.slot.unwrap_or(focused_slot);
+    // Slots and their workspace counts change with the monitors after the rule was checked.
+    if let Err(err) = rules::validate_placement(state, slot, rule.workspace) {
+        tracing::warn!("Ignoring rule for class {:?}: {err}", rule.class);
+        return Ok(());
+    }
+    let workspace_id = state.workspace_id_for(group, slot, rule.workspace);
+    hyprctl(
+        config,
+        &format!("dispatch movetoworkspacesilent {workspace_id},address:0x{address}"),
+    )?;
+    tracing::info!(
+        "Placed window {address} of class {:?} on group {group} slot {slot} visible {}",
+        rule.class,
+        rule.workspace
+    );
     Ok(())
 }
 
-fn is_status_command(command: &[String]) -> bool {
-    matches!(command, [cmd] if cmd == "status")
+// Starts the daemon again from the same argv[0], which picks up an upgraded binary from PATH. The
+// successor loads the runtime state persisted just before and keeps accepting on the inherited
+// command socket, so clients never see the socket missing.
+fn spawn_successor(command_fd: RawFd) -> Result<u32> {
+    if simulate::is_active() {
+        return Err(anyhow!("A simulated server has no state to hand over"));
+    }
+    // systemd stops the whole unit once its main process exits, successor included.
+    if env::var_os("INVOCATION_ID").is_some() {
+        return Err(anyhow!(
+            "Running as a systemd service, use `systemctl --user restart hywoma.service`"
+        ));
+    }
+    let mut args: Vec<String> = env::args().collect();
+    let program = args.remove(0);
+    if let Some(index) = args.iter().position(|arg| arg == "--command-fd") {
+        args.drain(index..(index + 2).min(args.len()));
+    }
+
+    let mut command = process::Command::new(program);
+    command
+        .args(args)
+        .arg("--command-fd")
+        .arg(command_fd.to_string());
+    // Rust opens every FD close-on-exec. Clear the flag in the child only, the listener stays
+    // private to this process otherwise.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::fcntl(command_fd, libc::F_SETFD, 0) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(command.spawn()?.id())
 }
 
-fn is_response_command(command: &[String]) -> bool {
-    matches!(command, [cmd] if cmd == "status" || cmd == "tmp-slots")
-        || matches!(command, [cmd, _] if cmd == "tmp-swap-with-slot")
+fn active_group_identity(state: &State) -> (GroupId, String) {
+    let group = state.active_group;
+    let name = state
+        .groups
+        .get(&group)
+        .map(|group| group.name.clone())
+        .unwrap_or_default();
+    (group, name)
 }
 
-fn write_status_response(mut stream: UnixStream, response: &str) {
-    if let Err(err) = stream.write_all(response.as_bytes()) {
-        eprintln!("Failed to write status response: {err:?}");
-        return;
-    }
-    if let Err(err) = stream.write_all(b"\n") {
-        eprintln!("Failed to terminate status response: {err:?}");
-        return;
+fn active_view(state: &State, focused_slot: SlotId) -> WorkspaceKey {
+    WorkspaceKey {
+        group: state.active_group,
+        slot: focused_slot,
+        visible: state.active_visible(focused_slot),
     }
-    if let Err(err) = stream.flush() {
-        eprintln!("Failed to flush status response: {err:?}");
+}
+
+// Like `recv`, but a deadline that passes first is reported as the end of the focus session.
+fn receive(rx: &mpsc::Receiver<Message>, deadline: Option<Instant>) -> Option<Message> {
+    let Some(deadline) = deadline else {
+        return rx.recv().ok();
+    };
+    match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(msg) => Some(msg),
+        Err(mpsc::RecvTimeoutError::Timeout) => Some(Message::FocusExpired),
+        Err(mpsc::RecvTimeoutError::Disconnected) => None,
     }
 }
 
-fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
-    let mut monitors = hyprland::get_monitors()?;
-    let initial_workspace_id = hyprland::get_active_workspace_id()?;
-    let initial_monitor_id = hyprland::get_active_workspace_monitor_id()?;
-    let initial_workspace = Workspace::from_id(initial_workspace_id);
+// Hyprland's present workspaces, and its active one.
+fn read_workspaces() -> Result<(HashSet<u64>, u64)> {
+    let workspace_ids = hyprland::get_workspace_ids()?.into_iter().collect();
+    Ok((workspace_ids, hyprland::get_active_workspace_id()?))
+}
+
+fn main_loop(rx: mpsc::Receiver<Message>, base_config: Config) -> Result<ShutdownReason> {
+    let mut config = base_config.active();
+
+    let startup = hyprland::get_startup_snapshot()?;
+    let mut monitors = startup.monitors;
+    let initial_workspace_id = startup.active_workspace_id;
+    let initial_monitor_id = startup.active_monitor_id;
+    let runtime_state = load_runtime_state(config.workspace_ids);
+    let loaded_runtime_state = runtime_state.is_some();
+    let mut state = runtime_state.unwrap_or_else(|| default_state(config.workspace_ids));
+    let initial_workspace = Workspace::from_id(initial_workspace_id, state.id_layout());
     let mut active_workspace_id = initial_workspace_id;
     let mut active_workspace = Some(initial_workspace);
     let mut focused_slot = initial_workspace.monitor;
-    let mut present_workspace_ids: HashSet<u64> =
-        hyprland::get_workspace_ids()?.into_iter().collect();
+    let mut present_workspace_ids: HashSet<u64> = startup.workspace_ids.into_iter().collect();
     present_workspace_ids.insert(active_workspace_id);
-    let runtime_state = load_runtime_state();
-    let loaded_runtime_state = runtime_state.is_some();
-    let mut state = runtime_state.unwrap_or_else(default_state);
-    let mut event_subscribers = Vec::new();
-    attach_monitors_for_host(&mut state, &monitors);
+    let snapshots = Broadcast::default();
+    let focus_changes = Broadcast::default();
+    let mut fifo_sink = FifoSink::default();
+    let mut dimmer = Dimmer::default();
+    let mut badges = Badges::default();
+    // Rules start from the config file and can be changed live with `hywoma rule ...`. Live edits
+    // are deliberately not persisted; `rule export` prints them for the config file instead.
+    let mut rules = rules::valid(&config.rules, &state);
+    // Slots left on the previous group's workspace by `switch_group --occupied-only`. They are
+    // intentionally out of sync with the active group until the next full switch.
+    let mut lingering_slots: HashSet<SlotId> = HashSet::new();
+    // Last focused window per workspace, so switching back lands on it instead of on whatever
+    // Hyprland picks. Addresses do not outlive the Hyprland session, so this is not persisted.
+    let mut last_windows: HashMap<WorkspaceKey, String> = HashMap::new();
+    let mut thumbnails = Thumbnails::default();
+    // Windows pinned with `lock_window`, with the workspace they are kept on. Addresses are only
+    // valid for this Hyprland session, so locks are not persisted either.
+    let mut locked_windows: HashMap<String, u64> = HashMap::new();
+    // Marked with `select add`, in marking order. Like locks they do not survive a restart.
+    let mut marked_windows: Vec<String> = Vec::new();
+    // Set by `mirror_group` until `mirror_group --stop` or the next group change.
+    let mut mirror: Option<Mirror> = None;
+    // The visible workspace each (group, slot) showed before its current one, for toggle_workspace.
+    let mut previous_visible: HashMap<(GroupId, SlotId), VisibleWorkspace> = HashMap::new();
+    let mut layouts = LayoutMemory::default();
+    // Created by Hyprland but not focused since, so their layout is not restored yet.
+    let mut recreated_workspace_ids: HashSet<u64> = HashSet::new();
+    let mut scheduler = Scheduler::default();
+    // Set between `hywoma idle` and `hywoma resume`.
+    let mut idle_since: Option<Instant> = None;
+    let mut focus_session: Option<FocusSession> = None;
+    attach_monitors_for_host(&config, &mut state, &monitors);
+    if let Err(err) = apply_orientation_defaults(&config, &mut state) {
+        tracing::warn!("Failed to apply orientation defaults: {err:?}");
+    }
     if let Some(key) = state.key_for_workspace_id(initial_workspace_id) {
         // Normal daemon restart path: the runtime state tells us what the active opaque ID means,
         // so recover group/slot/visible from the persisted mapping instead of unpacking the ID as an
@@ -709,7 +2464,7 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
         state.restore_active_group(key.group);
         state.set_workspace_id(key.group, focused_slot, key.visible, initial_workspace_id);
         state.set_active_visible(focused_slot, key.visible);
-    } else if initial_workspace_id >= FIRST_INTERNAL_WORKSPACE_ID {
+    } else if initial_workspace_id >= state.id_layout().first_internal_id() {
         // Clean-session bootstrap path: Hyprland can be configured to start monitors on hywoma's
         // seeded opaque IDs before the daemon has allocated any runtime mappings. Recognize those
         // IDs as the default group mapping; runtime state and swaps can still overwrite them later.
@@ -731,7 +2486,7 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
             let mut opaque_workspace_ids: Vec<u64> = present_workspace_ids
                 .iter()
                 .copied()
-                .filter(|id| *id >= FIRST_INTERNAL_WORKSPACE_ID)
+                .filter(|id| *id >= state.id_layout().first_internal_id())
                 .collect();
             opaque_workspace_ids.sort_unstable();
 
@@ -761,20 +2516,208 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
         state.set_active_visible(initial_workspace.monitor, initial_workspace.workspace);
     }
     if loaded_runtime_state {
-        println!("Loaded hywoma runtime state");
+        tracing::info!("Loaded hywoma runtime state");
     }
-    if let Some(workspace_id) = sync_attached_slots_to_active_group(&mut state, focused_slot)? {
+    refresh_unmanaged_workspaces(&config, &mut state);
+    apply_group_names(&config, &mut state);
+    // Before anything is dispatched, so "strict" refuses to start with the session as it was.
+    let startup_shown = hyprland::get_monitor_workspace_ids()?;
+    reconcile::check_startup(
+        &config,
+        config.reconcile.startup,
+        &mut state,
+        &startup_shown,
+        focused_slot,
+    )?;
+    if let Some(workspace_id) =
+        sync_attached_slots_to_active_group(&config, &mut state, focused_slot)?
+    {
         active_workspace_id = workspace_id;
         active_workspace = None;
         present_workspace_ids.insert(active_workspace_id);
     }
+    templates::furnish_active_group(&config, &mut state, focused_slot);
+    // Runtime state only survives within a login, so its absence means this is a fresh session
+    // rather than a restarted daemon whose windows are all still there.
+    if config.session.restore && !loaded_runtime_state {
+        match restore_session(&config, &mut state) {
+            Ok(count) => tracing::info!("Relaunched {count} window(s) from the last session"),
+            Err(err) => tracing::warn!("Failed to restore session: {err:?}"),
+        }
+    }
     persist_runtime_state(&state);
-    println!("Sorted monitors: {monitors:?}");
-    println!("Initial workspace: {initial_workspace:?}");
-    for msg in rx {
-        println!("Msg: {msg:?}");
+    // Group hooks run once at startup so themes match the restored group, then on every change of
+    // the active group or its name, whichever command caused it.
+    let mut announced_group = active_group_identity(&state);
+    hooks::group_changed(
+        &config,
+        announced_group.0,
+        &announced_group.1,
+        state.settings_group(announced_group.0),
+    );
+    if let Some(command) = &config.hooks.startup {
+        hooks::spawn_shell(command, &[]);
+    }
+    let mut announced_view = active_view(&state, focused_slot);
+    tracing::info!("Sorted monitors: {monitors:?}");
+    tracing::info!("Initial workspace: {initial_workspace:?}");
+    let mut retired = false;
+    let mut shutdown_reason = ShutdownReason::Disconnected;
+    let mut backend_healthy = true;
+    // Commands received while Hyprland is unresponsive, and the ones to replay once it is back.
+    let mut paused = VecDeque::new();
+    let mut replay = VecDeque::new();
+    // When a client command last changed the active group. Events and timers moving the group are
+    // not another client's switch to yield to.
+    let mut group_changed_at = None;
+    while let Some((msg, replayed)) = replay.pop_front().map(|msg| (msg, true)).or_else(|| {
+        let deadline = focus_session.as_ref().filter(|_| !retired);
+        receive(&rx, deadline.map(|session| session.until)).map(|msg| (msg, false))
+    }) {
+        if retired {
+            // The state has been handed over. Anything handled now would be lost or diverge from
+            // the successor, which reads the same events from Hyprland itself.
+            continue;
+        }
+        let (msg, reporter) = match msg {
+            Message::Reporting(msg, reporter) => (*msg, Some(reporter)),
+            msg => (msg, None),
+        };
+        let (msg, reply, arrived_at) = match msg {
+            // A replayed command waited out the hang, not another client. It arrives again now,
+            // so the commands replayed before it do not reject it.
+            Message::Replying(msg, reply, arrived_at) => (
+                *msg,
+                Some(reply),
+                if replayed { Instant::now() } else { arrived_at },
+            ),
+            msg => (msg, None, Instant::now()),
+        };
+        let msg = match resolve_group_name(&state, msg) {
+            Ok(msg) => msg,
+            Err(text) => {
+                tracing::warn!("{text}");
+                if let Some(reply) = reply {
+                    let _ = reply.send(Err(text));
+                }
+                continue;
+            }
+        };
+        let msg = match msg {
+            Message::Forced(msg) => *msg,
+            msg => {
+                if let Some(session) = &focus_session
+                    && focus::leaves_group(&msg)
+                    && !matches!(msg, Message::SwitchGroup(group) if group == session.group)
+                {
+                    let text = format!(
+                        "Staying on {} for another {} min. Add --force or run `hywoma focus stop` to leave.",
+                        session.group_name,
+                        session.minutes_left(Instant::now())
+                    );
+                    focus::notify(&config.focus, &text);
+                    if let Some(reply) = reply {
+                        let _ = reply.send(Err(text));
+                    }
+                    continue;
+                }
+                msg
+            }
+        };
+        if !backend_healthy && pauses_while_unhealthy(&msg) {
+            tracing::info!("Hyprland is not responding, queueing {msg:?}");
+            // The client keeps waiting for its reply until the command is replayed.
+            let msg = match reply {
+                Some(reply) => Message::Replying(Box::new(msg), reply, arrived_at),
+                None => msg,
+            };
+            paused.push_back(match reporter {
+                Some(reporter) => Message::Reporting(Box::new(msg), reporter),
+                None => msg,
+            });
+            continue;
+        }
+        if superseded_by_group_change(&msg, arrived_at, group_changed_at, state.active_group) {
+            let text = format!(
+                "The active group changed to {} while {msg:?} was queued, not switching",
+                state.active_group
+            );
+            tracing::warn!("{text}");
+            if let Some(reply) = reply {
+                let _ = reply.send(Err(text));
+            }
+            continue;
+        }
+        if needs_another_monitor(&state, focused_slot, &monitors, &msg) {
+            let result = match config.single_monitor {
+                SingleMonitor::Error => {
+                    let text = "Only one monitor is attached, there is no other to act on. Set single_monitor = \"ignore\" to skip slot commands quietly.".to_string();
+                    tracing::warn!("{text}");
+                    Err(text)
+                }
+                SingleMonitor::Ignore => {
+                    tracing::info!("Only one monitor is attached, ignoring {msg:?}");
+                    Ok(())
+                }
+            };
+            if let Some(reply) = reply {
+                let _ = reply.send(result);
+            }
+            continue;
+        }
+        // Whatever an earlier message without a client recorded.
+        take_rejection();
+        hyprland::take_dispatched();
+        progress::report_to(reporter);
+        tracing::debug!("Msg: {msg:?}");
+        // Cues are for navigation the user asked for. Hyprland events following a command (every
+        // monitor of a group switch reports its focus change) or a hotplug would otherwise turn one
+        // switch into a burst of sounds and announcements.
+        let counts_changed = matches!(
+            msg,
+            Message::WindowOpened { .. }
+                | Message::WindowClosed { .. }
+                | Message::WindowMoved { .. }
+                | Message::Reconcile
+        );
+        let should_announce = !matches!(
+            msg,
+            Message::ActiveWorkspaceChanged { .. }
+                | Message::WorkspaceCreated { .. }
+                | Message::WorkspaceDestroyed { .. }
+                | Message::MonitorTopologyChanged
+                | Message::Reconcile
+                | Message::ScheduleTick
+                | Message::BackendHealth(_)
+                | Message::WindowOpened { .. }
+                | Message::ActiveWindowChanged { .. }
+                | Message::WindowClosed { .. }
+                | Message::WindowMoved { .. }
+                | Message::SpecialWorkspaceChanged { .. }
+                | Message::Shutdown(_)
+                | Message::FocusExpired
+        );
+        // Everything logged while handling a command carries it. Events are too frequent for that.
+        let _command_span = if should_announce {
+            tracing::info_span!("command", command = ?msg).entered()
+        } else {
+            tracing::Span::none().entered()
+        };
         let mut should_broadcast = false;
         let mut should_persist = false;
+        let mut should_reattach = false;
+        let mut should_reconcile = false;
+        let mut should_refocus = false;
+        // Closed windows deliberately do not update the recorded session: logging out closes them
+        // all, and the session to restore is the one from before that.
+        let mut should_record_session = false;
+        let view_before = active_view(&state, focused_slot);
+        if config.thumbnails.enabled {
+            thumbnails.capture_on_dispatch(
+                &config.thumbnails,
+                leaving_workspaces(&state, focused_slot, &msg),
+            );
+        }
         match msg {
             Message::ActiveWorkspaceChanged {
                 workspace_id,
@@ -789,10 +2732,27 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
                     workspace_id,
                     monitor_name.as_deref(),
                 );
+                if recreated_workspace_ids.remove(&workspace_id)
+                    && let Some(key) = state.key_for_workspace_id(workspace_id)
+                {
+                    layouts.reapply(&config, key, workspace_id);
+                    // Hyprland forgets the name of a workspace it destroyed for being empty.
+                    if state.workspace_label(key).is_some() {
+                        mirror_workspace_label(&config, &state, key);
+                    }
+                }
                 should_broadcast = true;
                 should_persist = true;
             }
             Message::WorkspaceCreated { workspace_id } => {
+                if config
+                    .unmanaged_workspaces
+                    .iter()
+                    .any(|selector| matches!(selector, WorkspaceSelector::Name(_)))
+                {
+                    refresh_unmanaged_workspaces(&config, &mut state);
+                }
+                recreated_workspace_ids.insert(workspace_id);
                 if present_workspace_ids.insert(workspace_id) {
                     should_broadcast = true;
                 }
@@ -804,115 +2764,695 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
                 if workspace_id != active_workspace_id
                     && present_workspace_ids.remove(&workspace_id)
                 {
+                    thumbnails.forget(workspace_id);
                     should_broadcast = true;
                 }
             }
-            Message::MonitorTopologyChanged => {
-                let previous_active_group = state.active_group;
-                let previous_focused_slot = focused_slot;
-                monitors = hyprland::get_monitors()?;
-                attach_monitors_for_host(&mut state, &monitors);
-                // Monitor removal can emit transitional old workspace IDs such as `1` before the
-                // final active opaque workspace event arrives. Re-read Hyprland's current active
-                // workspace and present workspace list here to recover from those transient events.
-                present_workspace_ids = hyprland::get_workspace_ids()?.into_iter().collect();
-                active_workspace_id = hyprland::get_active_workspace_id()?;
-                present_workspace_ids.insert(active_workspace_id);
-                sync_active_workspace_id(
-                    &mut state,
-                    &mut active_workspace,
-                    &mut focused_slot,
-                    active_workspace_id,
-                    None,
+            Message::MonitorTopologyChanged => should_reattach = true,
+            Message::Reconcile => should_reconcile = true,
+            Message::Sync => {
+                tracing::info!("Refreshing all cached Hyprland state");
+                // Windows closed or moved behind our back would otherwise be refocused later, or
+                // keep locks that can never trigger.
+                match hyprland::get_window_workspace_ids() {
+                    Ok(windows) => {
+                        last_windows.retain(|key, address| {
+                            windows
+                                .get(address)
+                                .and_then(|id| state.key_for_workspace_id(*id))
+                                == Some(*key)
+                        });
+                        locked_windows.retain(|address, _| windows.contains_key(address));
+                        marked_windows.retain(|address| windows.contains_key(address));
+                    }
+                    Err(err) => tracing::warn!("Failed to list windows: {err:?}"),
+                }
+                // Reconciling corrects the model against what monitors show; the reattach after it
+                // re-reads monitors, workspaces and the active workspace from scratch.
+                should_reconcile = true;
+                should_reattach = true;
+            }
+            Message::ScheduleTick => {
+                let now = chrono::Local::now().naive_local();
+                match scheduler.tick(&config.schedule, now) {
+                    Some(group) if focus_session.is_some() => {
+                        tracing::info!(
+                            "Skipping scheduled switch to group {group} during a focus session"
+                        );
+                    }
+                    Some(group) => {
+                        tracing::info!("Scheduled switch to group {group}");
+                        lingering_slots.clear();
+                        match switch_group(&config, &mut state, focused_slot, group) {
+                            Ok(Some(workspace_id)) => {
+                                active_workspace_id = workspace_id;
+                                active_workspace = None;
+                                present_workspace_ids.insert(active_workspace_id);
+                            }
+                            Ok(None) => {}
+                            Err(err) => {
+                                reject(format!("Failed to switch to group {group}: {err:?}"))
+                            }
+                        }
+                        should_broadcast = true;
+                        should_persist = true;
+                        should_refocus = true;
+                    }
+                    None => {}
+                }
+            }
+            Message::Idle => {
+                // hypridle can run several timeouts; the first one marks the start of the break.
+                idle_since.get_or_insert_with(Instant::now);
+            }
+            Message::Resume => {
+                let idle_for = idle_since.take().map(|since| since.elapsed());
+                if let Some(group) = config.idle.home_group
+                    && let Some(idle_for) = idle_for
+                    && idle_for.as_secs() >= config.idle.return_after_secs
+                    && state.active_group != group
+                    && focus_session.is_none()
+                {
+                    tracing::info!("Returning to home group {group} after {idle_for:?} idle");
+                    lingering_slots.clear();
+                    match switch_group(&config, &mut state, focused_slot, group) {
+                        Ok(Some(workspace_id)) => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                        }
+                        Ok(None) => {}
+                        Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
+                    }
+                    should_broadcast = true;
+                    should_persist = true;
+                    should_refocus = true;
+                }
+            }
+            Message::FocusStart(secs) => {
+                let (group, group_name) = active_group_identity(&state);
+                focus::notify(
+                    &config.focus,
+                    &format!("Focusing on {group_name} for {} min", secs.div_ceil(60)),
                 );
-                if state.has_group(previous_active_group) {
-                    state.restore_active_group(previous_active_group);
+                focus_session = Some(FocusSession {
+                    group,
+                    group_name,
+                    until: Instant::now() + Duration::from_secs(secs),
+                });
+            }
+            Message::FocusStop => {
+                if let Some(session) = focus_session.take() {
+                    focus::notify(
+                        &config.focus,
+                        &format!("Focus session on {} stopped", session.group_name),
+                    );
+                }
+            }
+            Message::FocusExpired => {
+                if let Some(session) = focus_session.take() {
+                    focus::notify(
+                        &config.focus,
+                        &format!("Focus session on {} is over", session.group_name),
+                    );
+                }
+            }
+            // Unwrapped before dispatching.
+            Message::Forced(_)
+            | Message::NamedGroup(..)
+            | Message::Replying(..)
+            | Message::Reporting(..) => {}
+            Message::Snooze(minutes) => {
+                let now = chrono::Local::now().naive_local();
+                scheduler.snooze(&config.schedule, now, minutes);
+            }
+            Message::WindowOpened { address, class } => {
+                should_record_session = config.session.restore;
+                // Explicit rules win over the rules of the active group's template.
+                let rule = rules::matching(&rules, &class)
+                    .cloned()
+                    .or_else(|| templates::matching_rule(&config, &state, &class));
+                if let Some(rule) = rule {
+                    // A failed placement must not take the daemon down, the window simply stays
+                    // where Hyprland opened it.
+                    if let Err(err) = apply_rule(&config, &mut state, focused_slot, &rule, &address)
+                    {
+                        tracing::warn!("Failed to apply rule for class {class:?}: {err:?}");
+                    }
+                    should_persist = true;
                 }
-                if state
-                    .runtime_monitor_id_for_slot(previous_focused_slot)
-                    .is_some()
+            }
+            Message::ActiveWindowChanged { address } => {
+                // Over a shown special workspace, the focused window is most likely on that one,
+                // and focusing it again later would pull the scratchpad back up.
+                let over_special = state
+                    .slots
+                    .get(&focused_slot)
+                    .is_some_and(|slot| slot.special_workspace.is_some());
+                if !over_special && let Some(key) = state.key_for_workspace_id(active_workspace_id)
                 {
-                    focused_slot = previous_focused_slot;
+                    last_windows.insert(key, address);
                 }
-                if let Some(workspace_id) =
-                    sync_attached_slots_to_active_group(&mut state, focused_slot)?
+            }
+            // A moved window would drag focus back to its new workspace, a closed one cannot be
+            // focused at all.
+            Message::WindowClosed { address } => {
+                last_windows.retain(|_, last| *last != address);
+                locked_windows.remove(&address);
+                marked_windows.retain(|marked| *marked != address);
+            }
+            Message::WindowMoved {
+                address,
+                workspace_id,
+            } => {
+                should_record_session = config.session.restore;
+                last_windows.retain(|_, last| *last != address);
+                if let Some(&locked) = locked_windows.get(&address)
+                    && workspace_id != Some(locked)
                 {
-                    active_workspace_id = workspace_id;
-                    active_workspace = None;
-                    present_workspace_ids.insert(active_workspace_id);
+                    // Moving it back reports another move, to the locked workspace, which ends
+                    // here.
+                    if let Err(err) = hyprctl(
+                        &config,
+                        &format!("dispatch movetoworkspacesilent {locked},address:0x{address}"),
+                    ) {
+                        tracing::warn!("Failed to move locked window {address} back: {err:?}");
+                    }
                 }
-                println!("Monitor topology update, sorted monitors: {monitors:?}");
-                should_broadcast = true;
-                should_persist = true;
             }
             Message::Status(response_tx) => {
                 let status = status_snapshot(
                     active_workspace_id,
                     focused_slot,
                     &present_workspace_ids,
+                    &monitors,
                     &state,
+                    &thumbnails,
+                    backend_healthy,
                 );
                 let response = serde_json::to_string_pretty(&status)?;
-                let _ = response_tx.send(response);
+                let _ = response_tx.send(Ok(response));
+            }
+            Message::Ping(response_tx) => {
+                let mut response = format!("hywoma server {} is running", process::id());
+                if !backend_healthy {
+                    response.push_str(", but Hyprland is not responding");
+                }
+                let _ = response_tx.send(Ok(response));
             }
             Message::TmpSlots(response_tx) => {
-                let _ = response_tx.send(tmp_slots_response(&state, &present_workspace_ids));
+                let _ = response_tx.send(Ok(tmp_slots_response(&state, &present_workspace_ids)));
             }
             Message::TmpSwapWithSlot(slot, response_tx) => {
                 let response = if slot_to_monitor_pos(slot).is_some() {
                     tmp_swap_with_slot(
+                        &config,
                         &mut state,
                         &mut present_workspace_ids,
                         focused_slot,
                         slot,
                         &mut active_workspace_id,
-                    )?
+                    )
+                    .map_err(|err| format!("Failed to swap with slot {slot}: {err:?}"))
                 } else {
-                    format!("Slot numbers start at 1, got {slot}")
+                    Err(format!("Slot numbers start at 1, got {slot}"))
                 };
                 active_workspace = None;
                 should_broadcast = true;
                 should_persist = true;
                 let _ = response_tx.send(response);
             }
-            Message::SelectWorkspace(workspace) => {
-                active_workspace_id = select_workspace(&mut state, focused_slot, workspace)?;
-                active_workspace = None;
-                present_workspace_ids.insert(active_workspace_id);
-                should_broadcast = true;
-                should_persist = true;
+            Message::Rule(request, response_tx) => {
+                let _ = response_tx.send(rules::handle_request(&mut rules, request, &state));
             }
-            Message::SelectWorkspaceDelta(delta) => {
-                if let Some(workspace_id) =
-                    select_workspace_delta(&mut state, &present_workspace_ids, focused_slot, delta)?
+            Message::SwitchProfile(name, response_tx) => {
+                match base_config.with_profile(&name) {
+                    Ok(profile_config) => {
+                        config = profile_config;
+                        // Live rule edits belong to the profile that was active when they were
+                        // made, so the new profile starts from its configured rules.
+                        rules = rules::valid(&config.rules, &state);
+                        refresh_unmanaged_workspaces(&config, &mut state);
+                        apply_group_names(&config, &mut state);
+                        should_reattach = true;
+                        let _ = response_tx.send(Ok(format!("Switched to profile {name}")));
+                    }
+                    Err(err) => {
+                        let _ = response_tx.send(Err(err.to_string()));
+                    }
+                }
+            }
+            Message::ListProfiles(response_tx) => {
+                let _ = response_tx.send(Ok(list_profiles(&base_config, &config)));
+            }
+            Message::ListGroups(tree, json, response_tx) => {
+                let clients = if tree {
+                    Some(hyprland::get_clients())
+                } else {
+                    None
+                };
+                let response = match clients.transpose() {
+                    Ok(clients) => {
+                        let groups = group_tree(&state, clients.as_deref());
+                        Ok(if json {
+                            serde_json::to_string_pretty(&groups)?
+                        } else {
+                            describe_group_tree(&groups)
+                        })
+                    }
+                    Err(err) => Err(format!("Failed to list windows: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::PlanGroupSwitch(group, response_tx) => {
+                let _ =
+                    response_tx.send(Ok(describe_group_switch_plan(&state, focused_slot, group)));
+            }
+            Message::Retile(all, response_tx) => {
+                let response = match retile(&config, &state, all) {
+                    Ok(count) => Ok(format!("Retiled {count} window(s)")),
+                    Err(err) => Err(format!("Failed to retile: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::Hyprctl(args, unchecked, response_tx) => {
+                let response = match passthrough_request(&args, unchecked) {
+                    Ok(request) => hyprland::hyprctl(&request)
+                        .map_err(|err| format!("hyprctl `{request}` failed: {err:#}")),
+                    Err(refusal) => {
+                        tracing::warn!("{refusal}");
+                        Err(refusal)
+                    }
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::Where(pattern, focus, response_tx) => {
+                let response = match hyprland::get_clients() {
+                    Ok(clients) => {
+                        let found = matching_windows(&clients, &pattern);
+                        if focus && let Some(window) = found.first() {
+                            // Switch the group first: focusing a window of another group would show
+                            // its workspace without the rest of that group.
+                            if let Some(key) = state.key_for_workspace_id(window.workspace_id)
+                                && key.group != state.active_group
+                            {
+                                lingering_slots.clear();
+                                match switch_group(&config, &mut state, focused_slot, key.group) {
+                                    Ok(Some(workspace_id)) => {
+                                        active_workspace_id = workspace_id;
+                                        active_workspace = None;
+                                        present_workspace_ids.insert(active_workspace_id);
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => reject(format!(
+                                        "Failed to switch to group {}: {err:?}",
+                                        key.group
+                                    )),
+                                }
+                                should_broadcast = true;
+                                should_persist = true;
+                            }
+                            if let Err(err) = hyprctl(
+                                &config,
+                                &format!("dispatch focuswindow address:0x{}", window.address),
+                            ) {
+                                reject(format!(
+                                    "Failed to focus window {}: {err:?}",
+                                    window.address
+                                ));
+                            }
+                        }
+                        // A window that could not be focused fails the command, not only the log.
+                        match take_rejection() {
+                            Some(reason) => Err(reason),
+                            None => Ok(describe_windows(&state, &found)),
+                        }
+                    }
+                    Err(err) => Err(format!("Failed to list windows: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::CycleWindow(reverse) => match hyprland::get_clients() {
+                Ok(clients) => {
+                    let current = hyprland::get_active_window_address().unwrap_or_else(|err| {
+                        tracing::warn!("Failed to get the focused window: {err:?}");
+                        None
+                    });
+                    match cycle_target(&state, &clients, current.as_deref(), reverse) {
+                        // Hyprland shows the window's workspace on its monitor, and the focus
+                        // events that follow update the model.
+                        Some(window) => {
+                            if let Err(err) = hyprctl(
+                                &config,
+                                &format!("dispatch focuswindow address:0x{}", window.address),
+                            ) {
+                                reject(format!(
+                                    "Failed to focus window {}: {err:?}",
+                                    window.address
+                                ));
+                            }
+                        }
+                        None => reject(format!("No windows in group {}", state.active_group)),
+                    }
+                }
+                Err(err) => reject(format!("Failed to list windows: {err:?}")),
+            },
+            Message::SaveSession(response_tx) => {
+                let response = match save_session(&state) {
+                    Ok(count) => Ok(format!("Saved {count} window(s)")),
+                    Err(err) => Err(format!("Failed to save session: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::RestoreSession(response_tx) => {
+                let response = match restore_session(&config, &mut state) {
+                    Ok(count) => {
+                        should_broadcast = true;
+                        should_persist = true;
+                        Ok(format!("Relaunched {count} window(s)"))
+                    }
+                    Err(err) => Err(format!("Failed to restore session: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::ArchiveGroup(group, response_tx) => {
+                let response = match archive::archive(&config, &mut state, group) {
+                    Ok(count) => {
+                        should_broadcast = true;
+                        should_persist = true;
+                        Ok(format!("Archived {count} window(s) of group {group}"))
+                    }
+                    Err(err) => Err(format!("Failed to archive group {group}: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::RestoreGroup(group, response_tx) => {
+                let response = match archive::restore(&config, &mut state, group) {
+                    Ok(count) => {
+                        should_broadcast = true;
+                        should_persist = true;
+                        Ok(format!("Restored {count} window(s) of group {group}"))
+                    }
+                    Err(err) => Err(format!("Failed to restore group {group}: {err:?}")),
+                };
+                let _ = response_tx.send(response);
+            }
+            Message::RestartServer(command_fd, response_tx) => {
+                persist_runtime_state(&state);
+                let spawned = spawn_successor(command_fd);
+                if let Ok(pid) = &spawned {
+                    tracing::info!("Handing over to hywoma server {pid}");
+                    retired = true;
+                }
+                let _ = response_tx.send(spawned);
+            }
+            Message::SelectWorkspace(workspace, target) => {
+                let slot = match &target {
+                    Some(target) => resolve_slot_target(&state, focused_slot, &monitors, target),
+                    None => Some(focused_slot),
+                };
+                if let Some(slot) = slot
+                    && workspace_in_range(&state, slot, workspace)
                 {
-                    active_workspace_id = workspace_id;
-                    active_workspace = None;
-                    present_workspace_ids.insert(active_workspace_id);
+                    let selected = if slot == focused_slot {
+                        select_workspace(&config, &mut state, focused_slot, workspace).map(Some)
+                    } else {
+                        select_workspace_on_slot(&config, &mut state, slot, focused_slot, workspace)
+                    };
+                    match selected {
+                        Ok(Some(workspace_id)) if slot == focused_slot => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            should_refocus = true;
+                        }
+                        Ok(workspace_id) => present_workspace_ids.extend(workspace_id),
+                        Err(err) => reject(format!(
+                            "Failed to select workspace {workspace} on slot {slot}: {err:?}"
+                        )),
+                    }
                     should_broadcast = true;
                     should_persist = true;
                 }
             }
-            Message::MoveToWorkspace(workspace) => {
-                move_to_workspace(&mut state, focused_slot, workspace)?;
+            Message::SelectWorkspaceDelta(delta) => {
+                match select_workspace_delta(
+                    &config,
+                    &mut state,
+                    &present_workspace_ids,
+                    focused_slot,
+                    delta,
+                ) {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                        should_broadcast = true;
+                        should_persist = true;
+                        should_refocus = true;
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!(
+                        "Failed to select the workspace {delta:+} from the focused one: {err:?}"
+                    )),
+                }
+            }
+            Message::SelectWorkspaceRelative(delta) => {
+                if let Some(workspace) =
+                    relative_workspace(&state, focused_slot, delta, config.wrap_workspaces)
+                {
+                    match select_workspace(&config, &mut state, focused_slot, workspace) {
+                        Ok(workspace_id) => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            should_broadcast = true;
+                            should_persist = true;
+                            should_refocus = true;
+                        }
+                        Err(err) => {
+                            reject(format!("Failed to select workspace {workspace}: {err:?}"))
+                        }
+                    }
+                }
+            }
+            Message::MoveToWorkspaceRelative(delta, follow) => {
+                let follow = follow || config.follow_moves;
+                if let Some(workspace) =
+                    relative_workspace(&state, focused_slot, delta, config.wrap_workspaces)
+                {
+                    match move_to_workspace(&config, &mut state, focused_slot, workspace, follow) {
+                        Ok(workspace_id) if follow => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            should_broadcast = true;
+                        }
+                        Ok(_) => {}
+                        Err(err) => reject(format!(
+                            "Failed to move window to workspace {workspace}: {err:?}"
+                        )),
+                    }
+                    should_persist = true;
+                }
+            }
+            Message::ToggleWorkspace => {
+                match previous_visible.get(&(state.active_group, focused_slot)) {
+                    Some(&workspace) if workspace_in_range(&state, focused_slot, workspace) => {
+                        match select_workspace(&config, &mut state, focused_slot, workspace) {
+                            Ok(workspace_id) => {
+                                active_workspace_id = workspace_id;
+                                active_workspace = None;
+                                present_workspace_ids.insert(active_workspace_id);
+                                should_broadcast = true;
+                                should_persist = true;
+                                should_refocus = true;
+                            }
+                            Err(err) => {
+                                reject(format!("Failed to select workspace {workspace}: {err:?}"))
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => reject(format!("No previous workspace on slot {focused_slot}")),
+                }
+            }
+            Message::SetLayout(layout) => {
+                let key = active_view(&state, focused_slot);
+                let workspace_id = state.workspace_id_for(key.group, key.slot, key.visible);
+                layouts.set_layout(&config, key, workspace_id, &layout);
+            }
+            Message::LayoutMsg(message) => {
+                layouts.send_message(
+                    &config,
+                    state.key_for_workspace_id(active_workspace_id),
+                    &message,
+                );
+            }
+            Message::MoveToEmpty => {
+                if let Err(err) = move_to_empty(&config, &mut state, focused_slot) {
+                    reject(format!(
+                        "Failed to move window to an empty workspace: {err:?}"
+                    ));
+                }
                 should_persist = true;
             }
+            Message::Compact => match compact(&config, &mut state) {
+                Ok(count) => {
+                    tracing::info!("Compacted {count} overflow workspace(s)");
+                    should_broadcast = true;
+                    should_persist = true;
+                }
+                Err(err) => reject(format!("Failed to compact overflow workspaces: {err:?}")),
+            },
+            Message::MoveToWorkspace(workspace, target, follow) => {
+                let follow = follow || config.follow_moves;
+                let slot = match &target {
+                    Some(target) => resolve_slot_target(&state, focused_slot, &monitors, target),
+                    None => Some(focused_slot),
+                };
+                if let Some(slot) = slot
+                    && workspace_in_range(&state, slot, workspace)
+                {
+                    match move_to_workspace(&config, &mut state, slot, workspace, follow) {
+                        Ok(workspace_id) if follow => {
+                            focused_slot = slot;
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            should_broadcast = true;
+                        }
+                        Ok(_) => {}
+                        Err(err) => reject(format!(
+                            "Failed to move window to workspace {workspace} on slot {slot}: {err:?}"
+                        )),
+                    }
+                    should_persist = true;
+                }
+            }
             Message::SwitchGroup(group) => {
-                if let Some(workspace_id) = switch_group(&mut state, focused_slot, group)? {
-                    active_workspace_id = workspace_id;
-                    active_workspace = None;
-                    present_workspace_ids.insert(active_workspace_id);
+                lingering_slots.clear();
+                match switch_group(&config, &mut state, focused_slot, group) {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
+                }
+                should_broadcast = true;
+                should_persist = true;
+                should_refocus = true;
+            }
+            Message::SwitchGroupRelative(delta) => {
+                let group = relative_group(&mut state, delta, config.max_groups);
+                lingering_slots.clear();
+                match switch_group(&config, &mut state, focused_slot, group) {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
+                }
+                should_broadcast = true;
+                should_persist = true;
+                should_refocus = true;
+            }
+            Message::SwitchGroupOccupiedOnly(group) => {
+                lingering_slots.clear();
+                let switched = hyprland::get_workspace_window_counts().and_then(|window_counts| {
+                    switch_group_where(
+                        &config,
+                        &mut state,
+                        focused_slot,
+                        group,
+                        |slot, workspace_id| {
+                            let occupied = window_counts.get(&workspace_id).is_some_and(|n| *n > 0);
+                            if !occupied {
+                                lingering_slots.insert(slot);
+                            }
+                            occupied
+                        },
+                    )
+                });
+                match switched {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
                 }
                 should_broadcast = true;
                 should_persist = true;
+                should_refocus = true;
             }
-            Message::CreateGroup(name) => {
+            Message::CreateGroup(name, template) => {
                 let group = state.create_group(name);
-                if let Some(workspace_id) = switch_group(&mut state, focused_slot, group)? {
-                    active_workspace_id = workspace_id;
-                    active_workspace = None;
-                    present_workspace_ids.insert(active_workspace_id);
+                if let Some(entry) = state.groups.get_mut(&group) {
+                    entry.template = template;
+                }
+                lingering_slots.clear();
+                match switch_group(&config, &mut state, focused_slot, group) {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
+                }
+                should_broadcast = true;
+                should_persist = true;
+            }
+            Message::EnterSubgroup(name) => {
+                let group = state.find_or_create_subgroup(state.active_group, name);
+                lingering_slots.clear();
+                match switch_group(&config, &mut state, focused_slot, group) {
+                    Ok(Some(workspace_id)) => {
+                        active_workspace_id = workspace_id;
+                        active_workspace = None;
+                        present_workspace_ids.insert(active_workspace_id);
+                    }
+                    Ok(None) => {}
+                    Err(err) => reject(format!("Failed to switch to group {group}: {err:?}")),
+                }
+                should_broadcast = true;
+                should_persist = true;
+                should_refocus = true;
+            }
+            Message::LeaveSubgroup => match state.parent_of(state.active_group) {
+                Some(parent) => {
+                    lingering_slots.clear();
+                    match switch_group(&config, &mut state, focused_slot, parent) {
+                        Ok(Some(workspace_id)) => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                        }
+                        Ok(None) => {}
+                        Err(err) => reject(format!("Failed to switch to group {parent}: {err:?}")),
+                    }
+                    should_broadcast = true;
+                    should_persist = true;
+                    should_refocus = true;
+                }
+                None => reject(format!("Group {} is not a sub-group", state.active_group)),
+            },
+            Message::RenameWorkspace(label) => {
+                let key = WorkspaceKey {
+                    group: state.active_group,
+                    slot: focused_slot,
+                    visible: state.active_visible(focused_slot),
+                };
+                if let Some(group) = state.groups.get_mut(&key.group) {
+                    group.set_workspace_label(key.slot, key.visible, label);
                 }
+                mirror_workspace_label(&config, &state, key);
                 should_broadcast = true;
                 should_persist = true;
             }
@@ -922,63 +3462,518 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
                     should_broadcast = true;
                     should_persist = true;
                 } else {
-                    eprintln!("Cannot rename unknown workspace group {group}");
+                    reject(format!("Cannot rename unknown workspace group {group}"));
                 }
             }
             Message::DeleteGroup(group) => {
                 should_broadcast = delete_group(&mut state, &present_workspace_ids, group);
                 should_persist = should_broadcast;
+                if should_broadcast {
+                    layouts.forget_group(group);
+                }
             }
             Message::MoveToGroup(group) => {
-                move_to_group(&mut state, focused_slot, group)?;
+                if let Err(err) = move_to_group(&config, &mut state, focused_slot, group) {
+                    reject(format!("Failed to move window to group {group}: {err:?}"));
+                }
                 should_persist = true;
             }
-            Message::SelectSlot(slot) => {
-                if slot_to_monitor_pos(slot).is_some() {
-                    if let Some(workspace_id) = select_slot(&mut state, slot)? {
-                        focused_slot = slot;
-                        active_workspace_id = workspace_id;
-                        active_workspace = None;
-                        present_workspace_ids.insert(active_workspace_id);
-                        should_broadcast = true;
-                        should_persist = true;
+            Message::SelectSlot(target) => {
+                if let Some(slot) = resolve_slot_target(&state, focused_slot, &monitors, &target) {
+                    match select_slot(&config, &mut state, slot) {
+                        Ok(Some(workspace_id)) => {
+                            // With edge switching the pointer has to leave the edge it rests on,
+                            // or Hyprland would focus the monitor under it again.
+                            if (config.warp_cursor || config.edge_switch.enabled)
+                                && let Err(err) = warp_cursor_to_slot(&config, &state, slot)
+                            {
+                                tracing::warn!("Failed to warp cursor to slot {slot}: {err:?}");
+                            }
+                            focused_slot = slot;
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            should_broadcast = true;
+                            should_persist = true;
+                        }
+                        Ok(None) => {}
+                        Err(err) => reject(format!("Failed to select slot {slot}: {err:?}")),
                     }
-                } else {
-                    eprintln!("Slot numbers start at 1, got {slot}");
                 }
             }
-            Message::MoveToSlot(slot) => {
-                if slot_to_monitor_pos(slot).is_some() {
-                    move_to_slot(&mut state, slot)?;
+            Message::MoveToSlot(target, follow) => {
+                let follow = follow || config.follow_moves;
+                if let Some(slot) = resolve_slot_target(&state, focused_slot, &monitors, &target) {
+                    match move_to_slot(&config, &mut state, slot, follow) {
+                        Ok(Some(workspace_id)) => {
+                            // Hyprland reports the new focus too, but bindings chained after this
+                            // one should already act on the target monitor.
+                            if follow {
+                                focused_slot = slot;
+                                active_workspace_id = workspace_id;
+                                active_workspace = None;
+                                present_workspace_ids.insert(active_workspace_id);
+                                should_broadcast = true;
+                            }
+                            should_persist = true;
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            reject(format!("Failed to move window to slot {slot}: {err:?}"))
+                        }
+                    }
+                }
+            }
+            Message::MoveWorkspaceToSlot(target, follow) => {
+                let follow = follow || config.follow_moves;
+                if let Some(slot) = resolve_slot_target(&state, focused_slot, &monitors, &target) {
+                    match move_workspace_to_slot(
+                        &config,
+                        &mut state,
+                        focused_slot,
+                        slot,
+                        &locked_windows,
+                    ) {
+                        Ok(Some(workspace_id)) => {
+                            if follow {
+                                match select_slot(&config, &mut state, slot) {
+                                    Ok(Some(_)) => {
+                                        focused_slot = slot;
+                                        active_workspace_id = workspace_id;
+                                        active_workspace = None;
+                                        present_workspace_ids.insert(active_workspace_id);
+                                    }
+                                    Ok(None) => {}
+                                    Err(err) => reject(format!(
+                                        "Failed to follow the workspace to slot {slot}: {err:?}"
+                                    )),
+                                }
+                            }
+                            should_broadcast = true;
+                            should_persist = true;
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            reject(format!("Failed to move workspace to slot {slot}: {err:?}"))
+                        }
+                    }
+                }
+            }
+            Message::SwapSlot(target) => {
+                if let Some(slot) = resolve_slot_target(&state, focused_slot, &monitors, &target) {
+                    if let Err(err) = swap_slot(&config, &mut state, focused_slot, slot) {
+                        reject(format!("Failed to swap with slot {slot}: {err:?}"));
+                    }
+                    should_broadcast = true;
                     should_persist = true;
-                } else {
-                    eprintln!("Slot numbers start at 1, got {slot}");
                 }
             }
-            Message::SwapSlot(slot) => {
-                if slot_to_monitor_pos(slot).is_some() {
-                    swap_slot(&mut state, focused_slot, slot)?;
+            Message::SwapMonitors(targets) => {
+                let slots = match &targets {
+                    Some((a, b)) => resolve_slot_target(&state, focused_slot, &monitors, a)
+                        .zip(resolve_slot_target(&state, focused_slot, &monitors, b)),
+                    None => outermost_slots(&state, &monitors),
+                };
+                if let Some((a, b)) = slots {
+                    if let Err(err) = swap_slot(&config, &mut state, a, b) {
+                        reject(format!("Failed to swap slots {a} and {b}: {err:?}"));
+                    }
                     should_broadcast = true;
                     should_persist = true;
+                }
+            }
+            Message::SwapWithWorkspace(workspace) => {
+                let current = state.active_visible(focused_slot);
+                if workspace == current {
+                    tracing::info!("Skipping swap of workspace {workspace} with itself");
+                } else if workspace_in_range(&state, focused_slot, workspace) {
+                    match swap_with_workspace(&config, &mut state, focused_slot, workspace) {
+                        Ok(workspace_id) => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                            // Remembered windows travel with their workspace.
+                            let group = state.active_group;
+                            let key = |visible| WorkspaceKey {
+                                group,
+                                slot: focused_slot,
+                                visible,
+                            };
+                            let current_window = last_windows.remove(&key(current));
+                            if let Some(address) = last_windows.remove(&key(workspace)) {
+                                last_windows.insert(key(current), address);
+                            }
+                            if let Some(address) = current_window {
+                                last_windows.insert(key(workspace), address);
+                            }
+                            should_broadcast = true;
+                            should_persist = true;
+                            should_refocus = true;
+                        }
+                        Err(err) => reject(format!(
+                            "Failed to swap with workspace {workspace}: {err:?}"
+                        )),
+                    }
+                }
+            }
+            Message::MirrorGroup(group, target) => {
+                if mirror.is_some() {
+                    reject("Already mirroring a group, run `hywoma mirror-group --stop` first");
+                } else if let Some(slot) =
+                    presentation_slot(&state, focused_slot, &monitors, target.as_ref())
+                {
+                    match start_mirror(
+                        &config,
+                        &mut state,
+                        focused_slot,
+                        slot,
+                        group,
+                        &locked_windows,
+                    ) {
+                        Ok(started) => {
+                            mirror = started;
+                            if let Some(started) = &mirror {
+                                present_workspace_ids.insert(started.target_workspace_id);
+                                should_broadcast = true;
+                                should_persist = true;
+                            }
+                        }
+                        Err(err) => reject(format!("Failed to mirror group {group}: {err:?}")),
+                    }
+                }
+            }
+            Message::StopMirror => match mirror.take() {
+                Some(stopped) => {
+                    match stop_mirror(&config, &mut state, focused_slot, stopped) {
+                        Ok(workspace_id) => present_workspace_ids.extend(workspace_id),
+                        Err(err) => reject(format!("Failed to stop mirroring: {err:?}")),
+                    }
+                    should_broadcast = true;
+                    should_persist = true;
+                }
+                None => reject("No group is mirrored"),
+            },
+            Message::SpecialWorkspaceChanged { monitor_name, name } => {
+                // The regular workspace underneath stays the active one of its slot.
+                if state.set_special_workspace(&monitor_name, name) {
+                    should_broadcast = true;
+                }
+            }
+            Message::ToggleSpecial(name) => {
+                let dispatch = match name {
+                    Some(name) => format!("dispatch togglespecialworkspace {name}"),
+                    None => "dispatch togglespecialworkspace".to_string(),
+                };
+                if let Err(err) = hyprctl(&config, &dispatch) {
+                    reject(format!("{err:#}"));
+                }
+            }
+            Message::MoveToSpecial(name) => {
+                let workspace = match name {
+                    Some(name) => format!("special:{name}"),
+                    None => "special".to_string(),
+                };
+                if let Err(err) = hyprctl(
+                    &config,
+                    &format!("dispatch movetoworkspacesilent {workspace}"),
+                ) {
+                    reject(format!("{err:#}"));
+                }
+            }
+            Message::Unknown(args) => {
+                let command = args.join(" ");
+                match config.unknown_commands {
+                    UnknownCommands::Reject => reject(format!(
+                        "Unknown command `{command}`. Set unknown_commands = \"dispatch\" to pass such commands to Hyprland."
+                    )),
+                    UnknownCommands::Dispatch => {
+                        let dispatcher = args.first().map(String::as_str).unwrap_or_default();
+                        if let Some(reason) = dispatch_refusal(dispatcher) {
+                            reject(format!(
+                                "Refusing `dispatch {dispatcher}`: {reason}. Use `hywoma hyprctl --unchecked dispatch {command}` to send it anyway."
+                            ));
+                        } else if let Err(err) = hyprctl(&config, &format!("dispatch {command}")) {
+                            reject(format!("{err:#}"));
+                        }
+                    }
+                }
+            }
+            Message::LockWindow(target) => match window_lock(&target) {
+                Ok((address, workspace_id)) => {
+                    tracing::info!("Locked window {address} to workspace {workspace_id}");
+                    locked_windows.insert(address, workspace_id);
+                }
+                Err(err) => reject(format!("Failed to lock window: {err:?}")),
+            },
+            Message::MarkWindow(target) => match target.resolve() {
+                Ok(Some(address)) => {
+                    if !marked_windows.contains(&address) {
+                        marked_windows.push(address);
+                    }
+                    tracing::info!("{} window(s) selected", marked_windows.len());
+                }
+                Ok(None) => reject("No focused window to select"),
+                Err(err) => reject(format!("Failed to resolve window to select: {err:?}")),
+            },
+            Message::ClearMarks => marked_windows.clear(),
+            Message::MoveMarkedToWorkspace(workspace) => {
+                if marked_windows.is_empty() {
+                    reject("No windows selected");
+                } else if workspace_in_range(&state, focused_slot, workspace) {
+                    let workspace_id =
+                        state.workspace_id_for(state.active_group, focused_slot, workspace);
+                    let dispatches: Vec<String> = marked_windows
+                        .drain(..)
+                        .map(|address| {
+                            format!(
+                                "dispatch movetoworkspacesilent {workspace_id},address:0x{address}"
+                            )
+                        })
+                        .collect();
+                    match hyprland::hyprctl_batch(&config, &dispatches) {
+                        Ok(()) => tracing::info!(
+                            "Moved {} window(s) to workspace {workspace}",
+                            dispatches.len()
+                        ),
+                        Err(err) => reject(format!(
+                            "Failed to move the selected windows to workspace {workspace}: {err:?}"
+                        )),
+                    }
+                    should_persist = true;
+                }
+            }
+            Message::Gather => {
+                let workspace_id = state.workspace_id_for(
+                    state.active_group,
+                    focused_slot,
+                    state.active_visible(focused_slot),
+                );
+                match hyprland::get_clients() {
+                    Ok(clients) => {
+                        let dispatches: Vec<String> = windows_to_gather(
+                            &state,
+                            &clients,
+                            workspace_id,
+                            &locked_windows,
+                        )
+                        .iter()
+                        .map(|window| {
+                            format!(
+                                "dispatch movetoworkspacesilent {workspace_id},address:0x{}",
+                                window.address
+                            )
+                        })
+                        .collect();
+                        if dispatches.is_empty() {
+                            reject("No windows to gather");
+                        } else {
+                            match hyprland::hyprctl_batch(&config, &dispatches) {
+                                Ok(()) => tracing::info!(
+                                    "Gathered {} window(s) onto workspace {workspace_id}",
+                                    dispatches.len()
+                                ),
+                                Err(err) => reject(format!("Failed to gather windows: {err:?}")),
+                            }
+                            should_persist = true;
+                        }
+                    }
+                    Err(err) => reject(format!("Failed to list windows: {err:?}")),
+                }
+            }
+            Message::UnlockWindow(target) => match target.resolve() {
+                Ok(Some(address)) => {
+                    if locked_windows.remove(&address).is_none() {
+                        reject(format!("Window {address} is not locked"));
+                    }
+                }
+                Ok(None) => reject("No focused window to unlock"),
+                Err(err) => reject(format!("Failed to resolve window to unlock: {err:?}")),
+            },
+            Message::Shutdown(reason) => {
+                shutdown_reason = reason;
+                break;
+            }
+            Message::BackendHealth(healthy) => {
+                backend_healthy = healthy;
+                if healthy {
+                    tracing::info!(
+                        "Hyprland is responding again, replaying {} command(s)",
+                        paused.len()
+                    );
+                    replay.extend(paused.drain(..));
+                    // Whatever happened while it hung, the model may no longer match.
+                    should_reconcile = true;
                 } else {
-                    eprintln!("Slot numbers start at 1, got {slot}");
+                    tracing::warn!("Hyprland is not responding, pausing commands");
                 }
+                should_broadcast = true;
             }
-            Message::SubscribeEvents(mut stream) => {
-                stream.set_nonblocking(true)?;
+            Message::Watch(subscription_tx) => {
+                let event = focus_event(&state, active_view(&state, focused_slot));
+                let _ = subscription_tx.send(focus_changes.subscribe(&event)?);
+            }
+            Message::SubscribeEvents(subscription_tx) => {
                 // Subscribers receive an initial snapshot immediately, so AGS can start with a
                 // correct bar before any future Hyprland event happens.
-                if let Err(err) = write_event_snapshot(
-                    &mut stream,
+                let status = status_snapshot(
                     active_workspace_id,
                     focused_slot,
                     &present_workspace_ids,
+                    &monitors,
                     &state,
-                ) {
-                    eprintln!("Failed to write initial hywoma event snapshot: {err:?}");
-                } else {
-                    event_subscribers.push(stream);
+                    &thumbnails,
+                    backend_healthy,
+                );
+                let _ = subscription_tx.send(snapshots.subscribe(&status)?);
+            }
+        }
+        // Dispatches after the handler's own, like refocusing or reconciling, switch nothing away.
+        thumbnails::drop_pending();
+        // Tracked from the view rather than per command, so switches made in Hyprland directly
+        // count too.
+        let view_after = active_view(&state, focused_slot);
+        if view_after.visible != view_before.visible
+            && view_after.group == view_before.group
+            && view_after.slot == view_before.slot
+        {
+            previous_visible.insert((view_before.group, view_before.slot), view_before.visible);
+        }
+        if should_record_session && let Err(err) = save_session(&state) {
+            tracing::warn!("Failed to record session: {err:?}");
+        }
+        if templates::furnish_active_group(&config, &mut state, focused_slot) {
+            should_persist = true;
+        }
+        if should_refocus
+            && let Some(address) = state
+                .key_for_workspace_id(active_workspace_id)
+                .and_then(|key| last_windows.get(&key))
+        {
+            // Best effort: the window may have gone away without us seeing the event.
+            if let Err(err) = hyprctl(
+                &config,
+                &format!("dispatch focuswindow address:0x{address}"),
+            ) {
+                tracing::warn!("Failed to refocus window {address}: {err:?}");
+            }
+        }
+        // Queries and events answer from what Hyprland already reports, there is nothing to verify.
+        if should_announce && config.reconcile.verify_dispatches && hyprland::take_dispatched() {
+            match hyprland::get_active_workspace_id() {
+                Ok(reported_workspace_id) if reported_workspace_id != active_workspace_id => {
+                    tracing::warn!(
+                        "Dispatch verification failed: expected workspace {active_workspace_id}, Hyprland reports {reported_workspace_id}"
+                    );
+                    should_reconcile = true;
                 }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Failed to verify dispatches: {err:?}"),
+            }
+        }
+        if should_reconcile {
+            match hyprland::get_monitors() {
+                // Missed hotplug events; the full reattach below re-reads everything.
+                Ok(current_monitors) if current_monitors != monitors => should_reattach = true,
+                Ok(_) => match hyprland::get_monitor_workspace_ids() {
+                    Ok(mut shown) => {
+                        shown.retain(|(monitor_id, _)| {
+                            state
+                                .slot_for_monitor_id(*monitor_id)
+                                .is_none_or(|slot| !lingering_slots.contains(&slot))
+                        });
+                        let corrections = reconcile::plan(&mut state, &shown);
+                        if !corrections.is_empty() {
+                            tracing::info!("Reconciling drift from Hyprland: {corrections:?}");
+                            if let Err(err) =
+                                reconcile::apply(&config, &mut state, &corrections, focused_slot)
+                            {
+                                reject(format!("Failed to reconcile drift: {err:?}"));
+                            }
+                            should_persist = true;
+                        }
+                        match read_workspaces() {
+                            Ok((current_workspace_ids, current_active_workspace_id)) => {
+                                if current_workspace_ids != present_workspace_ids
+                                    || current_active_workspace_id != active_workspace_id
+                                    || !corrections.is_empty()
+                                {
+                                    present_workspace_ids = current_workspace_ids;
+                                    active_workspace_id = current_active_workspace_id;
+                                    present_workspace_ids.insert(active_workspace_id);
+                                    sync_active_workspace_id(
+                                        &mut state,
+                                        &mut active_workspace,
+                                        &mut focused_slot,
+                                        active_workspace_id,
+                                        None,
+                                    );
+                                    should_broadcast = true;
+                                    should_persist = true;
+                                }
+                            }
+                            Err(err) => reject(format!("Failed to read workspaces: {err:?}")),
+                        }
+                    }
+                    Err(err) => reject(format!("Failed to read shown workspaces: {err:?}")),
+                },
+                Err(err) => reject(format!("Failed to read monitors: {err:?}")),
+            }
+        }
+        if should_reattach {
+            let previous_active_group = state.active_group;
+            let previous_focused_slot = focused_slot;
+            match hyprland::get_monitors() {
+                Ok(current_monitors) => {
+                    monitors = current_monitors;
+                    attach_monitors_for_host(&config, &mut state, &monitors);
+                    lingering_slots.clear();
+                    if let Err(err) = apply_orientation_defaults(&config, &mut state) {
+                        tracing::warn!("Failed to apply orientation defaults: {err:?}");
+                    }
+                    // Monitor removal can emit transitional old workspace IDs such as `1` before
+                    // the final active opaque workspace event arrives. Re-read Hyprland's current
+                    // active workspace and present workspace list here to recover from those
+                    // transient events.
+                    match read_workspaces() {
+                        Ok((current_workspace_ids, current_active_workspace_id)) => {
+                            present_workspace_ids = current_workspace_ids;
+                            active_workspace_id = current_active_workspace_id;
+                            present_workspace_ids.insert(active_workspace_id);
+                        }
+                        Err(err) => reject(format!("Failed to read workspaces: {err:?}")),
+                    }
+                    sync_active_workspace_id(
+                        &mut state,
+                        &mut active_workspace,
+                        &mut focused_slot,
+                        active_workspace_id,
+                        None,
+                    );
+                    if state.has_group(previous_active_group) {
+                        state.restore_active_group(previous_active_group);
+                    }
+                    if state
+                        .runtime_monitor_id_for_slot(previous_focused_slot)
+                        .is_some()
+                    {
+                        focused_slot = previous_focused_slot;
+                    }
+                    match sync_attached_slots_to_active_group(&config, &mut state, focused_slot) {
+                        Ok(Some(workspace_id)) => {
+                            active_workspace_id = workspace_id;
+                            active_workspace = None;
+                            present_workspace_ids.insert(active_workspace_id);
+                        }
+                        Ok(None) => {}
+                        Err(err) => {
+                            reject(format!("Failed to sync slots to the active group: {err:?}"))
+                        }
+                    }
+                    tracing::info!("Monitor topology update, sorted monitors: {monitors:?}");
+                    should_broadcast = true;
+                    should_persist = true;
+                }
+                Err(err) => reject(format!("Failed to read monitors: {err:?}")),
             }
         }
         if should_persist {
@@ -986,36 +3981,99 @@ fn main_loop(rx: mpsc::Receiver<Message>) -> Result<()> {
             // runtime Hyprland state and are recomputed on startup.
             persist_runtime_state(&state);
         }
+        let active_group = active_group_identity(&state);
+        if active_group != announced_group {
+            if active_group.0 != announced_group.0 {
+                if reply.is_some() {
+                    group_changed_at = Some(Instant::now());
+                }
+                // The presentation monitor followed the switch, so the mirror is gone from view.
+                if let Some(stopped) = mirror.take() {
+                    tracing::info!("The active group changed, ending the mirror");
+                    if let Err(err) = stop_mirror(&config, &mut state, focused_slot, stopped) {
+                        tracing::warn!("Failed to end the mirror: {err:?}");
+                    }
+                }
+            }
+            hooks::group_changed(
+                &config,
+                active_group.0,
+                &active_group.1,
+                state.settings_group(active_group.0),
+            );
+            announced_group = active_group;
+        }
+        let view = active_view(&state, focused_slot);
+        if view != announced_view {
+            if should_announce {
+                hooks::announce_switch(&config, &state, announced_view, view);
+            }
+            let event = focus_event(&state, view);
+            if focus_changes.is_followed() {
+                focus_changes.send(&event)?;
+            }
+            if config.hooks.webhook_url.is_some() || config.hooks.mqtt.is_some() {
+                let payload = serde_json::to_string(&event)?;
+                hooks::post_webhook(&config, &payload);
+                hooks::publish_mqtt(&config, &payload);
+            }
+            announced_view = view;
+        }
+        // Also run without a FIFO, so one dropped from the config on reload gets closed.
+        let fifo_line = match &config.hooks.fifo {
+            Some(_) => focus_text(
+                &focus_event(&state, view),
+                config
+                    .hooks
+                    .fifo_format
+                    .as_deref()
+                    .unwrap_or(DEFAULT_WAYBAR_FORMAT),
+            ),
+            None => String::new(),
+        };
+        fifo_sink.update(config.hooks.fifo.as_deref(), fifo_line);
         if should_broadcast {
-            broadcast_event_snapshot(
-                &mut event_subscribers,
+            dimmer.update(&config, &state);
+        }
+        if should_broadcast || counts_changed {
+            badges.update(&config, &state);
+        }
+        progress::report_to(None);
+        if let Some(reply) = reply {
+            let _ = reply.send(take_rejection().map_or(Ok(()), Err));
+        }
+        if should_broadcast && snapshots.is_followed() {
+            let status = status_snapshot(
                 active_workspace_id,
                 focused_slot,
                 &present_workspace_ids,
+                &monitors,
                 &state,
+                &thumbnails,
+                backend_healthy,
             );
+            snapshots.send(&status)?;
         }
     }
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::slot_to_monitor_pos;
-
-    #[test]
-    fn slot_to_monitor_position_is_one_based() {
-        assert_eq!(slot_to_monitor_pos(1), Some(0));
-        assert_eq!(slot_to_monitor_pos(3), Some(2));
-    }
-
-    #[test]
-    fn slot_zero_is_invalid() {
-        assert_eq!(slot_to_monitor_pos(0), None);
+    tracing::info!("Shutting down: {}", shutdown_reason.describe());
+    persist_runtime_state(&state);
+    if let Some(command) = &config.hooks.shutdown {
+        hooks::run_shell(
+            command,
+            &[(
+                "HYWOMA_SHUTDOWN_REASON",
+                shutdown_reason.describe().to_string(),
+            )],
+            SHUTDOWN_HOOK_TIMEOUT,
+        );
     }
+    Ok(shutdown_reason)
 }
 
-fn get_command_socket_path() -> Result<PathBuf> {
+pub fn get_command_socket_path() -> Result<PathBuf> {
+    if let Some(path) = Config::command_socket() {
+        return Ok(path);
+    }
     let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")?;
     let path = PathBuf::from(xdg_runtime_dir).join(COMMAND_SOCKET);
     Ok(path)
@@ -1027,99 +4085,106 @@ fn get_event_socket_path() -> Result<PathBuf> {
     Ok(path)
 }
 
-// processes incoming connections synchronously, so the clients must open connection, send command and close the connection
-fn command_reader(tx: mpsc::Sender<Message>) -> Result<()> {
-    let path = get_command_socket_path()?;
-    let _ = fs::remove_file(&path);
-
-    let listener = UnixListener::bind(path)?;
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                let mut reader = BufReader::new(stream);
-                let mut buf = Vec::<u8>::new();
-                reader.read_to_end(&mut buf)?;
-                let command: Vec<String> = bincode::deserialize(&buf)?;
-                println!("Received command: {command:?}");
-                if is_status_command(&command) {
-                    let (response_tx, response_rx) = mpsc::channel();
-                    tx.send(Message::Status(response_tx))?;
-                    let response = response_rx.recv()?;
-                    write_status_response(reader.into_inner(), &response);
-                } else if matches!(command.as_slice(), [cmd] if cmd == "tmp-slots") {
-                    let (response_tx, response_rx) = mpsc::channel();
-                    tx.send(Message::TmpSlots(response_tx))?;
-                    let response = response_rx.recv()?;
-                    write_status_response(reader.into_inner(), &response);
-                } else if matches!(command.as_slice(), [cmd, _] if cmd == "tmp-swap-with-slot") {
-                    let Ok(slot) = command[1].parse() else {
-                        write_status_response(
-                            reader.into_inner(),
-                            &format!("Invalid slot number: {}", command[1]),
-                        );
-                        continue;
-                    };
-                    let (response_tx, response_rx) = mpsc::channel();
-                    tx.send(Message::TmpSwapWithSlot(slot, response_tx))?;
-                    let response = response_rx.recv()?;
-                    write_status_response(reader.into_inner(), &response);
-                } else {
-                    process_command(command, &tx)?;
+fn get_state_socket_path() -> Result<PathBuf> {
+    let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")?;
+    let path = PathBuf::from(xdg_runtime_dir).join(STATE_SOCKET);
+    Ok(path)
+}
+
+// The daemon's answer, or None for commands that only report that they ran.
+// Progress of bulk operations is shown on a terminal only, scripts get the reply alone.
+pub fn send_command(token: Option<String>, command: Command) -> Result<Option<String>> {
+    let expects_response = command.expects_response();
+    let show_progress = std::io::stderr().is_terminal();
+    let response = Client::with_socket(get_command_socket_path()?).send_with_progress(
+        token,
+        command,
+        |Progress { done, total }| {
+            if show_progress {
+                eprint!("\rDispatched {done}/{total}");
+                if done == total {
+                    eprintln!();
                 }
             }
-            Err(_err) => {
-                break;
-            }
-        }
-    }
-    Ok(())
+        },
+    )?;
+    Ok(expects_response.then_some(response))
 }
 
-fn event_reader(tx: mpsc::Sender<Message>) -> Result<()> {
+pub fn stream_events() -> Result<()> {
     let path = get_event_socket_path()?;
-    let _ = fs::remove_file(&path);
-
-    let listener = UnixListener::bind(path)?;
+    let stream = UnixStream::connect(path)?;
+    copy_lines_to_stdout(stream)
+}
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                tx.send(Message::SubscribeEvents(stream))?;
-            }
-            Err(_err) => {
-                break;
-            }
-        }
-    }
-    Ok(())
+// Raw Hyprland events and focus changes are requested over the command socket, which then stays
+// open for the daemon to write lines into until the client goes away.
+pub fn stream_raw_events() -> Result<()> {
+    stream_command_lines(Command::RawEvents)
 }
 
-pub fn send_command(command: &Vec<String>) -> Result<()> {
-    let path = get_command_socket_path()?;
-    let mut stream = UnixStream::connect(path)?;
+pub fn watch() -> Result<()> {
+    stream_command_lines(Command::Watch)
+}
 
-    let serialized = bincode::serialize(command)?;
+fn stream_command_lines(command: Command) -> Result<()> {
+    copy_lines_to_stdout(open_command_stream(
+        &get_command_socket_path()?,
+        None,
+        command,
+    )?)
+}
 
-    stream.write_all(&serialized)?;
+pub fn open_command_stream(
+    path: &Path,
+    token: Option<String>,
+    command: Command,
+) -> Result<UnixStream> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.write_all(&protocol::encode(token, command)?)?;
     stream.flush()?;
     stream.shutdown(Shutdown::Write)?;
+    Ok(stream)
+}
 
-    if is_response_command(command) {
-        let mut response = String::new();
-        stream.read_to_string(&mut response)?;
-        print!("{response}");
-        return Ok(());
-    }
+pub const DEFAULT_WAYBAR_FORMAT: &str = "G{group} · M{slot} · W{workspace}";
+
+fn focus_text(event: &FocusEvent, format: &str) -> String {
+    format
+        .replace("{group_name}", &event.group_name)
+        .replace("{group}", &event.group.to_string())
+        .replace("{slot}", &event.slot.to_string())
+        .replace("{workspace}", &event.workspace.to_string())
+}
+
+// One line of a Waybar custom module with `return-type = "json"`. The class lets the bar style
+// groups differently, e.g. `#custom-hywoma.group-2`.
+fn waybar_line(event: &FocusEvent, format: &str) -> String {
+    serde_json::json!({
+        "text": focus_text(event, format),
+        "class": format!("group-{}", event.group),
+        "tooltip": format!(
+            "{} (group {}), slot {}, workspace {}",
+            event.group_name, event.group, event.slot, event.workspace
+        ),
+    })
+    .to_string()
+}
 
-    println!("Sent command to server: {command:?}");
+// Follows `watch` and prints a Waybar line for every focus change, until the daemon goes away.
+pub fn waybar(format: &str) -> Result<()> {
+    let events = Client::with_socket(get_command_socket_path()?).focus_events()?;
+    let mut stdout = std::io::stdout().lock();
+
+    for event in events {
+        writeln!(stdout, "{}", waybar_line(&event?, format))?;
+        stdout.flush()?;
+    }
 
     Ok(())
 }
 
-pub fn stream_events() -> Result<()> {
-    let path = get_event_socket_path()?;
-    let stream = UnixStream::connect(path)?;
+fn copy_lines_to_stdout(stream: UnixStream) -> Result<()> {
     let mut reader = BufReader::new(stream);
     let mut stdout = std::io::stdout().lock();
     let mut line = Vec::new();
@@ -1138,43 +4203,770 @@ pub fn stream_events() -> Result<()> {
     Ok(())
 }
 
-pub fn server() -> Result<()> {
-    println!("Server started");
+pub fn server(command_fd: Option<RawFd>) -> Result<()> {
+    // Inherited from the daemon this one replaces via `restart-server`, or from `hywoma.socket`.
+    let command_fd = command_fd.or_else(systemd::listen_fd);
+    // Never released: the lock goes away when the process does.
+    let _server_lock = match command_fd {
+        Some(_) => None,
+        None => {
+            let path = get_command_socket_path()?;
+            let lock = lock_command_socket(&path)?;
+            refuse_second_server(&path)?;
+            Some(lock)
+        }
+    };
+    tracing::info!("Server started");
+    let config = Config::load();
     let (tx, rx) = mpsc::channel::<Message>();
+    // The sockets are served from one thread however many clients follow the daemon. The main loop
+    // stays synchronous: it owns the state and waits on Hyprland for every dispatch anyway.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let reason = runtime.block_on(async move {
+        let command_listener = match command_fd {
+            Some(fd) => sockets::from_std(unsafe { UnixListener::from_raw_fd(fd) })?,
+            None => bind(&get_command_socket_path()?)?,
+        };
+        let audit_log = config.audit_log.clone();
+        let listeners = [
+            sockets::spawn_listener(
+                &tx,
+                ShutdownReason::CommandSocket,
+                sockets::serve_commands(tx.clone(), command_listener, audit_log),
+            ),
+            sockets::spawn_listener(
+                &tx,
+                ShutdownReason::EventSocket,
+                sockets::serve_events(tx.clone(), bind(&get_event_socket_path()?)?),
+            ),
+            sockets::spawn_listener(
+                &tx,
+                ShutdownReason::StateSocket,
+                sockets::serve_state(tx.clone(), bind(&get_state_socket_path()?)?),
+            ),
+        ];
+        sockets::spawn_signal_handler(&tx)?;
+        spawn_reader(&tx, ShutdownReason::HyprlandEvents, hyprland::event_reader);
+        watchdog::spawn(&config.watchdog, tx.clone());
+        edges::spawn(&config.edge_switch, tx.clone());
 
-    thread::spawn({
-        let tx = tx.clone();
-        move || {
-            if let Err(x) = hyprland::event_reader(tx) {
-                eprintln!("Hyprland socket reader returned an error: {x:?}");
-                exit(1);
-            }
+        if let Some(interval) = config
+            .reconcile
+            .interval_secs
+            .filter(|interval| *interval > 0)
+        {
+            sockets::spawn_ticker(&tx, Duration::from_secs(interval), || Message::Reconcile);
+        }
+        if !config.schedule.entries.is_empty() {
+            sockets::spawn_ticker(&tx, Duration::from_secs(schedule::TICK_SECS), || {
+                Message::ScheduleTick
+            });
         }
-    });
 
-    thread::spawn({
-        let tx = tx.clone();
-        move || {
-            if let Err(x) = command_reader(tx) {
-                eprintln!("Hywoma command socket reader returned an error: {x:?}");
-                exit(2);
-            }
+        drop(tx);
+        let reason = tokio::task::spawn_blocking(move || main_loop(rx, config))
+            .await
+            .expect("Main loop panicked");
+        // New clients get a refused connection rather than a daemon that never answers.
+        for listener in listeners {
+            listener.abort();
         }
-    });
+        remove_sockets(command_fd.is_some());
+        sockets::close_connections(CLOSE_CONNECTIONS_GRACE).await;
+        reason
+    })?;
+    exit(reason.exit_code());
+}
 
-    thread::spawn({
-        let tx = tx.clone();
-        move || {
-            if let Err(x) = event_reader(tx) {
-                eprintln!("Hywoma event socket reader returned an error: {x:?}");
-                exit(3);
+// An inherited command socket belongs to whoever created it: systemd keeps listening on it to
+// start the next daemon, and so does a `restart-server` successor.
+fn remove_sockets(inherited_command_socket: bool) {
+    let mut sockets = vec![get_event_socket_path(), get_state_socket_path()];
+    if !inherited_command_socket {
+        sockets.push(get_command_socket_path());
+    }
+    for path in sockets.into_iter().flatten() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+// Two servers started at once would both find no daemon answering and bind over each other's
+// socket. Whoever holds the lock next to it has the socket to itself until it exits.
+fn lock_command_socket(path: &Path) -> Result<fs::File> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+    match lock.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(fs::TryLockError::WouldBlock) => Err(anyhow!(
+            "Another hywoma server is already running at {path:?}. Stop it first, or replace it with `hywoma restart-server`."
+        )),
+        Err(fs::TryLockError::Error(err)) => Err(anyhow!("Failed to lock {lock_path:?}: {err:#}")),
+    }
+}
+
+// Binding over the socket of a live daemon would silently take its clients. A socket nobody listens
+// on is left over from a daemon that died, and bind() replaces it.
+fn refuse_second_server(path: &Path) -> Result<()> {
+    let err = match Client::with_socket(path).send(Command::Ping) {
+        Ok(_) => {
+            return Err(anyhow!(
+                "Another hywoma server is already running at {path:?}. Stop it first, or replace it with `hywoma restart-server`."
+            ));
+        }
+        Err(err) => err,
+    };
+    match err
+        .downcast_ref::<std::io::Error>()
+        .map(std::io::Error::kind)
+    {
+        Some(std::io::ErrorKind::NotFound) => Ok(()),
+        Some(std::io::ErrorKind::ConnectionRefused) => {
+            tracing::info!("Taking over the stale socket {path:?}");
+            Ok(())
+        }
+        // Something is there, but does not answer as a working daemon would.
+        _ => Err(anyhow!(
+            "Cannot tell whether a hywoma server runs at {path:?}: {err:#}"
+        )),
+    }
+}
+
+fn bind(path: &Path) -> Result<tokio::net::UnixListener> {
+    let _ = fs::remove_file(path);
+    Ok(tokio::net::UnixListener::bind(path)?)
+}
+
+// A reader that fails takes the daemon down through the main loop, so state is saved and sockets
+// are cleaned up. Readers that return normally have handed their sender off, like the simulator.
+fn spawn_reader<F>(tx: &mpsc::Sender<Message>, reason: ShutdownReason, reader: F)
+where
+    F: FnOnce(mpsc::Sender<Message>) -> Result<()> + Send + 'static,
+{
+    let tx = tx.clone();
+    thread::spawn(move || {
+        if let Err(err) = reader(tx.clone()) {
+            tracing::error!("{}: {err:?}", reason.describe());
+            if tx.send(Message::Shutdown(reason)).is_err() {
+                exit(reason.exit_code());
             }
         }
     });
+}
 
-    drop(tx);
-    thread::spawn(move || main_loop(rx))
-        .join()
-        .expect("Main loop panicked")?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::{
+        Compaction, DEFAULT_WAYBAR_FORMAT, FocusEvent, Message, SlotTarget, WindowTarget,
+        cycle_target, describe_group_switch_plan, describe_group_tree, describe_windows,
+        dispatch_refusal, empty_workspace, free_workspace, group_tree, leaving_workspaces,
+        matching_windows, needs_another_monitor, passthrough_request, plan_compaction,
+        presentation_slot, relative_group, relative_workspace, resolve_slot_target,
+        slot_to_monitor_pos, spare_workspace, status_snapshot, superseded_by_group_change,
+        waybar_line, windows_to_gather, windows_to_retile,
+    };
+    use crate::config::Config;
+    use crate::hyprland::{ClientInfo, MonitorInfo};
+    use crate::state::WorkspaceKey;
+    use crate::state::{Slot, State};
+    use crate::thumbnails::Thumbnails;
+    use std::collections::{HashMap, HashSet};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn slot_to_monitor_position_is_one_based() {
+        assert_eq!(slot_to_monitor_pos(1), Some(0));
+        assert_eq!(slot_to_monitor_pos(3), Some(2));
+    }
+
+    #[test]
+    fn slot_zero_is_invalid() {
+        assert_eq!(slot_to_monitor_pos(0), None);
+    }
+
+    #[test]
+    fn slot_targets_resolve_numbers_and_output_names() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        state.attach_output(2, "DP-1", 7);
+
+        assert_eq!(SlotTarget::parse("2"), SlotTarget::Slot(2));
+        assert_eq!(
+            resolve_slot_target(&state, 1, &[], &SlotTarget::parse("DP-1")),
+            Some(2)
+        );
+        assert_eq!(
+            resolve_slot_target(&state, 1, &[], &SlotTarget::parse("HDMI-A-1")),
+            None
+        );
+        assert_eq!(
+            resolve_slot_target(&state, 1, &[], &SlotTarget::Slot(0)),
+            None
+        );
+    }
+
+    #[test]
+    fn relative_slot_targets_follow_monitor_positions() {
+        let mut state = State::new([
+            Slot::new(1, "u", "left"),
+            Slot::new(2, "i", "middle"),
+            Slot::new(3, "o", "right"),
+        ]);
+        let monitor = |id, x| MonitorInfo {
+            id,
+            name: format!("DP-{id}"),
+            x,
+        };
+        // Slot 3 sits left of slot 1 and slot 2 is detached.
+        state.attach_output(3, "DP-5", 5);
+        state.attach_output(1, "DP-6", 6);
+        let monitors = [monitor(5, 0), monitor(6, 1920)];
+
+        assert_eq!(SlotTarget::parse("left"), SlotTarget::Relative(-1));
+        assert_eq!(SlotTarget::parse("+1"), SlotTarget::Relative(1));
+        assert_eq!(
+            resolve_slot_target(&state, 1, &monitors, &SlotTarget::parse("left")),
+            Some(3)
+        );
+        assert_eq!(
+            resolve_slot_target(&state, 3, &monitors, &SlotTarget::parse("+1")),
+            Some(1)
+        );
+        assert_eq!(
+            resolve_slot_target(&state, 1, &monitors, &SlotTarget::parse("right")),
+            None
+        );
+    }
+
+    #[test]
+    fn slot_commands_need_another_monitor_to_act_on() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        let monitors = [MonitorInfo {
+            id: 5,
+            name: "eDP-1".to_string(),
+            x: 0,
+        }];
+        state.attach_output(1, "eDP-1", 5);
+        let needs = |msg| needs_another_monitor(&state, 1, &monitors, &msg);
+
+        assert!(needs(Message::SelectSlot(SlotTarget::Slot(2))));
+        assert!(needs(Message::MoveToSlot(SlotTarget::Relative(1), false)));
+        assert!(needs(Message::MoveWorkspaceToSlot(
+            SlotTarget::Slot(2),
+            false
+        )));
+        assert!(needs(Message::SwapMonitors(None)));
+        assert!(!needs(Message::SelectSlot(SlotTarget::Slot(1))));
+        assert!(!needs(Message::SelectSlot(
+            SlotTarget::parse("2").clamped(true)
+        )));
+        assert!(!needs(Message::SelectWorkspace(2, None)));
+
+        state.attach_output(2, "DP-1", 6);
+        assert!(!needs_another_monitor(
+            &state,
+            1,
+            &monitors,
+            &Message::SelectSlot(SlotTarget::Slot(2))
+        ));
+    }
+
+    #[test]
+    fn mirrors_go_to_a_spare_workspace_on_the_outermost_other_monitor() {
+        let mut state = State::new([
+            Slot::new(1, "u", "left"),
+            Slot::new(2, "i", "middle"),
+            Slot::new(3, "o", "right"),
+        ]);
+        let monitor = |id, x| MonitorInfo {
+            id,
+            name: format!("DP-{id}"),
+            x,
+        };
+        state.attach_output(1, "DP-5", 5);
+        state.attach_output(2, "DP-6", 6);
+        state.attach_output(3, "DP-7", 7);
+        let monitors = [monitor(5, 0), monitor(6, 1920), monitor(7, 3840)];
+
+        assert_eq!(presentation_slot(&state, 1, &monitors, None), Some(3));
+        assert_eq!(presentation_slot(&state, 3, &monitors, None), Some(1));
+        assert_eq!(
+            presentation_slot(&state, 1, &monitors, Some(&SlotTarget::parse("DP-6"))),
+            Some(2)
+        );
+
+        let count = state.workspace_count(3);
+        assert_eq!(spare_workspace(&state, 3), count + 1);
+        state.workspace_id_for(state.active_group, 3, count + 1);
+        assert_eq!(spare_workspace(&state, 3), count + 2);
+    }
+
+    #[test]
+    fn whole_workspaces_keep_their_number_on_the_other_slot_when_it_is_free() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "right")]);
+        let group = state.active_group;
+        let second = state.workspace_id_for(group, 2, 2);
+        let first = state.workspace_id_for(group, 2, 1);
+        let mut window_counts = HashMap::from([(first, 0)]);
+
+        assert_eq!(free_workspace(&state, 2, 2, &window_counts), Some(2));
+        window_counts.insert(second, 3);
+        assert_eq!(free_workspace(&state, 2, 2, &window_counts), Some(1));
+        window_counts.insert(first, 1);
+        assert_eq!(free_workspace(&state, 2, 2, &window_counts), Some(3));
+        let count = state.workspace_count(2);
+        for visible in 3..=count {
+            window_counts.insert(state.workspace_id_for(group, 2, visible), 1);
+        }
+        assert_eq!(free_workspace(&state, 2, 2, &window_counts), None);
+    }
+
+    #[test]
+    fn clamped_slot_targets_fall_back_to_the_nearest_attached_monitor() {
+        let mut state = State::new([
+            Slot::new(1, "u", "left"),
+            Slot::new(2, "i", "middle"),
+            Slot::new(3, "o", "right"),
+        ]);
+        let monitor = |id, x| MonitorInfo {
+            id,
+            name: format!("DP-{id}"),
+            x,
+        };
+        state.attach_output(1, "DP-5", 5);
+        state.attach_output(2, "DP-6", 6);
+        let monitors = [monitor(5, 0), monitor(6, 1920)];
+        let resolve = |target: &str| {
+            resolve_slot_target(
+                &state,
+                1,
+                &monitors,
+                &SlotTarget::parse(target).clamped(true),
+            )
+        };
+
+        assert_eq!(resolve("3"), Some(2));
+        assert_eq!(resolve("7"), Some(2));
+        assert_eq!(resolve("0"), Some(1));
+        assert_eq!(resolve("-1"), Some(1));
+        assert_eq!(resolve("+5"), Some(2));
+        assert_eq!(
+            resolve_slot_target(&state, 1, &monitors, &SlotTarget::parse("+5")),
+            None
+        );
+    }
+
+    #[test]
+    fn a_group_switch_loses_to_one_handled_after_it_arrived() {
+        let earlier = Instant::now();
+        let arrived = earlier + Duration::from_millis(5);
+        let changed = Some(arrived + Duration::from_millis(5));
+        let superseded = |msg, changed_at| superseded_by_group_change(&msg, arrived, changed_at, 1);
+
+        assert!(!superseded(Message::SwitchGroup(2), None));
+        assert!(!superseded(Message::SwitchGroup(2), Some(earlier)));
+        assert!(superseded(Message::SwitchGroup(2), changed));
+        assert!(!superseded(Message::SwitchGroup(1), changed));
+        assert!(!superseded(Message::SwitchGroupRelative(1), changed));
+        assert!(superseded(Message::LeaveSubgroup, changed));
+    }
+
+    #[test]
+    fn relative_groups_wrap_around() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        state.ensure_group(1, "Work");
+        state.ensure_group(4, "Games");
+
+        assert_eq!(relative_group(&mut state, 1, None), 1);
+        assert_eq!(relative_group(&mut state, -1, None), 4);
+        state.switch_group(4);
+        assert_eq!(relative_group(&mut state, 1, None), 0);
+        assert_eq!(relative_group(&mut state, 1, Some(6)), 5);
+        assert!(state.has_group(5));
+        assert_eq!(relative_group(&mut state, 2, Some(6)), 0);
+
+        let sub = state.find_or_create_subgroup(1, None);
+        state.switch_group(sub);
+        assert_eq!(relative_group(&mut state, 1, None), 4);
+    }
+
+    #[test]
+    fn relative_workspaces_wrap_only_when_asked_to() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+
+        assert_eq!(relative_workspace(&state, 1, -1, true), Some(10));
+        assert_eq!(relative_workspace(&state, 1, -1, false), None);
+        assert_eq!(relative_workspace(&state, 1, 1, false), Some(2));
+        state.set_active_visible(1, 10);
+        assert_eq!(relative_workspace(&state, 1, 1, true), Some(1));
+        assert_eq!(relative_workspace(&state, 1, 1, false), None);
+    }
+
+    #[test]
+    fn full_slots_overflow_and_compact_back() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        state.set_slot_defaults(1, None, Some(3));
+        let mut window_counts: HashMap<u64, u64> = (1..=3)
+            .map(|visible| (state.workspace_id_for(0, 1, visible), 1))
+            .collect();
+
+        assert_eq!(empty_workspace(&state, 1, &window_counts), 4);
+        let overflow = state.workspace_id_for(0, 1, 4);
+        window_counts.insert(overflow, 2);
+        assert_eq!(empty_workspace(&state, 1, &window_counts), 5);
+
+        let key = |visible| WorkspaceKey {
+            group: 0,
+            slot: 1,
+            visible,
+        };
+        assert_eq!(
+            plan_compaction(&state, &window_counts, &HashSet::new()),
+            vec![]
+        );
+        window_counts.insert(state.workspace_id_for(0, 1, 2), 0);
+        state.workspace_id_for(0, 1, 5);
+        assert_eq!(
+            plan_compaction(&state, &window_counts, &HashSet::new()),
+            vec![
+                Compaction {
+                    from: key(4),
+                    to: Some(2),
+                },
+                Compaction {
+                    from: key(5),
+                    to: None,
+                },
+            ]
+        );
+        assert_eq!(
+            plan_compaction(&state, &window_counts, &HashSet::from([overflow])),
+            vec![Compaction {
+                from: key(5),
+                to: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn status_names_the_focused_workspace_and_monitors() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        state.attach_output(2, "DP-2", 4);
+        let workspace_id = state.workspace_id_for(0, 2, 3);
+        let monitors = [MonitorInfo {
+            id: 4,
+            name: "DP-2".to_string(),
+            x: 0,
+        }];
+
+        let status = status_snapshot(
+            workspace_id,
+            2,
+            &HashSet::from([workspace_id]),
+            &monitors,
+            &state,
+            &Thumbnails::default(),
+            true,
+        );
+
+        assert_eq!(
+            status.active_workspace,
+            Some(WorkspaceKey {
+                group: 0,
+                slot: 2,
+                visible: 3,
+            })
+        );
+        assert_eq!(status.focused_monitor_id, Some(4));
+        assert_eq!(status.monitor_ids, vec![4]);
+    }
+
+    #[test]
+    fn window_targets_accept_hyprland_address_forms() {
+        let address = WindowTarget::Address("55d1c0a0".to_string());
+
+        assert_eq!(WindowTarget::parse("focused"), WindowTarget::Focused);
+        assert_eq!(WindowTarget::parse("55d1c0a0"), address);
+        assert_eq!(WindowTarget::parse("0x55d1c0a0"), address);
+        assert_eq!(WindowTarget::parse("address:0x55d1c0a0"), address);
+    }
+
+    #[test]
+    fn group_switches_leave_every_attached_workspace() {
+        let mut state = State::new([
+            Slot::new(1, "u", "left"),
+            Slot::new(2, "i", "middle"),
+            Slot::new(3, "o", "right"),
+        ]);
+        state.attach_output(1, "DP-1", 10);
+        state.attach_output(2, "DP-2", 20);
+        let left = state.workspace_id_for(0, 1, 1);
+        let middle = state.workspace_id_for(0, 2, 1);
+
+        assert_eq!(
+            leaving_workspaces(&state, 2, &Message::SelectWorkspace(3, None)),
+            vec![(middle, "DP-2".to_string())]
+        );
+        let mut leaving = leaving_workspaces(&state, 2, &Message::SwitchGroup(1));
+        leaving.sort_unstable();
+        assert_eq!(
+            leaving,
+            vec![(left, "DP-1".to_string()), (middle, "DP-2".to_string())]
+        );
+        assert_eq!(
+            leaving_workspaces(
+                &state,
+                2,
+                &Message::SelectWorkspace(3, Some(SlotTarget::parse("DP-1")))
+            ),
+            vec![(left, "DP-1".to_string())]
+        );
+        assert_eq!(
+            leaving_workspaces(&state, 2, &Message::MoveToWorkspace(3, None, false)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn group_switch_plan_leaves_the_state_alone() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        state.attach_output(1, "DP-1", 10);
+        state.attach_output(2, "DP-2", 20);
+        let work = state.create_group("Work");
+        let before = state.clone();
+
+        assert_eq!(
+            describe_group_switch_plan(&state, 1, work),
+            "dispatch focusmonitor 20\n\
+             dispatch workspace 1021\n\
+             dispatch focusmonitor 10\n\
+             dispatch workspace 1022\n\
+             \n\
+             DP-1 (slot 1): workspace 1022 (group 1, workspace 1)\n\
+             DP-2 (slot 2): workspace 1021 (group 1, workspace 1)"
+        );
+        assert_eq!(state, before);
+        assert_eq!(
+            describe_group_switch_plan(&state, 1, 9),
+            "Unknown workspace group 9"
+        );
+    }
+
+    #[test]
+    fn where_lists_matching_windows_by_logical_workspace() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        let work = state.create_group("Work");
+        let window = |class: &str, title: &str, workspace_id| ClientInfo {
+            address: "55d1c0a0".to_string(),
+            class: class.to_string(),
+            title: title.to_string(),
+            workspace_id,
+            pid: 1,
+            floating: false,
+        };
+        let clients = [
+            window("firefox", "Inbox", state.workspace_id_for(0, 1, 1)),
+            window("zoom", "Zoom Meeting", state.workspace_id_for(work, 2, 3)),
+            window("kitty", "zoom notes", 5),
+        ];
+
+        assert_eq!(
+            describe_windows(&state, &matching_windows(&clients, "ZOOM")),
+            "zoom \"Zoom Meeting\": Work, middle monitor, workspace 3\n\
+             kitty \"zoom notes\": unmanaged workspace 5"
+        );
+        assert_eq!(
+            describe_windows(&state, &matching_windows(&clients, "slack")),
+            "No matching windows"
+        );
+    }
+
+    #[test]
+    fn group_tree_nests_monitors_workspaces_and_windows() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        let work = state.create_group("Work");
+        state.slots.get_mut(&1).unwrap().attached_output = Some("DP-1".to_string());
+        let clients = [ClientInfo {
+            address: "55d1c0a0".to_string(),
+            class: "kitty".to_string(),
+            title: "notes".to_string(),
+            workspace_id: state.workspace_id_for(work, 1, 3),
+            pid: 1,
+            floating: false,
+        }];
+
+        assert_eq!(
+            describe_group_tree(&group_tree(&state, None)),
+            "Main (group 0, active)\n\
+             Work (group 1)"
+        );
+        assert_eq!(
+            describe_group_tree(&group_tree(&state, Some(&clients))[1..]),
+            "Work (group 1)\n  \
+             left monitor (slot 1, DP-1)\n    \
+             workspace 1 (0 window(s), current)\n    \
+             workspace 3 (1 window(s))\n      \
+             kitty \"notes\" 0x55d1c0a0\n  \
+             middle monitor (slot 2, detached)\n    \
+             workspace 1 (0 window(s), current)"
+        );
+    }
+
+    #[test]
+    fn cycle_window_walks_the_active_group_across_slots() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        let work = state.create_group("Work");
+        let window = |address: &str, workspace_id| ClientInfo {
+            address: address.to_string(),
+            class: "kitty".to_string(),
+            title: String::new(),
+            workspace_id,
+            pid: 1,
+            floating: false,
+        };
+        let clients = [
+            window("a", state.workspace_id_for(0, 2, 1)),
+            window("b", state.workspace_id_for(work, 1, 1)),
+            window("c", state.workspace_id_for(0, 1, 3)),
+            window("d", state.workspace_id_for(0, 1, 1)),
+            window("e", 5),
+        ];
+        let next = |current, reverse| {
+            cycle_target(&state, &clients, current, reverse).map(|window| window.address.as_str())
+        };
+
+        assert_eq!(next(Some("d"), false), Some("c"));
+        assert_eq!(next(Some("c"), false), Some("a"));
+        assert_eq!(next(Some("a"), false), Some("d"));
+        assert_eq!(next(Some("d"), true), Some("a"));
+        assert_eq!(next(Some("e"), false), Some("d"));
+        assert_eq!(next(None, true), Some("a"));
+        state.switch_group(work);
+        assert_eq!(
+            cycle_target(&state, &clients, Some("b"), false).map(|window| window.address.as_str()),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn retile_skips_other_groups_and_kept_floating_classes() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        let work = state.create_group("Work");
+        let mut config = Config::default();
+        config.retile.keep_floating.push("pavucontrol".to_string());
+        let window = |address: &str, class: &str, workspace_id, floating| ClientInfo {
+            address: address.to_string(),
+            class: class.to_string(),
+            title: String::new(),
+            workspace_id,
+            pid: 1,
+            floating,
+        };
+        let clients = [
+            window("a", "kitty", state.workspace_id_for(0, 1, 1), true),
+            window("b", "kitty", state.workspace_id_for(0, 1, 2), false),
+            window("c", "pavucontrol", state.workspace_id_for(0, 1, 1), true),
+            window("d", "kitty", state.workspace_id_for(work, 1, 1), true),
+        ];
+        let addresses = |all| {
+            windows_to_retile(&config, &state, &clients, all)
+                .iter()
+                .map(|window| window.address.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(addresses(false), vec!["a"]);
+        assert_eq!(addresses(true), vec!["a", "d"]);
+    }
+
+    #[test]
+    fn gather_takes_the_active_groups_other_windows_but_not_locked_ones() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "right")]);
+        let work = state.create_group("Work");
+        let window = |address: &str, workspace_id| ClientInfo {
+            address: address.to_string(),
+            class: "kitty".to_string(),
+            title: String::new(),
+            workspace_id,
+            pid: 1,
+            floating: false,
+        };
+        let target = state.workspace_id_for(0, 1, 1);
+        let clients = [
+            window("a", target),
+            window("b", state.workspace_id_for(0, 1, 2)),
+            window("c", state.workspace_id_for(0, 2, 3)),
+            window("d", state.workspace_id_for(work, 1, 2)),
+            window("e", state.workspace_id_for(0, 1, 4)),
+            window("f", 9999),
+        ];
+        let locked_windows = HashMap::from([("e".to_string(), clients[4].workspace_id)]);
+
+        let addresses: Vec<&str> = windows_to_gather(&state, &clients, target, &locked_windows)
+            .iter()
+            .map(|window| window.address.as_str())
+            .collect();
+        assert_eq!(addresses, ["b", "c"]);
+    }
+
+    #[test]
+    fn waybar_lines_fill_in_the_focus() {
+        let event = FocusEvent {
+            group: 2,
+            group_name: "Work".to_string(),
+            slot: 1,
+            monitor_id: Some(0),
+            workspace: 4,
+            workspace_id: Some(1023),
+        };
+
+        let line: serde_json::Value =
+            serde_json::from_str(&waybar_line(&event, DEFAULT_WAYBAR_FORMAT)).unwrap();
+        assert_eq!(line["text"], "G2 · M1 · W4");
+        assert_eq!(line["class"], "group-2");
+        assert_eq!(line["tooltip"], "Work (group 2), slot 1, workspace 4");
+
+        let line: serde_json::Value =
+            serde_json::from_str(&waybar_line(&event, "{group_name}:{workspace}")).unwrap();
+        assert_eq!(line["text"], "Work:4");
+    }
+
+    #[test]
+    fn passes_hyprctl_requests_on_unless_they_bypass_hywoma() {
+        let args =
+            |line: &str| -> Vec<String> { line.split_whitespace().map(String::from).collect() };
+
+        assert_eq!(
+            passthrough_request(&args("-j clients"), false).unwrap(),
+            "-j/clients"
+        );
+        assert_eq!(
+            passthrough_request(&args("dispatch movefocus l"), false).unwrap(),
+            "dispatch movefocus l"
+        );
+        assert_eq!(
+            passthrough_request(&args("dispatch resizeactive -10 0"), false).unwrap(),
+            "dispatch resizeactive -10 0"
+        );
+        assert!(passthrough_request(&args("dispatch exit"), false).is_err());
+        assert!(passthrough_request(&args("dispatch workspace 3"), false).is_err());
+        assert_eq!(
+            passthrough_request(&args("dispatch workspace 3"), true).unwrap(),
+            "dispatch workspace 3"
+        );
+        assert!(passthrough_request(&args("-i 1 clients"), false).is_err());
+        assert!(passthrough_request(&args("-j"), false).is_err());
+    }
+
+    #[test]
+    fn unknown_commands_are_not_dispatched_when_they_bypass_hywoma() {
+        assert!(dispatch_refusal("exit").is_some());
+        assert!(dispatch_refusal("movetoworkspacesilent").is_some());
+        assert_eq!(dispatch_refusal("togglefloating"), None);
+    }
 }
@@ -0,0 +1,135 @@
+use anyhow::{Result, anyhow};
+use std::collections::HashSet;
+
+use crate::config::Config;
+use crate::hyprland;
+use crate::state::{ArchivedWindow, GroupId, State};
+
+// Parking an inactive project: its windows wait on a special workspace of their own, out of every
+// regular workspace, and go back to where they were on `group restore`. The special workspace can
+// still be peeked at with `togglespecialworkspace hywoma-archive-<n>`.
+fn parking_workspace(group: GroupId) -> String {
+    format!("special:hywoma-archive-{group}")
+}
+
+pub fn archive(config: &Config, state: &mut State, group: GroupId) -> Result<usize> {
+    if !state.has_group(group) {
+        return Err(anyhow!("Unknown workspace group {group}"));
+    }
+    if group == state.active_group {
+        return Err(anyhow!("Cannot archive the active group {group}"));
+    }
+
+    let mut parked = Vec::new();
+    let mut dispatches = Vec::new();
+    for client in hyprland::get_clients()? {
+        let Some(key) = state
+            .key_for_workspace_id(client.workspace_id)
+            .filter(|key| key.group == group)
+        else {
+            continue;
+        };
+        dispatches.push(format!(
+            "dispatch movetoworkspacesilent {},address:0x{}",
+            parking_workspace(group),
+            client.address
+        ));
+        parked.push(ArchivedWindow {
+            address: client.address,
+            class: client.class,
+            title: client.title,
+            slot: key.slot,
+            visible: key.visible,
+        });
+    }
+    hyprland::hyprctl_batch(config, &dispatches)?;
+
+    let count = parked.len();
+    if let Some(entry) = state.groups.get_mut(&group) {
+        entry.archived.extend(parked);
+    }
+    Ok(count)
+}
+
+// Splits the archive into the dispatches bringing live windows back and the windows closed since.
+fn restore_plan(
+    state: &mut State,
+    group: GroupId,
+    archived: Vec<ArchivedWindow>,
+    live: &HashSet<String>,
+) -> (Vec<String>, Vec<ArchivedWindow>) {
+    let mut dispatches = Vec::new();
+    let mut closed = Vec::new();
+    for window in archived {
+        if !live.contains(&window.address) {
+            closed.push(window);
+            continue;
+        }
+        let workspace_id = state.workspace_id_for(group, window.slot, window.visible);
+        dispatches.push(format!(
+            "dispatch movetoworkspacesilent {workspace_id},address:0x{}",
+            window.address
+        ));
+    }
+    (dispatches, closed)
+}
+
+pub fn restore(config: &Config, state: &mut State, group: GroupId) -> Result<usize> {
+    let Some(entry) = state.groups.get_mut(&group) else {
+        return Err(anyhow!("Unknown workspace group {group}"));
+    };
+    if entry.archived.is_empty() {
+        return Err(anyhow!("Group {group} has no archived windows"));
+    }
+    let live = hyprland::get_window_addresses()?;
+    let archived = std::mem::take(&mut entry.archived);
+    let (dispatches, closed) = restore_plan(state, group, archived, &live);
+    for window in &closed {
+        tracing::info!(
+            "Archived window {:?} ({}) was closed in the meantime",
+            window.title,
+            window.class
+        );
+    }
+    hyprland::hyprctl_batch(config, &dispatches)?;
+    Ok(dispatches.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Slot;
+
+    fn archived(address: &str, visible: u64) -> ArchivedWindow {
+        ArchivedWindow {
+            address: address.to_string(),
+            class: "kitty".to_string(),
+            title: "notes".to_string(),
+            slot: 1,
+            visible,
+        }
+    }
+
+    #[test]
+    fn restores_live_windows_to_their_logical_workspace() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        state.ensure_group(1, "Work");
+        let live = HashSet::from(["a".to_string()]);
+
+        let (dispatches, closed) = restore_plan(
+            &mut state,
+            1,
+            vec![archived("a", 3), archived("b", 1)],
+            &live,
+        );
+
+        let workspace_id = state.workspace_id_for(1, 1, 3);
+        assert_eq!(
+            dispatches,
+            vec![format!(
+                "dispatch movetoworkspacesilent {workspace_id},address:0xa"
+            )]
+        );
+        assert_eq!(closed, vec![archived("b", 1)]);
+    }
+}
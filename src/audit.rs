@@ -0,0 +1,116 @@
+use serde::Serialize;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use crate::protocol::Command;
+use crate::session;
+
+// Who sent a command. The pid is the `hywoma` client itself, so the parent's command line is what
+// names the script or keybind behind it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Peer {
+    pub pid: i32,
+    pub uid: u32,
+    pub parent: Option<String>,
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid {} (uid {}", self.pid, self.uid)?;
+        if let Some(parent) = &self.parent {
+            write!(f, ", parent {parent:?}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Must run right after accepting: the client exits as soon as its command is written, and its
+// parent can only be looked up while it is alive.
+pub fn peer(stream: &impl AsRawFd) -> Option<Peer> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: cred and len describe a buffer of the size SO_PEERCRED writes.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result == -1 {
+        tracing::warn!(
+            "Failed to get the command sender: {:?}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+    let parent = fs::read_to_string(format!("/proc/{}/status", cred.pid))
+        .ok()
+        .and_then(|status| parent_pid(&status))
+        .and_then(session::command_line);
+    Some(Peer {
+        pid: cred.pid,
+        uid: cred.uid,
+        parent,
+    })
+}
+
+fn parent_pid(status: &str) -> Option<i32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("PPid:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    at: String,
+    #[serde(flatten)]
+    peer: Option<&'a Peer>,
+    command: &'a Command,
+}
+
+// One JSON object per line, for `jq` or `grep`.
+pub fn record(path: &Path, peer: Option<&Peer>, command: &Command) {
+    let entry = Entry {
+        at: chrono::Local::now().to_rfc3339(),
+        peer,
+        command,
+    };
+    let written = serde_json::to_string(&entry)
+        .map_err(anyhow::Error::from)
+        .and_then(|line| {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{line}")?;
+            Ok(())
+        });
+    if let Err(err) = written {
+        tracing::warn!("Failed to write audit log {path:?}: {err:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn identifies_the_sender_and_its_parent() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let peer = peer(&server).unwrap();
+        drop(client);
+
+        assert_eq!(peer.pid, std::process::id() as i32);
+        assert_eq!(peer.uid, unsafe { libc::getuid() });
+        assert!(peer.parent.is_some());
+        assert_eq!(parent_pid("Name:\tbash\nPid:\t20\nPPid:\t7\n"), Some(7));
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{Result, anyhow, bail};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The command socket is owned by systemd rather than the daemon. Keybinds pressed while the daemon
+// starts (or restarts after a crash) queue up in the socket instead of failing, and the daemon
+// picks the listener up through LISTEN_FDS. It reports ready once it follows Hyprland's events.
+fn socket_unit() -> String {
+    "[Unit]
+Description=hywoma command socket
+PartOf=graphical-session.target
+
+[Socket]
+ListenStream=%t/.hywoma-commands.sock
+SocketMode=0600
+
+[Install]
+WantedBy=sockets.target
+"
+    .to_string()
+}
+
+// hywoma talks to the Hyprland instance named by HYPRLAND_INSTANCE_SIGNATURE, which systemd only
+// knows once the session imported it. The daemon exits when its Hyprland goes away, so restarting
+// on failure follows a new instance as soon as its environment is imported.
+fn service_unit(executable: &Path) -> String {
+    format!(
+        "[Unit]
+Description=hywoma Hyprland workspace manager
+PartOf=graphical-session.target
+After=graphical-session.target
+Requires=hywoma.socket
+ConditionEnvironment=HYPRLAND_INSTANCE_SIGNATURE
+
+[Service]
+Type=notify
+ExecStart={} server
+Restart=on-failure
+RestartSec=1
+
+[Install]
+WantedBy=graphical-session.target
+",
+        executable.display()
+    )
+}
+
+fn systemd_user_dir() -> Result<PathBuf> {
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map_err(|_| anyhow!("Neither XDG_CONFIG_HOME nor HOME is set"))?;
+    Ok(config_home.join("systemd").join("user"))
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()?;
+    if !status.success() {
+        bail!("systemctl --user {} exited with {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+pub fn install_systemd() -> Result<()> {
+    let executable = env::current_exe()?;
+    let dir = systemd_user_dir()?;
+    fs::create_dir_all(&dir)?;
+    let socket_path = dir.join("hywoma.socket");
+    let service_path = dir.join("hywoma.service");
+    fs::write(&socket_path, socket_unit())?;
+    fs::write(&service_path, service_unit(&executable))?;
+    println!("Wrote {}", socket_path.display());
+    println!("Wrote {}", service_path.display());
+
+    systemctl(&["daemon-reload"])?;
+    systemctl(&["enable", "--now", "hywoma.socket"])?;
+    println!("Enabled hywoma.socket");
+    println!();
+    println!("Add this to hyprland.conf so every Hyprland instance gets its own daemon:");
+    println!("{}", exec_once_systemd_line());
+    Ok(())
+}
+
+// Restarting, not starting: a daemon left from the previous Hyprland instance must not keep
+// serving commands against a compositor that is gone.
+fn exec_once_systemd_line() -> String {
+    "exec-once = systemctl --user import-environment HYPRLAND_INSTANCE_SIGNATURE WAYLAND_DISPLAY && systemctl --user restart hywoma.service".to_string()
+}
+
+// Without systemd the daemon binds the socket itself, replacing one left by an earlier instance.
+// The daemon of that instance exits once its Hyprland is gone, so nothing else needs to run.
+pub fn exec_once_line() -> Result<String> {
+    Ok(format!(
+        "exec-once = {} server",
+        env::current_exe()?.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_takes_the_socket_from_systemd() {
+        let unit = service_unit(Path::new("/usr/bin/hywoma"));
+
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains("ExecStart=/usr/bin/hywoma server\n"));
+        assert!(unit.contains("Requires=hywoma.socket\n"));
+        assert!(socket_unit().contains("ListenStream=%t/.hywoma-commands.sock\n"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::config::Config;
+use crate::hyprland::{self, WorkspaceInfo};
+use crate::state::State;
+
+// For bars that know nothing but Hyprland's workspace names: every managed workspace is renamed
+// after `workspace_badges`, e.g. "{name} ({windows})", whenever its window count changes.
+#[derive(Debug, Default)]
+pub struct Badges {
+    // Whether names carry badges, so turning them off by switching profiles takes them off again.
+    shown: bool,
+}
+
+impl Badges {
+    pub fn update(&mut self, config: &Config, state: &State) {
+        let format = config.workspace_badges.as_deref();
+        if format.is_none() && !self.shown {
+            return;
+        }
+        let workspaces = match hyprland::get_workspaces() {
+            Ok(workspaces) => workspaces,
+            Err(err) => {
+                tracing::warn!("Failed to query workspaces for badges: {err:?}");
+                return;
+            }
+        };
+        self.shown = format.is_some();
+        if let Err(err) = hyprland::hyprctl_batch(config, &plan(format, state, &workspaces)) {
+            tracing::warn!("Failed to update workspace badges: {err:?}");
+        }
+    }
+}
+
+// Renames for the workspaces whose Hyprland name is not what it should be, so an unchanged count
+// costs nothing but the query.
+fn plan(format: Option<&str>, state: &State, workspaces: &[WorkspaceInfo]) -> Vec<String> {
+    workspaces
+        .iter()
+        .filter(|workspace| !state.is_unmanaged(workspace.id))
+        .filter_map(|workspace| {
+            let key = state.key_for_workspace_id(workspace.id)?;
+            // The name `rename_workspace` would give it.
+            let name = state
+                .workspace_label(key)
+                .map_or_else(|| workspace.id.to_string(), str::to_string);
+            let name = match format {
+                Some(format) => format
+                    .replace("{name}", &name)
+                    .replace("{group}", &key.group.to_string())
+                    .replace(
+                        "{group_name}",
+                        state
+                            .groups
+                            .get(&key.group)
+                            .map_or("", |group| group.name.as_str()),
+                    )
+                    .replace("{workspace}", &key.visible.to_string())
+                    .replace("{windows}", &workspace.windows.to_string()),
+                None => name,
+            };
+            (name != workspace.name)
+                .then(|| format!("dispatch renameworkspace {} {name}", workspace.id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Slot;
+
+    fn workspace(id: u64, name: &str, windows: u64) -> WorkspaceInfo {
+        WorkspaceInfo {
+            id,
+            name: name.to_string(),
+            windows,
+        }
+    }
+
+    #[test]
+    fn renames_managed_workspaces_whose_badge_is_out_of_date() {
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        let work = state.create_group("Work");
+        let first = state.workspace_id_for(work, 1, 3);
+        let second = state.workspace_id_for(work, 1, 4);
+        state
+            .groups
+            .get_mut(&work)
+            .unwrap()
+            .set_workspace_label(1, 4, Some("mail".to_string()));
+        let workspaces = [
+            workspace(first, &format!("G{work}:W3 (1)"), 4),
+            workspace(second, "mail (2)", 2),
+            workspace(7, "7", 1),
+        ];
+
+        assert_eq!(
+            plan(
+                Some("G{group}:W{workspace} ({windows})"),
+                &state,
+                &workspaces
+            ),
+            [
+                format!("dispatch renameworkspace {first} G{work}:W3 (4)"),
+                format!("dispatch renameworkspace {second} G{work}:W4 (2)"),
+            ]
+        );
+        assert_eq!(
+            plan(Some("{name} ({windows})"), &state, &workspaces),
+            [format!("dispatch renameworkspace {first} {first} (4)")]
+        );
+        // Without badges, names go back to what they were.
+        assert_eq!(
+            plan(None, &state, &workspaces),
+            [
+                format!("dispatch renameworkspace {first} {first}"),
+                format!("dispatch renameworkspace {second} mail"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,821 @@
+use anyhow::{Result, bail};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::convert::Infallible;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+use crate::app::{SlotTarget, WindowTarget};
+use crate::focus;
+use crate::protocol::Command;
+use crate::rules::{Rule, RuleRequest};
+use crate::simulate::Layout;
+use crate::state::{GroupId, SlotId, VisibleWorkspace};
+
+// Subcommands are kebab-case like `restart-server`. Most of them started out snake_case, and the
+// aliases keep keybinds written back then working.
+#[derive(Debug, Parser)]
+#[command(
+    name = "hywoma",
+    version,
+    about = "Workspace groups for Hyprland",
+    arg_required_else_help = true
+)]
+pub struct Cli {
+    // Global, so they can be appended to an existing `hywoma server` exec-once line.
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILTER",
+        help = "Level or RUST_LOG-style directives like hywoma=debug, overrides RUST_LOG"
+    )]
+    pub log_level: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Ignore this command if one with the same token arrived just before"
+    )]
+    pub token: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Switch groups even during a focus session"
+    )]
+    pub force: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Print the outcome of a daemon command as one JSON object with ok, data and error"
+    )]
+    pub json: bool,
+    #[command(subcommand)]
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Action {
+    #[command(about = "Run the daemon")]
+    Server {
+        // Use a separate XDG_RUNTIME_DIR for the server and its clients when a live hywoma daemon
+        // is running, they share socket names.
+        #[arg(
+            long,
+            value_name = "COLUMNSxROWS",
+            value_parser = Layout::parse,
+            help = "Run against an in-memory compositor"
+        )]
+        simulate: Option<Layout>,
+        // Internal: passed by `hywoma restart-server` to the daemon taking over.
+        #[arg(long, hide = true)]
+        command_fd: Option<RawFd>,
+    },
+    #[command(about = "Start the daemon with every Hyprland session")]
+    InstallAutostart {
+        #[arg(
+            long,
+            required_unless_present = "exec_once",
+            conflicts_with = "exec_once"
+        )]
+        systemd: bool,
+        #[arg(long, help = "Print a hyprland.conf line instead")]
+        exec_once: bool,
+    },
+    #[command(about = "Print the focus line on every change")]
+    Watch,
+    #[command(about = "Print lines for a Waybar custom module")]
+    Waybar { format: Option<String> },
+    #[command(about = "Stream state change events")]
+    Events {
+        #[arg(long, help = "Relay Hyprland's own events")]
+        raw: bool,
+    },
+    // Generated from the definitions here, so they list every subcommand and flag as built.
+    #[command(about = "Print a shell completion script")]
+    Completions { shell: Shell },
+    #[command(flatten)]
+    Client(ClientCommand),
+    // Anything else goes to the daemon as it was typed: `unknown_commands = "dispatch"` turns
+    // `hywoma movefocus l` into `hyprctl dispatch movefocus l`. Last, so it only gets what no other
+    // subcommand claims.
+    #[command(external_subcommand)]
+    Unknown(Vec<String>),
+}
+
+// An absolute visible workspace, or a step from the focused one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkspaceArg {
+    Number(VisibleWorkspace),
+    Next,
+    Prev,
+}
+
+fn workspace_arg(arg: &str) -> Result<WorkspaceArg, String> {
+    match arg {
+        "next" => Ok(WorkspaceArg::Next),
+        "prev" => Ok(WorkspaceArg::Prev),
+        _ => arg
+            .parse()
+            .map(WorkspaceArg::Number)
+            .map_err(|_| "expected a workspace number, next or prev".to_string()),
+    }
+}
+
+const SLOT_HELP: &str = "Slot number, output name, left, right, or +N/-N from the focused slot";
+
+fn slot_target(arg: &str) -> Result<SlotTarget, Infallible> {
+    Ok(SlotTarget::parse(arg))
+}
+
+const GROUP_HELP: &str = "Group number or name";
+
+// A group by number, or by the name it was created or configured with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupArg {
+    Id(GroupId),
+    Name(String),
+}
+
+impl GroupArg {
+    // Names are looked up by the daemon, which fills in the group of the wrapped command.
+    fn command(self, command: impl FnOnce(GroupId) -> Command) -> Command {
+        match self {
+            GroupArg::Id(group) => command(group),
+            GroupArg::Name(name) => Command::NamedGroup(name, Box::new(command(0))),
+        }
+    }
+}
+
+fn group_arg(arg: &str) -> Result<GroupArg, Infallible> {
+    Ok(arg
+        .parse()
+        .map_or_else(|_| GroupArg::Name(arg.to_string()), GroupArg::Id))
+}
+
+fn window_target(arg: &str) -> Result<WindowTarget, Infallible> {
+    Ok(WindowTarget::parse(arg))
+}
+
+// Everything sent to the daemon. Values are typed here, so typos are reported before connecting.
+#[derive(Debug, Subcommand)]
+pub enum ClientCommand {
+    #[command(about = "Print the daemon's state")]
+    Status,
+    #[command(about = "Check that the daemon is running and answering, for health checks")]
+    Ping,
+    #[command(hide = true)]
+    TmpSlots,
+    #[command(hide = true)]
+    TmpSwapWithSlot { slot: SlotId },
+    #[command(about = "Hand over to a freshly started daemon, keeping the state")]
+    RestartServer,
+    #[command(about = "Retile the active group's windows")]
+    Retile {
+        #[arg(long, help = "Every group, not just the active one")]
+        all: bool,
+    },
+    #[command(subcommand, about = "Save or restore the windows of every group")]
+    Session(SessionCommand),
+    #[command(subcommand, about = "Archive a group or restore an archived one")]
+    Group(GroupCommand),
+    #[command(subcommand, about = "Switch between configured profiles")]
+    Profile(ProfileCommand),
+    #[command(subcommand, about = "Manage placement rules")]
+    Rule(RuleCommand),
+    #[command(alias = "select_workspace", about = "Show a workspace on a monitor")]
+    SelectWorkspace {
+        #[arg(value_parser = workspace_arg, value_name = "WORKSPACE|next|prev")]
+        workspace: WorkspaceArg,
+        #[arg(long, value_parser = slot_target, allow_hyphen_values = true)]
+        monitor: Option<SlotTarget>,
+    },
+    #[command(alias = "select_workspace_delta", hide = true)]
+    SelectWorkspaceDelta {
+        #[arg(allow_negative_numbers = true)]
+        delta: i64,
+    },
+    #[command(
+        alias = "move_to_workspace",
+        about = "Move the focused window to a workspace"
+    )]
+    MoveToWorkspace {
+        #[arg(value_parser = workspace_arg, value_name = "WORKSPACE|next|prev")]
+        workspace: WorkspaceArg,
+        #[arg(long, value_parser = slot_target, allow_hyphen_values = true)]
+        monitor: Option<SlotTarget>,
+        #[arg(long, help = "Go along with the window")]
+        follow: bool,
+    },
+    // select-group mirrors select-workspace and select-slot, next to move-to-group.
+    #[command(
+        aliases = ["switch_group", "select-group", "select_group"],
+        about = "Switch every monitor to a group"
+    )]
+    SwitchGroup {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+        #[arg(
+            long,
+            conflicts_with = "occupied_only",
+            help = "Print the switch instead"
+        )]
+        plan: bool,
+        #[arg(long, help = "Only switch monitors the group has windows on")]
+        occupied_only: bool,
+    },
+    #[command(alias = "next_group", about = "Switch to the next group")]
+    NextGroup,
+    #[command(alias = "prev_group", about = "Switch to the previous group")]
+    PrevGroup,
+    #[command(alias = "create_group", about = "Create a group and switch to it")]
+    CreateGroup {
+        #[arg(required = true)]
+        name: Vec<String>,
+        #[arg(long)]
+        template: Option<String>,
+    },
+    #[command(alias = "rename_group", about = "Rename a group")]
+    RenameGroup {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+        #[arg(required = true)]
+        name: Vec<String>,
+    },
+    #[command(alias = "delete_group", about = "Delete a group")]
+    DeleteGroup {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+    },
+    #[command(
+        alias = "enter_subgroup",
+        about = "Switch to a sub-group of the active group"
+    )]
+    EnterSubgroup { name: Vec<String> },
+    #[command(alias = "leave_subgroup", about = "Switch back to the parent group")]
+    LeaveSubgroup,
+    #[command(alias = "move_to_group", about = "Move the focused window to a group")]
+    MoveToGroup {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+    },
+    #[command(alias = "select_slot", about = "Focus a monitor slot")]
+    SelectSlot {
+        #[arg(value_parser = slot_target, allow_hyphen_values = true, help = SLOT_HELP)]
+        slot: SlotTarget,
+        #[arg(long, help = "Stop at the outermost attached monitor")]
+        clamp: bool,
+    },
+    #[command(
+        aliases = ["move_to_slot", "move-to-monitor", "move_to_monitor"],
+        about = "Move the focused window to a monitor slot"
+    )]
+    MoveToSlot {
+        #[arg(value_parser = slot_target, allow_hyphen_values = true, help = SLOT_HELP)]
+        slot: SlotTarget,
+        #[arg(long, help = "Stop at the outermost attached monitor")]
+        clamp: bool,
+        #[arg(long, help = "Go along with the window")]
+        follow: bool,
+    },
+    #[command(
+        aliases = ["move_workspace_to_slot", "move-workspace-to-monitor", "move_workspace_to_monitor"],
+        about = "Move the focused workspace's windows to a monitor slot"
+    )]
+    MoveWorkspaceToSlot {
+        #[arg(value_parser = slot_target, allow_hyphen_values = true, help = SLOT_HELP)]
+        slot: SlotTarget,
+        #[arg(long, help = "Stop at the outermost attached monitor")]
+        clamp: bool,
+        #[arg(long, help = "Go along with the windows")]
+        follow: bool,
+    },
+    #[command(
+        alias = "swap_slot",
+        about = "Swap the focused slot's workspaces with another slot's"
+    )]
+    SwapSlot {
+        #[arg(value_parser = slot_target, allow_hyphen_values = true, help = SLOT_HELP)]
+        slot: SlotTarget,
+        #[arg(long, help = "Stop at the outermost attached monitor")]
+        clamp: bool,
+    },
+    #[command(
+        alias = "swap_monitors",
+        about = "Swap what two monitors show, the only two without arguments"
+    )]
+    SwapMonitors {
+        #[arg(value_parser = slot_target, allow_hyphen_values = true, requires = "b")]
+        a: Option<SlotTarget>,
+        #[arg(value_parser = slot_target, allow_hyphen_values = true)]
+        b: Option<SlotTarget>,
+    },
+    #[command(
+        alias = "swap_with_workspace",
+        about = "Swap the focused workspace with another of the same slot"
+    )]
+    SwapWithWorkspace { workspace: VisibleWorkspace },
+    #[command(
+        alias = "mirror_group",
+        about = "Show a group's focused workspace on another monitor, like a projector"
+    )]
+    MirrorGroup {
+        #[arg(
+            required_unless_present = "stop",
+            value_parser = group_arg,
+            value_name = "GROUP",
+            help = GROUP_HELP
+        )]
+        group: Option<GroupArg>,
+        #[arg(
+            long,
+            value_parser = slot_target,
+            allow_hyphen_values = true,
+            help = "The monitor to show it on, the outermost other one by default"
+        )]
+        on: Option<SlotTarget>,
+        #[arg(
+            long,
+            conflicts_with_all = ["group", "on"],
+            help = "Move the windows back"
+        )]
+        stop: bool,
+    },
+    #[command(about = "Pause scheduled group switches")]
+    Snooze { minutes: Option<u64> },
+    #[command(about = "Mark the start of an idle break, for hypridle")]
+    Idle,
+    #[command(about = "End an idle break, back to the home group after a long one")]
+    Resume,
+    #[command(subcommand, about = "Stay in the active group for a while")]
+    Focus(FocusCommand),
+    #[command(alias = "list_groups", about = "List groups")]
+    ListGroups {
+        #[arg(long, help = "With monitors, workspaces and windows")]
+        tree: bool,
+    },
+    #[command(
+        alias = "cycle_window",
+        about = "Focus the next window of the active group"
+    )]
+    CycleWindow {
+        #[arg(long)]
+        reverse: bool,
+    },
+    #[command(about = "Refresh the cached Hyprland state")]
+    Sync,
+    #[command(
+        alias = "move_to_empty",
+        about = "Move the focused window to an empty workspace"
+    )]
+    MoveToEmpty,
+    #[command(
+        alias = "toggle_workspace",
+        about = "Go back to the previously shown workspace"
+    )]
+    ToggleWorkspace,
+    #[command(about = "Set the focused workspace's layout")]
+    Layout { layout: String },
+    #[command(about = "Send a message to the focused workspace's layout")]
+    Layoutmsg {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    #[command(about = "Compact overflow workspaces")]
+    Compact,
+    #[command(
+        alias = "lock_window",
+        about = "Keep a window on its workspace across group switches"
+    )]
+    LockWindow {
+        #[arg(value_parser = window_target, value_name = "focused|ADDRESS")]
+        window: WindowTarget,
+    },
+    #[command(alias = "unlock_window", about = "Undo lock-window")]
+    UnlockWindow {
+        #[arg(value_parser = window_target, value_name = "focused|ADDRESS")]
+        window: WindowTarget,
+    },
+    #[command(subcommand, about = "Mark windows to move them together")]
+    Select(SelectCommand),
+    #[command(
+        alias = "rename_workspace",
+        about = "Label the focused workspace, or remove its label"
+    )]
+    RenameWorkspace { label: Vec<String> },
+    #[command(
+        alias = "toggle_special",
+        about = "Show or hide a special workspace (scratchpad), the unnamed one by default"
+    )]
+    ToggleSpecial { name: Option<String> },
+    #[command(
+        alias = "move_to_special",
+        about = "Move the focused window to a special workspace, the unnamed one by default"
+    )]
+    MoveToSpecial { name: Option<String> },
+    #[command(about = "Move every window of the active group onto the focused workspace")]
+    Gather,
+    #[command(about = "Find windows by class or title")]
+    Where {
+        #[arg(required = true, value_name = "CLASS|TITLE")]
+        pattern: Vec<String>,
+        #[arg(long, help = "Focus the first match")]
+        focus: bool,
+    },
+    #[command(about = "Send a request to Hyprland through the daemon, like hyprctl")]
+    Hyprctl {
+        #[arg(
+            long,
+            help = "Also send dispatchers that end the session or place workspaces behind hywoma's back"
+        )]
+        unchecked: bool,
+        #[arg(
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    Save,
+    Restore,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GroupCommand {
+    Archive {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+    },
+    Restore {
+        #[arg(value_parser = group_arg, value_name = "GROUP", help = GROUP_HELP)]
+        group: GroupArg,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    Switch { name: String },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RuleCommand {
+    #[command(about = "Send new windows of a class to a workspace of a group")]
+    Add {
+        class: String,
+        group: GroupId,
+        workspace: VisibleWorkspace,
+        slot: Option<SlotId>,
+    },
+    List,
+    #[command(about = "Remove a rule by its number in `rule list`")]
+    Remove {
+        index: usize,
+    },
+    #[command(about = "Print the rules as config")]
+    Export,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FocusCommand {
+    Start {
+        #[arg(value_parser = focus::parse_duration, help = "e.g. 25m, 1h or 90s")]
+        duration: Duration,
+    },
+    Stop,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SelectCommand {
+    Add {
+        #[arg(value_parser = window_target, default_value = "focused")]
+        window: WindowTarget,
+    },
+    Clear,
+    #[command(alias = "move_to_workspace")]
+    MoveToWorkspace {
+        workspace: VisibleWorkspace,
+    },
+}
+
+impl ClientCommand {
+    pub fn into_command(self, force: bool, json: bool) -> Result<Command> {
+        let command = self.command(json);
+        if !force {
+            return Ok(command);
+        }
+        let leaves_group = command
+            .clone()
+            .into_message()
+            .is_ok_and(|message| focus::leaves_group(&message));
+        if !leaves_group {
+            bail!("--force only applies to group switches");
+        }
+        Ok(Command::Forced(Box::new(command)))
+    }
+
+    // With `json`, answers the daemon can format either way come back as JSON.
+    fn command(self, json: bool) -> Command {
+        match self {
+            ClientCommand::Status => Command::Status,
+            ClientCommand::Ping => Command::Ping,
+            ClientCommand::TmpSlots => Command::TmpSlots,
+            ClientCommand::TmpSwapWithSlot { slot } => Command::TmpSwapWithSlot(slot),
+            ClientCommand::RestartServer => Command::RestartServer,
+            ClientCommand::Retile { all } => Command::Retile(all),
+            ClientCommand::Session(SessionCommand::Save) => Command::SaveSession,
+            ClientCommand::Session(SessionCommand::Restore) => Command::RestoreSession,
+            ClientCommand::Group(GroupCommand::Archive { group }) => {
+                group.command(Command::ArchiveGroup)
+            }
+            ClientCommand::Group(GroupCommand::Restore { group }) => {
+                group.command(Command::RestoreGroup)
+            }
+            ClientCommand::Profile(ProfileCommand::Switch { name }) => Command::SwitchProfile(name),
+            ClientCommand::Profile(ProfileCommand::List) => Command::ListProfiles,
+            ClientCommand::Rule(rule) => Command::Rule(match rule {
+                RuleCommand::Add {
+                    class,
+                    group,
+                    workspace,
+                    slot,
+                } => RuleRequest::Add(Rule {
+                    class,
+                    group,
+                    workspace,
+                    slot,
+                }),
+                RuleCommand::List => RuleRequest::List,
+                RuleCommand::Remove { index } => RuleRequest::Remove(index),
+                RuleCommand::Export => RuleRequest::Export,
+            }),
+            ClientCommand::SelectWorkspace { workspace, monitor } => match workspace {
+                WorkspaceArg::Number(workspace) => Command::SelectWorkspace(workspace, monitor),
+                WorkspaceArg::Next => Command::SelectWorkspaceRelative(1),
+                WorkspaceArg::Prev => Command::SelectWorkspaceRelative(-1),
+            },
+            ClientCommand::SelectWorkspaceDelta { delta } => Command::SelectWorkspaceDelta(delta),
+            ClientCommand::MoveToWorkspace {
+                workspace,
+                monitor,
+                follow,
+            } => match workspace {
+                WorkspaceArg::Number(workspace) => {
+                    Command::MoveToWorkspace(workspace, monitor, follow)
+                }
+                WorkspaceArg::Next => Command::MoveToWorkspaceRelative(1, follow),
+                WorkspaceArg::Prev => Command::MoveToWorkspaceRelative(-1, follow),
+            },
+            ClientCommand::SwitchGroup {
+                group,
+                plan,
+                occupied_only,
+            } => {
+                if plan {
+                    group.command(Command::PlanGroupSwitch)
+                } else if occupied_only {
+                    group.command(Command::SwitchGroupOccupiedOnly)
+                } else {
+                    group.command(Command::SwitchGroup)
+                }
+            }
+            ClientCommand::NextGroup => Command::SwitchGroupRelative(1),
+            ClientCommand::PrevGroup => Command::SwitchGroupRelative(-1),
+            ClientCommand::CreateGroup { name, template } => {
+                Command::CreateGroup(name.join(" "), template)
+            }
+            ClientCommand::RenameGroup { group, name } => {
+                group.command(|group| Command::RenameGroup(group, name.join(" ")))
+            }
+            ClientCommand::DeleteGroup { group } => group.command(Command::DeleteGroup),
+            ClientCommand::EnterSubgroup { name } => {
+                Command::EnterSubgroup((!name.is_empty()).then(|| name.join(" ")))
+            }
+            ClientCommand::LeaveSubgroup => Command::LeaveSubgroup,
+            ClientCommand::MoveToGroup { group } => group.command(Command::MoveToGroup),
+            ClientCommand::SelectSlot { slot, clamp } => Command::SelectSlot(slot.clamped(clamp)),
+            ClientCommand::MoveToSlot {
+                slot,
+                clamp,
+                follow,
+            } => Command::MoveToSlot(slot.clamped(clamp), follow),
+            ClientCommand::MoveWorkspaceToSlot {
+                slot,
+                clamp,
+                follow,
+            } => Command::MoveWorkspaceToSlot(slot.clamped(clamp), follow),
+            ClientCommand::SwapSlot { slot, clamp } => Command::SwapSlot(slot.clamped(clamp)),
+            ClientCommand::SwapMonitors { a, b } => Command::SwapMonitors(a.zip(b)),
+            ClientCommand::SwapWithWorkspace { workspace } => Command::SwapWithWorkspace(workspace),
+            ClientCommand::MirrorGroup { group, on, stop } => match group {
+                Some(group) if !stop => group.command(|group| Command::MirrorGroup(group, on)),
+                _ => Command::StopMirror,
+            },
+            ClientCommand::Snooze { minutes } => Command::Snooze(minutes),
+            ClientCommand::Idle => Command::Idle,
+            ClientCommand::Resume => Command::Resume,
+            ClientCommand::Focus(FocusCommand::Start { duration }) => {
+                Command::FocusStart(duration.as_secs())
+            }
+            ClientCommand::Focus(FocusCommand::Stop) => Command::FocusStop,
+            ClientCommand::ListGroups { tree } => Command::ListGroups(tree, json),
+            ClientCommand::CycleWindow { reverse } => Command::CycleWindow(reverse),
+            ClientCommand::Sync => Command::Sync,
+            ClientCommand::MoveToEmpty => Command::MoveToEmpty,
+            ClientCommand::ToggleWorkspace => Command::ToggleWorkspace,
+            ClientCommand::Layout { layout } => Command::SetLayout(layout),
+            ClientCommand::Layoutmsg { args } => Command::LayoutMsg(args.join(" ")),
+            ClientCommand::Compact => Command::Compact,
+            ClientCommand::LockWindow { window } => Command::LockWindow(window),
+            ClientCommand::UnlockWindow { window } => Command::UnlockWindow(window),
+            ClientCommand::Select(SelectCommand::Add { window }) => Command::MarkWindow(window),
+            ClientCommand::Select(SelectCommand::Clear) => Command::ClearMarks,
+            ClientCommand::Select(SelectCommand::MoveToWorkspace { workspace }) => {
+                Command::MoveMarkedToWorkspace(workspace)
+            }
+            // Without a label, removes the focused workspace's label.
+            ClientCommand::RenameWorkspace { label } => {
+                Command::RenameWorkspace((!label.is_empty()).then(|| label.join(" ")))
+            }
+            ClientCommand::ToggleSpecial { name } => Command::ToggleSpecial(name),
+            ClientCommand::MoveToSpecial { name } => Command::MoveToSpecial(name),
+            ClientCommand::Gather => Command::Gather,
+            ClientCommand::Where { pattern, focus } => Command::Where(pattern.join(" "), focus),
+            ClientCommand::Hyprctl { unchecked, args } => Command::Hyprctl(args, unchecked),
+        }
+    }
+}
+
+pub fn completions(shell: Shell) -> Vec<u8> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "hywoma", &mut script);
+    script
+}
+
+// For clients from before the protocol header, which sent their argv as it was.
+pub fn unknown_command(args: Vec<String>, force: bool) -> Result<Command> {
+    if force {
+        bail!("--force only applies to group switches");
+    }
+    Ok(Command::Unknown(args))
+}
+
+pub fn parse_command(args: &[String]) -> Result<Command> {
+    let cli = Cli::try_parse_from(
+        ["hywoma"]
+            .into_iter()
+            .chain(args.iter().map(String::as_str)),
+    )?;
+    match cli.action {
+        Action::Client(command) => command.into_command(cli.force, cli.json),
+        Action::Unknown(args) => unknown_command(args, cli.force),
+        _ => bail!("`{}` is not a daemon command", args.join(" ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Command> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_command(&args)
+    }
+
+    #[test]
+    fn cli_definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn completions_cover_every_subcommand() {
+        let script = String::from_utf8(completions(Shell::Bash)).unwrap();
+
+        assert!(script.contains("select-workspace"));
+        assert!(script.contains("rename-workspace"));
+    }
+
+    #[test]
+    fn parses_argv_into_commands() {
+        assert_eq!(
+            parse("move_to_workspace 3 --follow --monitor 2").unwrap(),
+            Command::MoveToWorkspace(3, Some(SlotTarget::Slot(2)), true)
+        );
+        assert_eq!(
+            parse("create-group Deep work --template project").unwrap(),
+            Command::CreateGroup("Deep work".to_string(), Some("project".to_string()))
+        );
+        assert_eq!(
+            parse("select_group 2 --plan").unwrap(),
+            Command::PlanGroupSwitch(2)
+        );
+        assert_eq!(
+            parse("move-to-monitor -1 --clamp").unwrap(),
+            Command::MoveToSlot(
+                SlotTarget::Clamped(Box::new(SlotTarget::Relative(-1))),
+                false
+            )
+        );
+        assert_eq!(
+            parse("move_workspace_to_monitor 2 --follow").unwrap(),
+            Command::MoveWorkspaceToSlot(SlotTarget::Slot(2), true)
+        );
+        assert_eq!(
+            parse("rule add firefox 1 2 3").unwrap(),
+            Command::Rule(RuleRequest::Add(Rule {
+                class: "firefox".to_string(),
+                group: 1,
+                workspace: 2,
+                slot: Some(3),
+            }))
+        );
+        assert_eq!(
+            parse("rule remove 1").unwrap(),
+            Command::Rule(RuleRequest::Remove(1))
+        );
+        assert_eq!(
+            parse("mirror_group 2 --on HDMI-A-1").unwrap(),
+            Command::MirrorGroup(2, Some(SlotTarget::Output("HDMI-A-1".to_string())))
+        );
+        assert_eq!(parse("mirror-group --stop").unwrap(), Command::StopMirror);
+        assert_eq!(parse("ping").unwrap(), Command::Ping);
+        assert_eq!(
+            parse("toggle_special term").unwrap(),
+            Command::ToggleSpecial(Some("term".to_string()))
+        );
+        assert_eq!(
+            parse("move-to-special").unwrap(),
+            Command::MoveToSpecial(None)
+        );
+        assert_eq!(parse("gather").unwrap(), Command::Gather);
+        assert_eq!(
+            parse("hyprctl -j clients").unwrap(),
+            Command::Hyprctl(vec!["-j".to_string(), "clients".to_string()], false)
+        );
+        assert!(parse("mirror-group").is_err());
+        assert!(parse("rule add firefox").is_err());
+        assert!(parse("select_workspace three").is_err());
+        assert_eq!(
+            parse("movefocus l").unwrap(),
+            Command::Unknown(vec!["movefocus".to_string(), "l".to_string()])
+        );
+        assert!(parse("server").is_err());
+    }
+
+    #[test]
+    fn force_only_applies_to_group_switches() {
+        assert_eq!(
+            parse("next_group --force").unwrap(),
+            Command::Forced(Box::new(Command::SwitchGroupRelative(1)))
+        );
+        assert!(parse("select_workspace 2 --force").is_err());
+        assert_eq!(
+            parse("select_group work --force").unwrap(),
+            Command::Forced(Box::new(Command::NamedGroup(
+                "work".to_string(),
+                Box::new(Command::SwitchGroup(0))
+            )))
+        );
+    }
+
+    #[test]
+    fn groups_go_by_number_or_name() {
+        assert_eq!(parse("select_group 2").unwrap(), Command::SwitchGroup(2));
+        assert_eq!(
+            parse("group archive media").unwrap(),
+            Command::NamedGroup("media".to_string(), Box::new(Command::ArchiveGroup(0)))
+        );
+        assert!(parse("group archive media").unwrap().expects_response());
+    }
+
+    #[test]
+    fn global_flags_go_anywhere() {
+        let cli = Cli::try_parse_from([
+            "hywoma",
+            "--log-format=json",
+            "select-workspace",
+            "--token",
+            "abc",
+            "1",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.log_format, LogFormat::Json);
+        assert_eq!(cli.token.as_deref(), Some("abc"));
+        let cli = Cli::try_parse_from(["hywoma", "--token=abc", "status"]).unwrap();
+        assert_eq!(cli.token.as_deref(), Some("abc"));
+        assert!(Cli::try_parse_from(["hywoma", "server", "--log-format", "xml"]).is_err());
+        assert!(Cli::try_parse_from(["hywoma", "server", "--log-format"]).is_err());
+    }
+}
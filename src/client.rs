@@ -0,0 +1,225 @@
+use anyhow::{Result, bail};
+use std::io::{self, BufRead, BufReader, ErrorKind};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use crate::app::{self, FocusEvent};
+use crate::protocol::{self, Command, Progress};
+
+// Control of a running daemon for programs that embed it, like a status bar, instead of running
+// the `hywoma` binary. Every request is a connection of its own, as with the CLI, so a client can
+// be kept around and used from any thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Client {
+    socket: PathBuf,
+}
+
+impl Client {
+    // The daemon of this session, at the socket the CLI would use.
+    pub fn connect() -> Result<Self> {
+        let socket = app::get_command_socket_path()?;
+        if !socket.exists() {
+            bail!("No hywoma daemon is listening at {socket:?}");
+        }
+        Ok(Client { socket })
+    }
+
+    pub fn with_socket(socket: impl Into<PathBuf>) -> Self {
+        Client {
+            socket: socket.into(),
+        }
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    // The daemon's answer for commands that have one, like `Status` or `ListGroups`. Other
+    // commands answer with an empty string once they ran. Either fails with the daemon's reason
+    // when the command did nothing.
+    pub fn send(&self, command: Command) -> Result<String> {
+        self.send_with_token(None, command)
+    }
+
+    // With a token, a command repeated within a few minutes runs only once, for callers that
+    // retry.
+    pub fn send_with_token(&self, token: Option<String>, command: Command) -> Result<String> {
+        self.send_with_progress(token, command, |_| {})
+    }
+
+    // Like `send_with_token`, telling `on_progress` how far along a bulk operation like a session
+    // restore or an adoption is while the daemon works through its dispatches.
+    pub fn send_with_progress(
+        &self,
+        token: Option<String>,
+        command: Command,
+        on_progress: impl FnMut(Progress),
+    ) -> Result<String> {
+        if matches!(command, Command::Watch | Command::RawEvents) {
+            bail!("{command:?} is a stream, use Client::focus_events");
+        }
+        let expects_response = command.expects_response();
+        let response_timeout = command.response_timeout();
+        let stream = self.open(token, command)?;
+        stream.set_read_timeout(response_timeout)?;
+        let mut response = String::new();
+        if let Err(err) = read_response(BufReader::new(stream), &mut response, on_progress) {
+            if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                && let Some(timeout) = response_timeout
+            {
+                bail!("The daemon did not answer within {timeout:?}");
+            }
+            return Err(err.into());
+        }
+        if expects_response {
+            return protocol::parse_response(&response);
+        }
+        protocol::parse_reply(&response)?;
+        Ok(String::new())
+    }
+
+    // The `hywoma status` snapshot.
+    pub fn status(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::from_str(&self.send(Command::Status)?)?)
+    }
+
+    // Every change of the focused group, slot or workspace, starting with the current one, until
+    // the daemon goes away.
+    pub fn focus_events(&self) -> Result<FocusEvents> {
+        Ok(FocusEvents {
+            reader: BufReader::new(self.open(None, Command::Watch)?),
+            line: String::new(),
+        })
+    }
+
+    fn open(&self, token: Option<String>, command: Command) -> Result<UnixStream> {
+        app::open_command_stream(&self.socket, token, command)
+    }
+}
+
+// Progress lines come ahead of the reply, which is passed on as it is.
+fn read_response(
+    mut reader: impl BufRead,
+    response: &mut String,
+    mut on_progress: impl FnMut(Progress),
+) -> io::Result<()> {
+    loop {
+        if reader.read_line(response)? == 0 {
+            return Ok(());
+        }
+        match protocol::parse_progress(response) {
+            Some(progress) => {
+                on_progress(progress);
+                response.clear();
+            }
+            None => break,
+        }
+    }
+    reader.read_to_string(response)?;
+    Ok(())
+}
+
+pub struct FocusEvents {
+    reader: BufReader<UnixStream>,
+    line: String,
+}
+
+impl Iterator for FocusEvents {
+    type Item = Result<FocusEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.line.clear();
+        match self.reader.read_line(&mut self.line) {
+            Ok(0) => None,
+            Ok(_) => Some(serde_json::from_str(&self.line).map_err(Into::into)),
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::RuleRequest;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    // Answers each connection with the next reply, and hands back the commands it received.
+    fn fake_daemon(socket: &Path, replies: &[&str]) -> thread::JoinHandle<Vec<Command>> {
+        let listener = UnixListener::bind(socket).unwrap();
+        let replies: Vec<String> = replies.iter().map(|reply| reply.to_string()).collect();
+        thread::spawn(move || {
+            let mut received = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                stream.read_to_end(&mut request).unwrap();
+                received.push(protocol::decode(&request).unwrap().command);
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            received
+        })
+    }
+
+    #[test]
+    fn sends_typed_commands_and_reads_their_replies() {
+        let socket =
+            std::env::temp_dir().join(format!("hywoma-client-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let daemon = fake_daemon(
+            &socket,
+            &[
+                "Ok",
+                "Err: Cannot switch to unknown workspace group 7",
+                "No rules",
+                "Progress: 20/30\nProgress: 30/30\nRestored 30 window(s)\n",
+            ],
+        );
+        let client = Client::with_socket(&socket);
+
+        assert_eq!(client.send(Command::SwitchGroup(1)).unwrap(), "");
+        assert_eq!(
+            client
+                .send(Command::SwitchGroup(7))
+                .unwrap_err()
+                .to_string(),
+            "Cannot switch to unknown workspace group 7"
+        );
+        assert_eq!(
+            client.send(Command::Rule(RuleRequest::List)).unwrap(),
+            "No rules"
+        );
+        let mut progress = Vec::new();
+        assert_eq!(
+            client
+                .send_with_progress(None, Command::RestoreSession, |step| progress.push(step))
+                .unwrap(),
+            "Restored 30 window(s)\n"
+        );
+        assert_eq!(
+            progress,
+            [
+                Progress {
+                    done: 20,
+                    total: 30
+                },
+                Progress {
+                    done: 30,
+                    total: 30
+                }
+            ]
+        );
+        assert!(client.send(Command::Watch).is_err());
+        assert_eq!(
+            daemon.join().unwrap(),
+            [
+                Command::SwitchGroup(1),
+                Command::SwitchGroup(7),
+                Command::Rule(RuleRequest::List),
+                Command::RestoreSession
+            ]
+        );
+        std::fs::remove_file(&socket).unwrap();
+    }
+}
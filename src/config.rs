@@ -0,0 +1,837 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::edges::EdgeSwitchConfig;
+use crate::focus::FocusConfig;
+use crate::hooks::HooksConfig;
+use crate::hyprland::Orientation;
+use crate::reconcile::ReconcileConfig;
+use crate::rules::Rule;
+use crate::schedule::ScheduleConfig;
+use crate::session::SessionConfig;
+use crate::state::{GroupId, IdLayout, SlotId, VisibleWorkspace};
+use crate::templates::GroupTemplate;
+use crate::thumbnails::ThumbnailsConfig;
+use crate::watchdog::WatchdogConfig;
+
+// User configuration, as opposed to the runtime state under XDG_RUNTIME_DIR. Everything here is
+// optional: a missing file is the same as an empty one, so hywoma keeps working without setup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub rules: Vec<Rule>,
+    pub hooks: HooksConfig,
+    pub groups: Vec<GroupConfig>,
+    // Screen filter for groups without their own. When unset but some group has a filter, other
+    // groups reset hyprsunset to identity so a warm tint does not leak into neutral groups.
+    pub screen_filter: Option<ScreenFilter>,
+    // Opacity in percent for windows of other groups while they are on screen, e.g. on a slot left
+    // showing its workspace by an `--occupied-only` switch. Unset leaves them alone.
+    pub dim_inactive: Option<u8>,
+    // Hyprland name of every managed workspace, kept up to date with its window count, e.g.
+    // "G{group}:W{workspace} ({windows})". {name} is the label or ID it would have otherwise, and
+    // {group_name} is also there. Unset leaves the names alone.
+    pub workspace_badges: Option<String>,
+    pub sounds: SoundsConfig,
+    pub speech: SpeechConfig,
+    pub reconcile: ReconcileConfig,
+    // Move the pointer to the center of the monitor focused with `select_slot`, for focus models
+    // that follow the cursor.
+    pub warp_cursor: bool,
+    // Let `select_workspace next|prev` and `move_to_workspace next|prev` wrap from the last visible
+    // workspace to the first and back.
+    pub wrap_workspaces: bool,
+    // `next_group` and `prev_group` cycle through groups 0 to `max_groups - 1`, creating missing
+    // ones. Unset, they cycle through the existing top-level groups.
+    pub max_groups: Option<GroupId>,
+    // Visible workspaces per slot, 1 to workspace_ids.workspaces. Orientation defaults take
+    // precedence for their monitors.
+    pub workspaces_per_slot: Option<VisibleWorkspace>,
+    // Widths of the workspace numbering, e.g. `workspace_ids = { workspaces = 20, monitors = 4,
+    // groups = 50 }`. Defaults to 10 of each. Read at startup only.
+    pub workspace_ids: IdLayout,
+    // Let the focus follow windows moved with `move_to_workspace` and `move_to_slot` instead of
+    // moving them silently.
+    pub follow_moves: bool,
+    // What slot commands aimed at another monitor do while only one is attached, like on a laptop
+    // away from its dock: "error" tells the client, "ignore" quietly does nothing.
+    pub single_monitor: SingleMonitor,
+    // Hyprland workspaces hywoma keeps its hands off, by ID or name, e.g.
+    // `unmanaged_workspaces = [9, "obs"]`. Startup adoption, reconciliation and focus tracking
+    // ignore them, and hywoma never allocates their IDs.
+    pub unmanaged_workspaces: Vec<WorkspaceSelector>,
+    // Commands hywoma does not know: "reject" answers with an error, "dispatch" hands them to
+    // `hyprctl dispatch` as they are, so hywoma can be the one entry point for compositor bindings.
+    pub unknown_commands: UnknownCommands,
+    // Replacements for the dispatchers hywoma emits, e.g.
+    // `dispatchers = { workspace = "focusworkspaceoncurrentmonitor {args}" }`. Without `{args}` the
+    // arguments are appended.
+    pub dispatchers: BTreeMap<String, String>,
+    // Where the daemon listens for commands, instead of $XDG_RUNTIME_DIR/.hywoma-commands.sock.
+    pub command_socket: Option<PathBuf>,
+    // File every received command is appended to, with the pid, uid and parent command line of
+    // its sender. Read at startup only.
+    pub audit_log: Option<PathBuf>,
+    pub orientations: OrientationsConfig,
+    pub thumbnails: ThumbnailsConfig,
+    pub templates: BTreeMap<String, GroupTemplate>,
+    pub schedule: ScheduleConfig,
+    pub focus: FocusConfig,
+    pub idle: IdleConfig,
+    pub notifications: NotificationsConfig,
+    pub session: SessionConfig,
+    pub retile: RetileConfig,
+    pub bulk: BulkConfig,
+    pub watchdog: WatchdogConfig,
+    pub edge_switch: EdgeSwitchConfig,
+    // Output name -> slot, e.g. `monitors = { "DP-1" = 1, "eDP-1" = 2 }`. Replaces the built-in
+    // per-host monitor policy when set.
+    pub monitors: BTreeMap<String, SlotId>,
+    // Profile applied at startup. `hywoma profile switch <name>` changes it at runtime.
+    pub profile: Option<String>,
+    // Named overrides, from `[profiles.<name>]` tables or profiles/<name>.toml next to the config.
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SingleMonitor {
+    #[default]
+    Error,
+    Ignore,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WorkspaceSelector {
+    Id(u64),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownCommands {
+    #[default]
+    Reject,
+    Dispatch,
+}
+
+// A profile replaces the matching top level settings as a whole; anything it leaves out is taken
+// from the top level. Replacing instead of merging keeps "which rules are active" answerable by
+// reading one table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub monitors: Option<BTreeMap<String, SlotId>>,
+    pub groups: Option<Vec<GroupConfig>>,
+    pub rules: Option<Vec<Rule>>,
+}
+
+// Per-group settings, keyed by group ID. Groups without an entry simply leave the corresponding
+// desktop settings alone when they become active.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    pub id: GroupId,
+    // Applied when the config or a profile is loaded; a missing group is created.
+    pub name: Option<String>,
+    // PipeWire/PulseAudio sink name as listed by `pactl list short sinks`.
+    pub audio_sink: Option<String>,
+    pub screen_filter: Option<ScreenFilter>,
+    // Cue played when switching to this group, instead of sounds.group_switch.
+    pub switch_sound: Option<PathBuf>,
+    // Name of a `[templates.<name>]` entry applied when the group is first activated.
+    pub template: Option<String>,
+    // Notification daemon mode while the group is active, e.g. "do-not-disturb" for deep work.
+    pub notification_mode: Option<String>,
+    // Opacity of this group's windows while another group is active, instead of dim_inactive.
+    pub dim: Option<u8>,
+}
+
+// Accessibility cues for context changes. Nothing is played unless a sound file is configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoundsConfig {
+    pub player: String,
+    pub group_switch: Option<PathBuf>,
+    pub workspace_switch: Option<PathBuf>,
+}
+
+impl Default for SoundsConfig {
+    fn default() -> Self {
+        SoundsConfig {
+            // paplay ships with PulseAudio and pipewire-pulse, and plays .oga/.wav directly.
+            player: "paplay".to_string(),
+            group_switch: None,
+            workspace_switch: None,
+        }
+    }
+}
+
+// Spoken navigation feedback for blind and low-vision users, off by default. The command gets the
+// announcement as its only argument; spd-say hands it to speech-dispatcher.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeechConfig {
+    pub enabled: bool,
+    pub command: String,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        SpeechConfig {
+            enabled: false,
+            command: "spd-say".to_string(),
+        }
+    }
+}
+
+// `hywoma retile` tiles every floating window except these classes, e.g. pavucontrol or a
+// calculator that is meant to float.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetileConfig {
+    pub keep_floating: Vec<String>,
+}
+
+// Dispatch batches longer than `chunk_size`, like a startup adoption or a session restore, go to
+// Hyprland in chunks `pause_ms` apart so it is not flooded, and the client that asked is told how
+// far along they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BulkConfig {
+    pub chunk_size: usize,
+    pub pause_ms: u64,
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 20,
+            pause_ms: 50,
+        }
+    }
+}
+
+// Switches the notification daemon's mode with the active group. The command gets the mode in
+// HYWOMA_NOTIFICATION_MODE; for dunst use e.g.
+// `dunstctl set-paused $([ "$HYWOMA_NOTIFICATION_MODE" = default ] && echo false || echo true)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub command: String,
+    // Mode for groups without their own.
+    pub default_mode: String,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            command: r#"makoctl mode -s "$HYWOMA_NOTIFICATION_MODE""#.to_string(),
+            default_mode: "default".to_string(),
+        }
+    }
+}
+
+// Reactions to `hywoma idle` and `hywoma resume`, meant for a hypridle listener:
+// `on-timeout = hywoma idle` and `on-resume = hywoma resume`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    // Group to return to when resuming from a long idle period, e.g. after lunch.
+    pub home_group: Option<GroupId>,
+    // How long the idle period must have lasted for the return to the home group.
+    pub return_after_secs: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        IdleConfig {
+            home_group: None,
+            return_after_secs: 30 * 60,
+        }
+    }
+}
+
+// Defaults for slots by the orientation of the monitor attached to them, e.g.
+// `[orientations.portrait] workspaces = 4` for a rotated side monitor.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrientationsConfig {
+    pub landscape: Option<OrientationDefaults>,
+    pub portrait: Option<OrientationDefaults>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrientationDefaults {
+    pub workspaces: Option<VisibleWorkspace>,
+    // Replaces the slot label ("left", "middle", ...) in status output and announcements.
+    pub label: Option<String>,
+}
+
+impl OrientationsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.landscape.is_none() && self.portrait.is_none()
+    }
+
+    pub fn defaults_for(&self, orientation: Orientation) -> Option<&OrientationDefaults> {
+        match orientation {
+            Orientation::Landscape => self.landscape.as_ref(),
+            Orientation::Portrait => self.portrait.as_ref(),
+        }
+    }
+}
+
+// Gamma/blue-light profile. Temperature and gamma go to hyprsunset through `hyprctl hyprsunset`;
+// a command replaces hyprsunset entirely, e.g. `hyprshade on blue-light-filter`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenFilter {
+    pub temperature: Option<u32>,
+    pub gamma: Option<u32>,
+    pub command: Option<String>,
+}
+
+pub fn config_path() -> Result<PathBuf> {
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map_err(|_| anyhow!("Neither XDG_CONFIG_HOME nor HOME is set"))?;
+    Ok(config_home.join("hywoma").join("config.toml"))
+}
+
+impl Config {
+    #[cfg(test)]
+    pub fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+
+    // Reads a config file with its includes resolved and ${VAR} references expanded.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Ok(toml::Value::Table(load_table(path, 0)?).try_into()?)
+    }
+
+    // The configuration with `name`'s overrides applied, remembering which profile is active.
+    pub fn with_profile(&self, name: &str) -> Result<Config> {
+        let profile = self.profiles.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            anyhow!(
+                "Unknown profile {name:?}, known profiles: {}",
+                known.join(", ")
+            )
+        })?;
+        let mut config = self.clone();
+        if let Some(monitors) = &profile.monitors {
+            config.monitors = monitors.clone();
+        }
+        if let Some(groups) = &profile.groups {
+            config.groups = groups.clone();
+        }
+        if let Some(rules) = &profile.rules {
+            config.rules = rules.clone();
+        }
+        config.profile = Some(name.to_string());
+        Ok(config)
+    }
+
+    // The configuration the daemon starts with: the startup profile applied if one is set.
+    pub fn active(&self) -> Config {
+        let Some(name) = &self.profile else {
+            return self.clone();
+        };
+        self.with_profile(name).unwrap_or_else(|err| {
+            tracing::warn!("Ignoring startup profile: {err:?}");
+            Config {
+                profile: None,
+                ..self.clone()
+            }
+        })
+    }
+
+    pub fn group(&self, group: GroupId) -> Option<&GroupConfig> {
+        self.groups.iter().find(|config| config.id == group)
+    }
+
+    pub fn switch_sound_for(&self, group: GroupId) -> Option<&PathBuf> {
+        self.group(group)
+            .and_then(|group| group.switch_sound.as_ref())
+            .or(self.sounds.group_switch.as_ref())
+    }
+
+    pub fn screen_filter_for(&self, group: GroupId) -> Option<ScreenFilter> {
+        if let Some(filter) = self
+            .group(group)
+            .and_then(|group| group.screen_filter.as_ref())
+        {
+            return Some(filter.clone());
+        }
+        if let Some(filter) = &self.screen_filter {
+            return Some(filter.clone());
+        }
+        // An empty filter means hyprsunset identity. Only reset when filters are in use at all, so
+        // people who never configured one do not get hyprctl spawned on every group switch.
+        self.groups
+            .iter()
+            .any(|group| group.screen_filter.is_some())
+            .then(ScreenFilter::default)
+    }
+
+    // Like screen filters, groups without a mode only reset to the default once some group has one.
+    pub fn notification_mode_for(&self, group: GroupId) -> Option<&str> {
+        if let Some(mode) = self
+            .group(group)
+            .and_then(|group| group.notification_mode.as_deref())
+        {
+            return Some(mode);
+        }
+        self.groups
+            .iter()
+            .any(|group| group.notification_mode.is_some())
+            .then_some(self.notifications.default_mode.as_str())
+    }
+
+    // profiles/<name>.toml holds the body of a `[profiles.<name>]` table. Tables in the main file
+    // win, so a profile is never defined twice by accident.
+    fn load_profile_dir(&mut self, dir: &Path) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            if path.extension().is_none_or(|extension| extension != "toml") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            if self.profiles.contains_key(name) {
+                tracing::warn!(
+                    "Ignoring profile file {path:?}, profile {name:?} is defined in config"
+                );
+                continue;
+            }
+            let profile = load_table(&path, 0)
+                .and_then(|table| Ok(toml::Value::Table(table).try_into::<Profile>()?));
+            match profile {
+                Ok(profile) => {
+                    self.profiles.insert(name.to_string(), profile);
+                }
+                Err(err) => tracing::warn!("Ignoring invalid profile file {path:?}: {err:?}"),
+            }
+        }
+    }
+
+    // Clients only need the socket path, without the daemon's reporting about the config.
+    pub fn command_socket() -> Option<PathBuf> {
+        let path = config_path().ok().filter(|path| path.exists())?;
+        Config::from_file(&path).ok()?.command_socket
+    }
+
+    pub fn load() -> Self {
+        let Ok(path) = config_path() else {
+            return Config::default();
+        };
+        if !path.exists() {
+            return Config::default();
+        }
+        // A broken config should not take workspace switching down with it. Report it and fall
+        // back to defaults; the daemon still behaves like it did before the file existed.
+        match Config::from_file(&path) {
+            Ok(mut config) => {
+                tracing::info!("Loaded hywoma config {path:?}");
+                if let Some(dir) = path.parent() {
+                    config.load_profile_dir(&dir.join("profiles"));
+                }
+                config.schedule.drop_invalid_entries();
+                config
+            }
+            Err(err) => {
+                tracing::warn!("Ignoring invalid hywoma config {path:?}: {err:?}");
+                Config::default()
+            }
+        }
+    }
+}
+
+// Deep enough for a dotfiles layout, shallow enough to stop an include cycle quickly.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+// `include = ["monitors.toml"]` pulls in other files, relative to the including one. Included files
+// are merged in order and the including file is merged last, so it can override anything it
+// includes. Tables merge key by key; everything else, arrays included, is replaced.
+fn load_table(path: &Path, depth: usize) -> Result<toml::Table> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(anyhow!("Config includes nested too deeply at {path:?}"));
+    }
+    let data = fs::read_to_string(path).map_err(|err| anyhow!("Cannot read {path:?}: {err}"))?;
+    let mut table: toml::Table =
+        toml::from_str(&data).map_err(|err| anyhow!("Invalid TOML in {path:?}: {err}"))?;
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(toml::Value::Array(includes)) => includes,
+        Some(_) => return Err(anyhow!("`include` in {path:?} must be a list of paths")),
+    };
+
+    let mut merged = toml::Table::new();
+    for include in includes {
+        let toml::Value::String(include) = include else {
+            return Err(anyhow!("`include` in {path:?} must be a list of paths"));
+        };
+        let include = path.parent().unwrap_or(Path::new(".")).join(include);
+        merge_tables(&mut merged, load_table(&include, depth + 1)?);
+    }
+    merge_tables(
+        &mut merged,
+        expand_env_in_table(table, &|name| env::var(name).ok())?,
+    );
+    Ok(merged)
+}
+
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// Expansion works on parsed values rather than on the file text, so a variable can never inject
+// TOML syntax. Keys are expanded too, for tables keyed by output name like `monitors`.
+fn expand_env_in_table(
+    table: toml::Table,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<toml::Table> {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            Ok((
+                expand_env(&key, lookup)?,
+                expand_env_in_value(value, lookup)?,
+            ))
+        })
+        .collect()
+}
+
+fn expand_env_in_value(
+    value: toml::Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<toml::Value> {
+    Ok(match value {
+        toml::Value::String(value) => toml::Value::String(expand_env(&value, lookup)?),
+        toml::Value::Array(values) => toml::Value::Array(
+            values
+                .into_iter()
+                .map(|value| expand_env_in_value(value, lookup))
+                .collect::<Result<_>>()?,
+        ),
+        toml::Value::Table(table) => toml::Value::Table(expand_env_in_table(table, lookup)?),
+        value => value,
+    })
+}
+
+// Replaces ${NAME} with the variable's value; `$${` stays a literal `${`. An unset variable is an
+// error rather than an empty string, which would silently turn e.g. an output name into "".
+fn expand_env(value: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = escaped;
+        } else if let Some(reference) = rest.strip_prefix("${") {
+            let end = reference
+                .find('}')
+                .ok_or_else(|| anyhow!("Unterminated ${{ in {value:?}"))?;
+            let name = &reference[..end];
+            let Some(variable) = lookup(name) else {
+                return Err(anyhow!(
+                    "Environment variable {name} used in config is not set"
+                ));
+            };
+            expanded.push_str(&variable);
+            rest = &reference[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn parses_placement_rules() {
+        let config = Config::parse(
+            r#"
+            [[rules]]
+            class = "firefox"
+            group = 1
+            workspace = 2
+
+            [[rules]]
+            class = "Slack"
+            group = 0
+            workspace = 9
+            slot = 3
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules.len(), 2);
+        assert_eq!(config.rules[0].class, "firefox");
+        assert_eq!(config.rules[0].slot, None);
+        assert_eq!(config.rules[1].slot, Some(3));
+    }
+
+    #[test]
+    fn parses_hooks() {
+        let config = Config::parse(
+            r#"
+            [hooks]
+            group_changed = "~/bin/theme.sh"
+            group_state_file = "/tmp/hywoma-group"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.hooks.group_changed.as_deref(),
+            Some("~/bin/theme.sh")
+        );
+        assert_eq!(
+            config.hooks.group_state_file,
+            Some(PathBuf::from("/tmp/hywoma-group"))
+        );
+    }
+
+    #[test]
+    fn parses_top_level_behavior_settings() {
+        let config = Config::parse(
+            r#"
+            workspaces_per_slot = 5
+            follow_moves = true
+            single_monitor = "ignore"
+            command_socket = "/run/user/1000/hywoma.sock"
+            unmanaged_workspaces = [9, "obs"]
+
+            [bulk]
+            chunk_size = 10
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.workspaces_per_slot, Some(5));
+        assert!(config.follow_moves);
+        assert_eq!(config.single_monitor, SingleMonitor::Ignore);
+        assert_eq!(
+            config.command_socket,
+            Some(PathBuf::from("/run/user/1000/hywoma.sock"))
+        );
+        assert_eq!(
+            config.unmanaged_workspaces,
+            [
+                WorkspaceSelector::Id(9),
+                WorkspaceSelector::Name("obs".to_string())
+            ]
+        );
+        assert_eq!(
+            config.bulk,
+            BulkConfig {
+                chunk_size: 10,
+                pause_ms: 50
+            }
+        );
+    }
+
+    #[test]
+    fn looks_up_group_settings_by_id() {
+        let config = Config::parse(
+            r#"
+            [[groups]]
+            id = 2
+            audio_sink = "alsa_output.usb-headset"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config
+                .group(2)
+                .and_then(|group| group.audio_sink.as_deref()),
+            Some("alsa_output.usb-headset")
+        );
+        assert_eq!(config.group(0), None);
+    }
+
+    #[test]
+    fn groups_without_screen_filter_reset_to_identity_when_filters_are_used() {
+        let warm = ScreenFilter {
+            temperature: Some(4000),
+            ..ScreenFilter::default()
+        };
+        let mut config = Config::parse(
+            r#"
+            [[groups]]
+            id = 3
+            screen_filter = { temperature = 4000 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.screen_filter_for(3), Some(warm));
+        assert_eq!(config.screen_filter_for(0), Some(ScreenFilter::default()));
+
+        config.groups.clear();
+        assert_eq!(config.screen_filter_for(0), None);
+    }
+
+    #[test]
+    fn groups_without_notification_mode_use_the_default_mode() {
+        let mut config = Config::parse(
+            r#"
+            [[groups]]
+            id = 2
+            notification_mode = "do-not-disturb"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.notification_mode_for(2), Some("do-not-disturb"));
+        assert_eq!(config.notification_mode_for(0), Some("default"));
+
+        config.groups.clear();
+        assert_eq!(config.notification_mode_for(0), None);
+    }
+
+    #[test]
+    fn profiles_replace_top_level_settings() {
+        let config = Config::parse(
+            r#"
+            profile = "office"
+            monitors = { "eDP-1" = 2 }
+
+            [[rules]]
+            class = "firefox"
+            group = 1
+            workspace = 2
+
+            [profiles.office]
+            monitors = { "DP-1" = 1, "DP-2" = 3 }
+
+            [[profiles.office.groups]]
+            id = 1
+            name = "Meetings"
+
+            [profiles.home]
+            rules = []
+            "#,
+        )
+        .unwrap();
+
+        let office = config.active();
+        assert_eq!(office.profile.as_deref(), Some("office"));
+        assert_eq!(office.monitors.len(), 2);
+        assert_eq!(office.rules, config.rules);
+        assert_eq!(office.group(1).unwrap().name.as_deref(), Some("Meetings"));
+
+        let home = config.with_profile("home").unwrap();
+        assert_eq!(home.monitors, config.monitors);
+        assert!(home.rules.is_empty());
+
+        assert!(config.with_profile("cafe").is_err());
+    }
+
+    #[test]
+    fn expands_environment_references() {
+        let lookup = |name: &str| (name == "PRIMARY").then(|| "DP-1".to_string());
+
+        assert_eq!(expand_env("${PRIMARY}", &lookup).unwrap(), "DP-1");
+        assert_eq!(
+            expand_env("a $5 $${PRIMARY}", &lookup).unwrap(),
+            "a $5 ${PRIMARY}"
+        );
+        assert!(expand_env("${UNSET}", &lookup).is_err());
+        assert!(expand_env("${PRIMARY", &lookup).is_err());
+
+        let table: toml::Table = toml::from_str(r#"monitors = { "${PRIMARY}" = 1 }"#).unwrap();
+        let expanded = expand_env_in_table(table, &lookup).unwrap();
+        assert_eq!(expanded["monitors"]["DP-1"].as_integer(), Some(1));
+    }
+
+    #[test]
+    fn includes_are_overridden_by_the_including_file() {
+        let dir = std::env::temp_dir().join(format!("hywoma-config-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("machines")).unwrap();
+        fs::write(
+            dir.join("machines").join("laptop.toml"),
+            r#"
+            monitors = { "eDP-1" = 2, "HDMI-A-1" = 3 }
+            speech = { enabled = true }
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config.toml"),
+            r#"
+            include = ["machines/laptop.toml"]
+            monitors = { "eDP-1" = 1 }
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&dir.join("config.toml")).unwrap();
+
+        assert_eq!(config.monitors["eDP-1"], 1);
+        assert_eq!(config.monitors["HDMI-A-1"], 3);
+        assert!(config.speech.enabled);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn group_switch_sound_prefers_group_specific_cue() {
+        let config = Config::parse(
+            r#"
+            [sounds]
+            group_switch = "/sounds/default.oga"
+
+            [[groups]]
+            id = 1
+            switch_sound = "/sounds/work.oga"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.sounds.player, "paplay");
+        assert_eq!(
+            config.switch_sound_for(1),
+            Some(&PathBuf::from("/sounds/work.oga"))
+        );
+        assert_eq!(
+            config.switch_sound_for(0),
+            Some(&PathBuf::from("/sounds/default.oga"))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::config::Config;
+use crate::hyprland::{self, ClientInfo};
+use crate::state::{GroupId, State};
+
+// Windows of other groups show up on screen now and then: on a lingering slot after an
+// `--occupied-only` switch, or while they are adopted or migrated. Dimming them makes it obvious
+// they do not belong to the group at hand.
+#[derive(Debug, Default)]
+pub struct Dimmer {
+    // Address -> applied opacity, so unchanged windows are not dispatched again.
+    dimmed: BTreeMap<String, u8>,
+}
+
+impl Dimmer {
+    pub fn update(&mut self, config: &Config, state: &State) {
+        if !dims_anything(config) && self.dimmed.is_empty() {
+            return;
+        }
+        let (shown, clients) = match shown_windows() {
+            Ok(found) => found,
+            Err(err) => {
+                tracing::warn!("Failed to query windows to dim: {err:?}");
+                return;
+            }
+        };
+        let dispatches = self.plan(config, state, &shown, &clients);
+        if let Err(err) = hyprland::hyprctl_batch(config, &dispatches) {
+            tracing::warn!("Failed to dim windows of inactive groups: {err:?}");
+        }
+    }
+
+    // The setprop dispatches that bring the windows on `shown` workspaces to their opacity.
+    fn plan(
+        &mut self,
+        config: &Config,
+        state: &State,
+        shown: &HashSet<u64>,
+        clients: &[ClientInfo],
+    ) -> Vec<String> {
+        let mut wanted = BTreeMap::new();
+        for client in clients
+            .iter()
+            .filter(|client| shown.contains(&client.workspace_id))
+        {
+            if let Some(key) = state.key_for_workspace_id(client.workspace_id)
+                && key.group != state.active_group
+                && let Some(opacity) = inactive_opacity(config, state, key.group)
+            {
+                wanted.insert(client.address.clone(), opacity);
+            }
+        }
+        let present: HashSet<&str> = clients
+            .iter()
+            .map(|client| client.address.as_str())
+            .collect();
+        let mut dispatches = Vec::new();
+        for address in self.dimmed.keys() {
+            // Closed windows have nothing left to restore.
+            if !wanted.contains_key(address) && present.contains(address.as_str()) {
+                dispatches.extend(setprop(address, "unset"));
+            }
+        }
+        for (address, opacity) in &wanted {
+            if self.dimmed.get(address) != Some(opacity) {
+                dispatches.extend(setprop(
+                    address,
+                    &format!("{:.2}", f64::from(*opacity) / 100.0),
+                ));
+            }
+        }
+        self.dimmed = wanted;
+        dispatches
+    }
+}
+
+fn dims_anything(config: &Config) -> bool {
+    config.dim_inactive.is_some() || config.groups.iter().any(|group| group.dim.is_some())
+}
+
+// A group's own setting wins over the global one; subgroups share their parent's.
+fn inactive_opacity(config: &Config, state: &State, group: GroupId) -> Option<u8> {
+    let settings_group = state.settings_group(group);
+    config
+        .groups
+        .iter()
+        .find(|entry| entry.id == settings_group)
+        .and_then(|entry| entry.dim)
+        .or(config.dim_inactive)
+        .filter(|opacity| *opacity < 100)
+}
+
+fn shown_windows() -> Result<(HashSet<u64>, Vec<ClientInfo>)> {
+    let shown = hyprland::get_monitor_workspace_ids()?
+        .into_iter()
+        .map(|(_, workspace_id)| workspace_id)
+        .collect();
+    Ok((shown, hyprland::get_clients()?))
+}
+
+// Focused and unfocused opacity alike, the window may be focused while it is dimmed.
+fn setprop(address: &str, value: &str) -> [String; 2] {
+    ["alpha", "alphainactive"]
+        .map(|prop| format!("dispatch setprop address:0x{address} {prop} {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GroupConfig;
+    use crate::state::Slot;
+
+    fn client(address: &str, workspace_id: u64) -> ClientInfo {
+        ClientInfo {
+            address: address.to_string(),
+            class: "foot".to_string(),
+            title: String::new(),
+            workspace_id,
+            pid: 1,
+            floating: false,
+        }
+    }
+
+    #[test]
+    fn dims_shown_windows_of_other_groups_and_restores_them() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        let work = state.create_group("Work");
+        let active = state.workspace_id_for(state.active_group, 1, 1);
+        let lingering = state.workspace_id_for(work, 2, 1);
+        let config = Config {
+            dim_inactive: Some(60),
+            ..Config::default()
+        };
+        let shown = HashSet::from([active, lingering]);
+        let clients = [client("a", active), client("b", lingering)];
+        let mut dimmer = Dimmer::default();
+
+        assert_eq!(
+            dimmer.plan(&config, &state, &shown, &clients),
+            setprop("b", "0.60")
+        );
+        assert!(dimmer.plan(&config, &state, &shown, &clients).is_empty());
+
+        let config = Config {
+            dim_inactive: Some(60),
+            groups: vec![GroupConfig {
+                id: work,
+                dim: Some(80),
+                ..GroupConfig::default()
+            }],
+            ..Config::default()
+        };
+        assert_eq!(
+            dimmer.plan(&config, &state, &shown, &clients),
+            setprop("b", "0.80")
+        );
+
+        state.active_group = work;
+        assert_eq!(
+            dimmer.plan(&config, &state, &shown, &clients),
+            [setprop("b", "unset"), setprop("a", "0.60")].concat()
+        );
+        // A window that closed while dimmed is just forgotten.
+        assert!(dimmer.plan(&config, &state, &shown, &[]).is_empty());
+    }
+}
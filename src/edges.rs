@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::app::{Message, SlotTarget};
+use crate::hyprland::{self, MonitorRect};
+
+// Mouse navigation between monitors: resting the pointer at the left or right edge of a monitor
+// for `dwell_ms` selects the slot next to it, like `select_slot left|right`. Only edges with a
+// monitor beyond them count, so the outer edges stay free for hot corners and scrollbars.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeSwitchConfig {
+    pub enabled: bool,
+    pub dwell_ms: u64,
+    // Hyprland has no pointer events, so the position is polled.
+    pub poll_ms: u64,
+    // Distance from the edge, in layout pixels, that still counts as resting on it.
+    pub margin_px: i64,
+}
+
+impl Default for EdgeSwitchConfig {
+    fn default() -> Self {
+        EdgeSwitchConfig {
+            enabled: false,
+            dwell_ms: 400,
+            poll_ms: 100,
+            margin_px: 2,
+        }
+    }
+}
+
+// -1 for the left edge, 1 for the right one.
+fn edge_at(monitors: &[MonitorRect], position: (i64, i64), margin: i64) -> Option<i64> {
+    let monitor = monitors.iter().find(|monitor| monitor.contains(position))?;
+    let (x, _) = position;
+    if x <= monitor.x + margin && monitors.iter().any(|other| other.x < monitor.x) {
+        return Some(-1);
+    }
+    let right = monitor.x + monitor.width - 1;
+    if x >= right - margin && monitors.iter().any(|other| other.x > monitor.x) {
+        return Some(1);
+    }
+    None
+}
+
+#[derive(Debug, Default)]
+struct Dwell {
+    // The edge the pointer rests on, since when, and whether it already switched.
+    resting: Option<(i64, Instant, bool)>,
+}
+
+impl Dwell {
+    // Returns the direction to switch in once the pointer rested long enough. It has to leave the
+    // edge before the same edge switches again.
+    fn observe(&mut self, edge: Option<i64>, now: Instant, dwell: Duration) -> Option<i64> {
+        let Some(edge) = edge else {
+            self.resting = None;
+            return None;
+        };
+        match &mut self.resting {
+            Some((resting_edge, since, fired)) if *resting_edge == edge => {
+                if *fired || now.duration_since(*since) < dwell {
+                    return None;
+                }
+                *fired = true;
+                Some(edge)
+            }
+            _ => {
+                self.resting = Some((edge, now, false));
+                None
+            }
+        }
+    }
+}
+
+pub fn spawn(config: &EdgeSwitchConfig, tx: mpsc::Sender<Message>) {
+    if !config.enabled {
+        return;
+    }
+    let config = config.clone();
+    thread::spawn(move || {
+        let mut dwell = Dwell::default();
+        loop {
+            let edge = match hyprland::get_monitor_rects().and_then(|monitors| {
+                Ok(edge_at(
+                    &monitors,
+                    hyprland::get_cursor_position()?,
+                    config.margin_px,
+                ))
+            }) {
+                Ok(edge) => edge,
+                Err(err) => {
+                    tracing::warn!("Failed to read pointer position: {err:?}");
+                    None
+                }
+            };
+            if let Some(delta) =
+                dwell.observe(edge, Instant::now(), Duration::from_millis(config.dwell_ms))
+                && tx
+                    .send(Message::SelectSlot(SlotTarget::Relative(delta)))
+                    .is_err()
+            {
+                return;
+            }
+            thread::sleep(Duration::from_millis(config.poll_ms));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_inner_edges_switch_after_the_dwell() {
+        let monitor = |id, x| MonitorRect {
+            id,
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let monitors = [monitor(0, 0), monitor(1, 1920)];
+        assert_eq!(edge_at(&monitors, (0, 500), 2), None);
+        assert_eq!(edge_at(&monitors, (1918, 500), 2), Some(1));
+        assert_eq!(edge_at(&monitors, (1921, 500), 2), Some(-1));
+        assert_eq!(edge_at(&monitors, (3839, 500), 2), None);
+
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let dwell = Duration::from_millis(400);
+        let mut resting = Dwell::default();
+        assert_eq!(resting.observe(Some(1), at(0), dwell), None);
+        assert_eq!(resting.observe(Some(1), at(300), dwell), None);
+        assert_eq!(resting.observe(Some(1), at(400), dwell), Some(1));
+        assert_eq!(resting.observe(Some(1), at(900), dwell), None);
+        assert_eq!(resting.observe(None, at(1000), dwell), None);
+        assert_eq!(resting.observe(Some(1), at(1100), dwell), None);
+        assert_eq!(resting.observe(Some(1), at(1500), dwell), Some(1));
+    }
+}
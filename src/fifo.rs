@@ -0,0 +1,126 @@
+use anyhow::{Result, bail};
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+// Keeps the focus line flowing into a named pipe for bars that just `cat` one. Opening a FIFO for
+// writing blocks until someone reads it, so a writer thread does that instead of the main loop. It
+// keeps the pipe open between changes, so a reader sees one line per change instead of an EOF after
+// each, and a reader that shows up later starts with the current line.
+#[derive(Debug, Default)]
+pub struct FifoSink {
+    path: Option<PathBuf>,
+    writer: Option<mpsc::Sender<String>>,
+    line: String,
+}
+
+impl FifoSink {
+    pub fn update(&mut self, path: Option<&Path>, line: String) {
+        if self.path.as_deref() != path {
+            self.path = path.map(Path::to_path_buf);
+            // Dropping the old sender ends its writer thread.
+            self.writer = path.and_then(|path| match create_fifo(path) {
+                Ok(()) => Some(spawn_writer(path.to_path_buf())),
+                Err(err) => {
+                    tracing::warn!("Failed to create FIFO {path:?}: {err:?}");
+                    None
+                }
+            });
+            self.line.clear();
+        }
+        if line == self.line {
+            return;
+        }
+        if let Some(writer) = &self.writer
+            && writer.send(line.clone()).is_err()
+        {
+            self.writer = None;
+        }
+        self.line = line;
+    }
+}
+
+fn spawn_writer(path: PathBuf) -> mpsc::Sender<String> {
+    let (tx, rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        let Ok(mut line) = rx.recv() else {
+            return;
+        };
+        loop {
+            let mut pipe = match OpenOptions::new().write(true).open(&path) {
+                Ok(pipe) => pipe,
+                Err(err) => {
+                    tracing::warn!("Failed to open FIFO {path:?}: {err:?}");
+                    return;
+                }
+            };
+            // Whatever changed while waiting for a reader, only the latest line matters.
+            while let Ok(newer) = rx.try_recv() {
+                line = newer;
+            }
+            loop {
+                // The reader went away. Reopening waits for the next one.
+                if pipe.write_all(format!("{line}\n").as_bytes()).is_err() {
+                    break;
+                }
+                match rx.recv() {
+                    Ok(newer) => line = newer,
+                    Err(_) => return,
+                }
+            }
+        }
+    });
+    tx
+}
+
+fn create_fifo(path: &Path) -> Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => return Ok(()),
+        Ok(_) => bail!("{path:?} exists and is not a FIFO"),
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: c_path is a valid NUL-terminated string for the duration of the call.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn a_late_reader_starts_with_the_current_line() {
+        let dir = std::env::temp_dir().join(format!("hywoma-fifo-test-{}", std::process::id()));
+        let path = dir.join("focus");
+        let mut sink = FifoSink::default();
+
+        sink.update(Some(&path), "G0 · M1 · W1".to_string());
+        sink.update(Some(&path), "G0 · M1 · W2".to_string());
+        assert!(fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        let mut reader = BufReader::new(fs::File::open(&path).unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "G0 · M1 · W2\n");
+
+        sink.update(Some(&path), "G0 · M1 · W2".to_string());
+        sink.update(Some(&path), "G1 · M1 · W1".to_string());
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "G1 · M1 · W1\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
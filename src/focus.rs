@@ -0,0 +1,97 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::app::Message;
+use crate::hooks;
+use crate::state::GroupId;
+
+// `hywoma focus start 50m`: a pomodoro on the group model. Until the timer runs out, commands that
+// would leave the group are refused unless given `--force`, and scheduled switches are skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocusConfig {
+    // Gets the notification text as its only argument.
+    pub notify_command: String,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        FocusConfig {
+            notify_command: "notify-send".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusSession {
+    pub group: GroupId,
+    pub group_name: String,
+    pub until: Instant,
+}
+
+impl FocusSession {
+    pub fn minutes_left(&self, now: Instant) -> u64 {
+        self.until
+            .saturating_duration_since(now)
+            .as_secs()
+            .div_ceil(60)
+    }
+}
+
+// `50m`, `1h`, `90s`, or bare minutes.
+pub fn parse_duration(arg: &str) -> Result<Duration> {
+    let invalid = || anyhow!("Invalid duration {arg:?}, expected e.g. 25m, 1h or 90s");
+    let (number, unit) = match arg.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => arg.split_at(index),
+        None => (arg, "m"),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    if secs == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+// Commands that take the user out of the focused group. Moving a window elsewhere is still fine.
+pub fn leaves_group(msg: &Message) -> bool {
+    if let Message::NamedGroup(_, msg) = msg {
+        return leaves_group(msg);
+    }
+    matches!(
+        msg,
+        Message::SwitchGroup(_)
+            | Message::SwitchGroupRelative(_)
+            | Message::SwitchGroupOccupiedOnly(_)
+            | Message::CreateGroup(..)
+            | Message::EnterSubgroup(_)
+            | Message::LeaveSubgroup
+    )
+}
+
+pub fn notify(config: &FocusConfig, text: &str) {
+    tracing::info!("{text}");
+    hooks::spawn(&config.notify_command, &["hywoma", text]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("50m").unwrap(), Duration::from_secs(50 * 60));
+        assert_eq!(parse_duration("25").unwrap(), Duration::from_secs(25 * 60));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(60 * 60));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("5d").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, ScreenFilter};
+use crate::state::{GroupId, State, WorkspaceKey};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    // Shell command run whenever the active group (or its name) changes, with HYWOMA_GROUP and
+    // HYWOMA_GROUP_NAME set. Meant for theme/palette switching scripts.
+    pub group_changed: Option<String>,
+    // File that always holds "<group> <name>" of the active group, for tools that poll instead of
+    // being notified.
+    pub group_state_file: Option<PathBuf>,
+    // URL the focus (as printed by `hywoma watch`) is POSTed to on every workspace or group change,
+    // e.g. for home automation that changes the desk lighting per group.
+    pub webhook_url: Option<String>,
+    // The same payload, published with mosquitto_pub, e.g. for Home Assistant dashboards.
+    pub mqtt: Option<MqttConfig>,
+    // Named pipe that gets the focus as a line of `fifo_format` (see `hywoma waybar`) on every
+    // change, created if missing. For minimal bars and dwmblocks-style scripts.
+    pub fifo: Option<PathBuf>,
+    pub fifo_format: Option<String>,
+    // Shell commands run once the daemon is ready, and when it shuts down with
+    // HYWOMA_SHUTDOWN_REASON set, e.g. to set the wallpaper or tell the bar. Failures are only
+    // logged. A `restart-server` successor runs `startup` again, the daemon it replaces no
+    // `shutdown`.
+    pub startup: Option<String>,
+    pub shutdown: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    // Retained, a dashboard that subscribes later still gets the current focus.
+    pub retain: bool,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: "localhost".to_string(),
+            port: 1883,
+            topic: "hywoma/focus".to_string(),
+            retain: true,
+        }
+    }
+}
+
+fn shell(command: &str, envs: &[(&str, String)]) -> Command {
+    let mut child = Command::new("sh");
+    child
+        .arg("-c")
+        .arg(command)
+        .envs(envs.iter().map(|(key, value)| (*key, value)));
+    child
+}
+
+pub fn spawn_shell(command: &str, envs: &[(&str, String)]) {
+    spawn_detached(shell(command, envs), command);
+}
+
+// Waited for, unlike the other hooks: whatever is left running once the daemon exits may be
+// stopped along with it, by systemd for one. A hook still running after `timeout` is killed.
+pub fn run_shell(command: &str, envs: &[(&str, String)], timeout: Duration) {
+    let mut child = match shell(command, envs).stdin(Stdio::null()).spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            tracing::warn!("Failed to run hook {command:?}: {err:?}");
+            return;
+        }
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    tracing::warn!("Hook {command:?} exited with {status}");
+                }
+                return;
+            }
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                tracing::warn!("Hook {command:?} did not finish within {timeout:?}, killing it");
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to wait for hook {command:?}: {err:?}");
+                return;
+            }
+        }
+    }
+}
+
+pub fn spawn(program: &str, args: &[&str]) {
+    let mut child = Command::new(program);
+    child.args(args);
+    spawn_detached(child, &format!("{program} {}", args.join(" ")));
+}
+
+fn spawn_detached(mut child: Command, command: &str) {
+    child.stdin(Stdio::null());
+    // Hooks run detached from the main loop: a slow theme script must never delay workspace
+    // switching. A waiter thread reaps the child so finished hooks do not linger as zombies.
+    match child.spawn() {
+        Ok(mut child) => {
+            let command = command.to_string();
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => {
+                    tracing::warn!("Hook {command:?} exited with {status}");
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Failed to wait for hook {command:?}: {err:?}"),
+            });
+        }
+        Err(err) => tracing::warn!("Failed to run hook {command:?}: {err:?}"),
+    }
+}
+
+// curl keeps the daemon free of an HTTP stack, and a slow endpoint only holds up its own process.
+pub fn post_webhook(config: &Config, payload: &str) {
+    let Some(url) = &config.hooks.webhook_url else {
+        return;
+    };
+    spawn(
+        "curl",
+        &[
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--max-time",
+            "5",
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            payload,
+            url,
+        ],
+    );
+}
+
+pub fn publish_mqtt(config: &Config, payload: &str) {
+    let Some(mqtt) = &config.hooks.mqtt else {
+        return;
+    };
+    let port = mqtt.port.to_string();
+    let mut args = vec![
+        "-h",
+        mqtt.host.as_str(),
+        "-p",
+        &port,
+        "-t",
+        mqtt.topic.as_str(),
+        "-m",
+        payload,
+    ];
+    if mqtt.retain {
+        args.push("-r");
+    }
+    spawn("mosquitto_pub", &args);
+}
+
+fn apply_screen_filter(filter: &ScreenFilter) {
+    if let Some(command) = &filter.command {
+        spawn_shell(command, &[]);
+        return;
+    }
+    if filter.temperature.is_none() && filter.gamma.is_none() {
+        spawn("hyprctl", &["hyprsunset", "identity"]);
+        return;
+    }
+    if let Some(temperature) = filter.temperature {
+        spawn(
+            "hyprctl",
+            &["hyprsunset", "temperature", &temperature.to_string()],
+        );
+    }
+    if let Some(gamma) = filter.gamma {
+        spawn("hyprctl", &["hyprsunset", "gamma", &gamma.to_string()]);
+    }
+}
+
+// `settings` is the group whose `[[groups]]` entry applies, the parent for a sub-group.
+pub fn group_changed(config: &Config, group: GroupId, name: &str, settings: GroupId) {
+    if let Some(path) = &config.hooks.group_state_file {
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, format!("{group} {name}\n")));
+        if let Err(err) = written {
+            tracing::warn!("Failed to write group state file {path:?}: {err:?}");
+        }
+    }
+
+    if let Some(sink) = config
+        .group(settings)
+        .and_then(|group| group.audio_sink.as_deref())
+    {
+        // pactl talks to both PulseAudio and PipeWire's pulse server.
+        spawn("pactl", &["set-default-sink", sink]);
+    }
+
+    if let Some(filter) = config.screen_filter_for(settings) {
+        apply_screen_filter(&filter);
+    }
+
+    if let Some(mode) = config.notification_mode_for(settings) {
+        spawn_shell(
+            &config.notifications.command,
+            &[("HYWOMA_NOTIFICATION_MODE", mode.to_string())],
+        );
+    }
+
+    if let Some(command) = &config.hooks.group_changed {
+        spawn_shell(
+            command,
+            &[
+                ("HYWOMA_GROUP", group.to_string()),
+                ("HYWOMA_GROUP_NAME", name.to_string()),
+            ],
+        );
+    }
+}
+
+// Audio feedback for a user-visible context change. Unlike group_changed this is not run at
+// startup: restoring state is not a switch the user should hear.
+pub fn announce_switch(config: &Config, state: &State, previous: WorkspaceKey, view: WorkspaceKey) {
+    let group_switched = view.group != previous.group;
+    let sound = if group_switched {
+        config.switch_sound_for(state.settings_group(view.group))
+    } else {
+        config.sounds.workspace_switch.as_ref()
+    };
+    if let Some(sound) = sound {
+        spawn(&config.sounds.player, &[&sound.to_string_lossy()]);
+    }
+
+    if config.speech.enabled {
+        let text = announcement(state, previous, view);
+        spawn(&config.speech.command, &[&text]);
+    }
+}
+
+// Only mention what changed, so repeated workspace navigation stays short: "workspace three"
+// instead of the whole group and monitor every time. Numbers are left as digits, speech
+// synthesizers read them out naturally.
+fn announcement(state: &State, previous: WorkspaceKey, view: WorkspaceKey) -> String {
+    let mut parts = Vec::new();
+    if view.group != previous.group {
+        match state.groups.get(&view.group) {
+            Some(group) if group.name != format!("Group {}", view.group) => {
+                parts.push(format!("group {}", group.name));
+            }
+            _ => parts.push(format!("group {}", view.group)),
+        }
+    }
+    if view.slot != previous.slot {
+        match state.slots.get(&view.slot) {
+            Some(slot) => parts.push(format!("{} monitor", slot.label)),
+            None => parts.push(format!("slot {}", view.slot)),
+        }
+    }
+    parts.push(format!("workspace {}", view.visible));
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(group: GroupId, slot: u64, visible: u64) -> WorkspaceKey {
+        WorkspaceKey {
+            group,
+            slot,
+            visible,
+        }
+    }
+
+    #[test]
+    fn announcement_mentions_only_what_changed() {
+        let mut state = State::new([
+            crate::state::Slot::new(1, "u", "left"),
+            crate::state::Slot::new(2, "i", "middle"),
+        ]);
+        let work = state.create_group("Work");
+        state.ensure_group(7, "Group 7");
+
+        assert_eq!(
+            announcement(&state, key(0, 1, 1), key(0, 1, 3)),
+            "workspace 3"
+        );
+        assert_eq!(
+            announcement(&state, key(0, 1, 1), key(work, 2, 1)),
+            "group Work, middle monitor, workspace 1"
+        );
+        assert_eq!(
+            announcement(&state, key(0, 1, 1), key(7, 1, 2)),
+            "group 7, workspace 2"
+        );
+    }
+
+    #[test]
+    fn writes_group_state_file() {
+        let dir = std::env::temp_dir().join(format!("hywoma-hooks-test-{}", std::process::id()));
+        let path = dir.join("nested").join("group");
+        let config = Config {
+            hooks: HooksConfig {
+                group_changed: None,
+                group_state_file: Some(path.clone()),
+                ..HooksConfig::default()
+            },
+            ..Config::default()
+        };
+
+        group_changed(&config, 2, "Work stuff", 2);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2 Work stuff\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn waits_for_blocking_hooks_but_not_forever() {
+        let path = std::env::temp_dir().join(format!("hywoma-hook-test-{}", std::process::id()));
+        run_shell(
+            &format!("echo \"$HYWOMA_SHUTDOWN_REASON\" > {}", path.display()),
+            &[("HYWOMA_SHUTDOWN_REASON", "asked to terminate".to_string())],
+            Duration::from_secs(5),
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "asked to terminate\n");
+        fs::remove_file(&path).unwrap();
+
+        let started = Instant::now();
+        run_shell("sleep 5", &[], Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
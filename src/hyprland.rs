@@ -1,13 +1,48 @@
 use anyhow::{Result, anyhow};
 use serde::Deserialize;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::os::unix::net::UnixStream;
-use std::path::PathBuf;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::app::Message;
+use crate::config::Config;
+use crate::progress;
+use crate::simulate;
+use crate::state::IdLayout;
+use crate::systemd;
+use crate::thumbnails;
 
+// Every dispatch blocks the main loop until Hyprland answers, so a slow compositor IPC round trip
+// shows up directly as workspace switching lag. Calls slower than this are logged as warnings.
+const SLOW_HYPRCTL_THRESHOLD: Duration = Duration::from_millis(50);
+// Past this a request is given up on. The watchdog has long paused commands by then, and a main
+// loop stuck in one dispatch would not get to see that Hyprland recovered.
+const HYPRCTL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// `hywoma events --raw` clients. They live here rather than in the main loop so raw lines are
+// relayed straight from the reader thread, including the ones hywoma ignores.
+static RAW_SUBSCRIBERS: Mutex<Vec<UnixStream>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // Whether the message being handled sent Hyprland a dispatch. Only then is there anything for
+    // `verify_dispatches` to check.
+    static DISPATCHED: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn take_dispatched() -> bool {
+    DISPATCHED.take()
+}
+
+// The hand-rolled socket code below is the default backend. Building with the `hyprland-rs`
+// feature routes queries, dispatches and events through hyprland-rs instead, except in simulation,
+// which answers the socket protocol itself.
 #[derive(Debug)]
 pub enum HyprlandSocketKind {
     Command,
@@ -29,14 +64,14 @@ pub struct MonitorInfo {
 }
 
 impl Workspace {
-    pub fn from_id(mut id: u64) -> Self {
-        // Legacy encoded workspace layout. New opaque IDs (1000+) should not be decoded this way
-        // unless we are explicitly in the compatibility path.
-        id -= 1;
-        let workspace = id % 10 + 1;
-        id /= 10;
-        let monitor = id % 10 + 1;
-        id /= 10;
+    // Legacy encoded workspace layout, digits in the radices of `layout`. New opaque IDs should not
+    // be decoded this way unless we are explicitly in the compatibility path.
+    pub fn from_id(mut id: u64, layout: IdLayout) -> Self {
+        id = id.saturating_sub(1);
+        let workspace = id % layout.workspaces + 1;
+        id /= layout.workspaces;
+        let monitor = id % layout.monitors + 1;
+        id /= layout.monitors;
         let group = id;
         Workspace {
             workspace,
@@ -44,13 +79,24 @@ impl Workspace {
             group,
         }
     }
+
     #[cfg(test)]
-    pub fn to_id(&self) -> u64 {
-        (self.workspace - 1) + 10 * (self.monitor - 1) + 100 * self.group + 1
+    fn to_id(self, layout: IdLayout) -> u64 {
+        (self.workspace - 1)
+            + layout.workspaces * (self.monitor - 1)
+            + layout.workspaces * layout.monitors * self.group
+            + 1
     }
 }
 
 pub fn get_monitors() -> Result<Vec<MonitorInfo>> {
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        let mut monitors = crate::hyprland_rs::get_monitors()?;
+        monitors.sort_unstable_by_key(|m| m.x);
+        return Ok(monitors);
+    }
+
     #[derive(Debug, Deserialize)]
     struct MonitorEntry {
         id: u64,
@@ -72,35 +118,355 @@ pub fn get_monitors() -> Result<Vec<MonitorInfo>> {
         .collect())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+impl Orientation {
+    // Odd Hyprland transforms rotate the mode by 90 or 270 degrees.
+    fn from_mode(width: i64, height: i64, transform: u8) -> Self {
+        let (width, height) = if transform % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        if height > width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+pub fn get_monitor_orientations() -> Result<HashMap<u64, Orientation>> {
+    #[derive(Debug, Deserialize)]
+    struct MonitorEntry {
+        id: u64,
+        width: i64,
+        height: i64,
+        #[serde(default)]
+        transform: u8,
+    }
+
+    let monitors_json = hyprctl("-j/monitors")?;
+    let parsed: Vec<MonitorEntry> = serde_json::from_str(&monitors_json)?;
+    Ok(parsed
+        .into_iter()
+        .map(|m| (m.id, Orientation::from_mode(m.width, m.height, m.transform)))
+        .collect())
+}
+
+// A monitor's area in Hyprland's layout coordinates, as used by `movecursor` and `cursorpos`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorRect {
+    pub id: u64,
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl MonitorRect {
+    pub fn contains(&self, (x, y): (i64, i64)) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+pub fn get_monitor_rects() -> Result<Vec<MonitorRect>> {
+    fn default_scale() -> f64 {
+        1.0
+    }
+    #[derive(Debug, Deserialize)]
+    struct MonitorEntry {
+        id: u64,
+        x: i64,
+        y: i64,
+        width: i64,
+        height: i64,
+        #[serde(default = "default_scale")]
+        scale: f64,
+        #[serde(default)]
+        transform: u8,
+    }
+
+    let monitors_json = hyprctl("-j/monitors")?;
+    let parsed: Vec<MonitorEntry> = serde_json::from_str(&monitors_json)?;
+    Ok(parsed
+        .into_iter()
+        .map(|m| {
+            // width/height are in physical pixels of the unrotated mode; the layout uses scaled
+            // sizes, and odd transforms rotate by 90 degrees.
+            let (width, height) = if m.transform % 2 == 1 {
+                (m.height, m.width)
+            } else {
+                (m.width, m.height)
+            };
+            let scale = if m.scale > 0.0 { m.scale } else { 1.0 };
+            MonitorRect {
+                id: m.id,
+                x: m.x,
+                y: m.y,
+                width: (width as f64 / scale) as i64,
+                height: (height as f64 / scale) as i64,
+            }
+        })
+        .collect())
+}
+
+pub fn get_monitor_center(monitor_id: u64) -> Result<Option<(i64, i64)>> {
+    Ok(get_monitor_rects()?
+        .into_iter()
+        .find(|m| m.id == monitor_id)
+        .map(|m| (m.x + m.width / 2, m.y + m.height / 2)))
+}
+
+pub fn get_cursor_position() -> Result<(i64, i64)> {
+    #[derive(Debug, Deserialize)]
+    struct CursorPosition {
+        x: i64,
+        y: i64,
+    }
+
+    let position: CursorPosition = serde_json::from_str(&hyprctl("-j/cursorpos")?)?;
+    Ok((position.x, position.y))
+}
+
+// (monitor ID, active workspace ID) for every monitor, for comparing reality with the model.
+pub fn get_monitor_workspace_ids() -> Result<Vec<(u64, u64)>> {
+    #[derive(Debug, Deserialize)]
+    struct ActiveWorkspace {
+        id: i64,
+    }
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct MonitorEntry {
+        id: u64,
+        active_workspace: ActiveWorkspace,
+    }
+
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_monitor_workspace_ids();
+    }
+
+    let monitors_json = hyprctl("-j/monitors")?;
+    let parsed: Vec<MonitorEntry> = serde_json::from_str(&monitors_json)?;
+    Ok(parsed
+        .into_iter()
+        .filter_map(|m| Some((m.id, u64::try_from(m.active_workspace.id).ok()?)))
+        .collect())
+}
+
 pub fn get_active_workspace_id() -> Result<u64> {
-    let activeworkspace_json = hyprctl("-j/activeworkspace")?;
-    let v: serde_json::Value = serde_json::from_str(&activeworkspace_json)?;
-    Ok(v["id"].as_u64().unwrap())
+    Ok(get_active_workspace()?.0)
 }
 
-pub fn get_active_workspace_monitor_id() -> Result<Option<u64>> {
+// Active workspace ID and the ID of the monitor showing it.
+pub fn get_active_workspace() -> Result<(u64, Option<u64>)> {
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_active_workspace();
+    }
     let activeworkspace_json = hyprctl("-j/activeworkspace")?;
     let v: serde_json::Value = serde_json::from_str(&activeworkspace_json)?;
-    Ok(v["monitorID"].as_u64())
+    Ok((v["id"].as_u64().unwrap(), v["monitorID"].as_u64()))
+}
+
+// Address of the focused window, without the 0x prefix to match the event socket.
+pub fn get_active_window_address() -> Result<Option<String>> {
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_active_window_address();
+    }
+    let activewindow_json = hyprctl("-j/activewindow")?;
+    let v: serde_json::Value = serde_json::from_str(&activewindow_json)?;
+    Ok(v["address"]
+        .as_str()
+        .map(|address| address.trim_start_matches("0x").to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    // Without the 0x prefix to match the event socket.
+    pub address: String,
+    pub class: String,
+    pub title: String,
+    pub workspace_id: u64,
+    pub pid: i32,
+    pub floating: bool,
+}
+
+// Every window on a regular workspace.
+pub fn get_clients() -> Result<Vec<ClientInfo>> {
+    #[derive(Debug, Deserialize)]
+    struct ClientWorkspace {
+        id: i64,
+    }
+    #[derive(Debug, Deserialize)]
+    struct ClientEntry {
+        address: String,
+        class: String,
+        title: String,
+        workspace: ClientWorkspace,
+        pid: i32,
+        floating: bool,
+    }
+
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_clients();
+    }
+
+    let clients_json = hyprctl("-j/clients")?;
+    let parsed: Vec<ClientEntry> = serde_json::from_str(&clients_json)?;
+    Ok(parsed
+        .into_iter()
+        .filter_map(|client| {
+            Some(ClientInfo {
+                address: client.address.trim_start_matches("0x").to_string(),
+                class: client.class,
+                title: client.title,
+                workspace_id: u64::try_from(client.workspace.id).ok()?,
+                pid: client.pid,
+                floating: client.floating,
+            })
+        })
+        .collect())
+}
+
+// Address of every window, including those on special workspaces, without 0x.
+pub fn get_window_addresses() -> Result<HashSet<String>> {
+    #[derive(Debug, Deserialize)]
+    struct ClientEntry {
+        address: String,
+    }
+
+    let clients_json = hyprctl("-j/clients")?;
+    let parsed: Vec<ClientEntry> = serde_json::from_str(&clients_json)?;
+    Ok(parsed
+        .into_iter()
+        .map(|client| client.address.trim_start_matches("0x").to_string())
+        .collect())
+}
+
+// Workspace ID of every window on a regular workspace, keyed by address without 0x.
+pub fn get_window_workspace_ids() -> Result<HashMap<String, u64>> {
+    Ok(get_clients()?
+        .into_iter()
+        .map(|client| (client.address, client.workspace_id))
+        .collect())
+}
+
+#[derive(Debug)]
+pub struct StartupSnapshot {
+    pub monitors: Vec<MonitorInfo>,
+    pub active_workspace_id: u64,
+    pub active_monitor_id: Option<u64>,
+    pub workspace_ids: Vec<u64>,
+}
+
+// The startup queries are independent and every hyprctl request opens its own socket connection,
+// so they run concurrently. Startup then waits for the slowest query instead of all of them.
+pub fn get_startup_snapshot() -> Result<StartupSnapshot> {
+    thread::scope(|scope| {
+        let monitors = scope.spawn(get_monitors);
+        let workspace_ids = scope.spawn(get_workspace_ids);
+        let (active_workspace_id, active_monitor_id) = get_active_workspace()?;
+        Ok(StartupSnapshot {
+            monitors: monitors.join().expect("Monitor query panicked")?,
+            active_workspace_id,
+            active_monitor_id,
+            workspace_ids: workspace_ids.join().expect("Workspace query panicked")?,
+        })
+    })
 }
 
 pub fn get_workspace_ids() -> Result<Vec<u64>> {
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_workspace_ids();
+    }
+
+    parse_workspace_ids(&hyprctl("-j/workspaces")?)
+}
+
+// Special workspaces have negative IDs and are never managed by hywoma.
+pub fn parse_workspace_ids(workspaces_json: &str) -> Result<Vec<u64>> {
     #[derive(Debug, Deserialize)]
     struct WorkspaceEntry {
-        id: u64,
+        id: i64,
+    }
+
+    let parsed: Vec<WorkspaceEntry> = serde_json::from_str(workspaces_json)?;
+    Ok(parsed
+        .into_iter()
+        .filter_map(|workspace| u64::try_from(workspace.id).ok())
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceInfo {
+    pub id: u64,
+    // Numbered workspaces are named after their number, unless a workspace rule or
+    // `renameworkspace` named them.
+    pub name: String,
+    pub windows: u64,
+}
+
+// Every regular workspace.
+pub fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    #[derive(Debug, Deserialize)]
+    struct WorkspaceEntry {
+        id: i64,
+        name: String,
+        windows: u64,
+    }
+
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_workspaces();
+    }
+
+    let workspaces_json = hyprctl("-j/workspaces")?;
+    let parsed: Vec<WorkspaceEntry> = serde_json::from_str(&workspaces_json)?;
+    Ok(parsed
+        .into_iter()
+        .filter_map(|workspace| {
+            Some(WorkspaceInfo {
+                id: u64::try_from(workspace.id).ok()?,
+                name: workspace.name,
+                windows: workspace.windows,
+            })
+        })
+        .collect())
+}
+
+// Window count per workspace ID, to tell empty workspaces from occupied ones.
+pub fn get_workspace_window_counts() -> Result<HashMap<u64, u64>> {
+    #[derive(Debug, Deserialize)]
+    struct WorkspaceEntry {
+        id: i64,
+        windows: u64,
+    }
+
+    #[cfg(feature = "hyprland-rs")]
+    if !simulate::is_active() {
+        return crate::hyprland_rs::get_workspace_window_counts();
     }
 
     let workspaces_json = hyprctl("-j/workspaces")?;
     let parsed: Vec<WorkspaceEntry> = serde_json::from_str(&workspaces_json)?;
-    Ok(parsed.into_iter().map(|workspace| workspace.id).collect())
+    Ok(parsed
+        .into_iter()
+        .filter_map(|workspace| Some((u64::try_from(workspace.id).ok()?, workspace.windows)))
+        .collect())
 }
 
 fn get_socket_path(kind: HyprlandSocketKind) -> Result<PathBuf> {
-    let xdg_runtime_dir = env::var("XDG_RUNTIME_DIR")?;
-    let hyprland_instance_signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")?;
-    let path = PathBuf::from(xdg_runtime_dir)
-        .join("hypr")
-        .join(hyprland_instance_signature)
+    let path = instances_dir()?
+        .join(instance_signature()?)
         .join(match kind {
             HyprlandSocketKind::Command => ".socket.sock",
             HyprlandSocketKind::Event => ".socket2.sock",
@@ -108,29 +474,187 @@ fn get_socket_path(kind: HyprlandSocketKind) -> Result<PathBuf> {
     Ok(path)
 }
 
+fn instances_dir() -> Result<PathBuf> {
+    Ok(PathBuf::from(env::var("XDG_RUNTIME_DIR")?).join("hypr"))
+}
+
+// The instance hywoma talks to. It starts as HYPRLAND_INSTANCE_SIGNATURE, but a restarted Hyprland
+// comes back under a new signature that the environment of a running daemon never learns, so the
+// event reader replaces it with the newest instance when it reconnects.
+static INSTANCE_SIGNATURE: Mutex<Option<String>> = Mutex::new(None);
+
+fn instance_signature() -> Result<String> {
+    if let Some(signature) = INSTANCE_SIGNATURE.lock().unwrap().clone() {
+        return Ok(signature);
+    }
+    Ok(env::var("HYPRLAND_INSTANCE_SIGNATURE")?)
+}
+
+// The instance whose event socket was created last.
+fn newest_instance(instances_dir: &Path) -> Option<String> {
+    fs::read_dir(instances_dir)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let created = entry
+                .path()
+                .join(".socket2.sock")
+                .metadata()
+                .ok()?
+                .modified()
+                .ok()?;
+            Some((created, entry.file_name().into_string().ok()?))
+        })
+        .max()
+        .map(|(_, signature)| signature)
+}
+
+#[cfg_attr(feature = "hyprland-rs", allow(dead_code))]
+fn follow_newest_instance() {
+    let Some(newest) = instances_dir().ok().and_then(|dir| newest_instance(&dir)) else {
+        return;
+    };
+    if instance_signature().ok().as_ref() != Some(&newest) {
+        tracing::info!("Following Hyprland to instance {newest}");
+        *INSTANCE_SIGNATURE.lock().unwrap() = Some(newest);
+    }
+}
+
+// Hyprland drops the event socket when it restarts, and occasionally on a reload. The reader
+// reconnects with a growing delay, and gives up (taking the daemon down) only once the socket has
+// been gone for RECONNECT_GIVE_UP, e.g. because the session ended. Each retry looks for the newest
+// instance first, so a restarted Hyprland is picked up under its new signature. hyprland-rs reads
+// the signature from the environment itself, so with that feature only socket hiccups are covered.
+const RECONNECT_FIRST_DELAY: Duration = Duration::from_millis(250);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(8);
+const RECONNECT_GIVE_UP: Duration = Duration::from_secs(120);
+
 pub fn event_reader(tx: mpsc::Sender<Message>) -> Result<()> {
+    if simulate::is_active() {
+        // The simulated compositor pushes its events straight into the main loop channel.
+        simulate::subscribe(tx);
+        systemd::notify_ready();
+        return Ok(());
+    }
+    let mut delay = RECONNECT_FIRST_DELAY;
+    let mut lost_since: Option<Instant> = None;
+    loop {
+        let connected_at = Instant::now();
+        let resync = lost_since.is_some();
+        #[cfg(feature = "hyprland-rs")]
+        let result = crate::hyprland_rs::event_reader(tx.clone(), resync);
+        #[cfg(not(feature = "hyprland-rs"))]
+        let result = socket_event_reader(&tx, resync);
+        let err = result
+            .err()
+            .unwrap_or_else(|| anyhow!("Hyprland closed its event socket"));
+        if err.is::<mpsc::SendError<Message>>() {
+            // The main loop is gone, there is nobody to reconnect for.
+            return Err(err);
+        }
+        // A connection that lasted a while was not part of the same outage.
+        if connected_at.elapsed() >= RECONNECT_MAX_DELAY {
+            lost_since = None;
+            delay = RECONNECT_FIRST_DELAY;
+        }
+        let lost_since = *lost_since.get_or_insert_with(Instant::now);
+        if lost_since.elapsed() >= RECONNECT_GIVE_UP {
+            return Err(err.context(format!(
+                "Hyprland event socket gone for {:?}",
+                lost_since.elapsed()
+            )));
+        }
+        tracing::warn!("Lost the Hyprland event socket, reconnecting in {delay:?}: {err:?}");
+        thread::sleep(delay);
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+        #[cfg(not(feature = "hyprland-rs"))]
+        follow_newest_instance();
+    }
+}
+
+// Hyprland reports a single switch as both focusedmonv2 and workspacev2. The second one carries
+// nothing new unless it names a monitor the first did not, so readers drop it before it reaches
+// the main loop and triggers another round of state updates and subscriber broadcasts.
+#[derive(Debug, Default)]
+pub struct ActiveWorkspaceFilter {
+    last: Option<(u64, Option<String>)>,
+}
+
+impl ActiveWorkspaceFilter {
+    pub fn is_new(&mut self, workspace_id: u64, monitor_name: Option<&str>) -> bool {
+        if let Some((last_id, last_monitor)) = &mut self.last
+            && *last_id == workspace_id
+        {
+            return match monitor_name {
+                Some(name) if last_monitor.as_deref() != Some(name) => {
+                    *last_monitor = Some(name.to_string());
+                    true
+                }
+                _ => false,
+            };
+        }
+        self.last = Some((workspace_id, monitor_name.map(str::to_string)));
+        true
+    }
+
+    // A destroyed workspace's ID can come back, and focusing it then is a real change.
+    pub fn forget(&mut self, workspace_id: u64) {
+        if self
+            .last
+            .as_ref()
+            .is_some_and(|(id, _)| *id == workspace_id)
+        {
+            self.last = None;
+        }
+    }
+}
+
+// After a reconnect, everything that happened while disconnected is unknown, so the main loop is
+// told to re-read monitors, workspaces and the active workspace from scratch.
+#[cfg_attr(feature = "hyprland-rs", allow(dead_code))]
+fn socket_event_reader(tx: &mpsc::Sender<Message>, resync: bool) -> Result<()> {
     let path = get_socket_path(HyprlandSocketKind::Event)?;
     let stream = UnixStream::connect(path)?;
+    if resync {
+        tracing::info!("Reconnected to the Hyprland event socket");
+        tx.send(Message::Sync)?;
+    } else {
+        systemd::notify_ready();
+    }
     let reader = BufReader::new(stream);
+    let mut active_workspaces = ActiveWorkspaceFilter::default();
 
     for line in reader.lines() {
         let line = line?;
+        relay_raw_event(&line);
         let (event, data) = line.split_once(">>").ok_or(anyhow!(
             "Hyprland socket provided a line in an unexpected format: '{line}'"
         ))?;
         let msg: Message = match event {
             // create/destroy events drive present_workspace_ids. Allocated mappings can outlive
             // destroyed Hyprland workspaces, but AGS should only display present IDs.
-            "createworkspacev2" => Message::WorkspaceCreated {
-                workspace_id: data.split_once(",").unwrap().0.parse()?,
-            },
-            "destroyworkspacev2" => Message::WorkspaceDestroyed {
-                workspace_id: data.split_once(",").unwrap().0.parse()?,
-            },
-            "workspacev2" => Message::ActiveWorkspaceChanged {
-                workspace_id: data.split_once(",").unwrap().0.parse()?,
-                monitor_name: None,
-            },
+            // Special workspaces have negative IDs and are left out, see activespecial.
+            "createworkspacev2" | "destroyworkspacev2" | "workspacev2" => {
+                let (workspace_id, _) = data.split_once(",").unwrap_or((data, ""));
+                let Ok(workspace_id) = workspace_id.parse() else {
+                    continue;
+                };
+                match event {
+                    "createworkspacev2" => Message::WorkspaceCreated { workspace_id },
+                    "destroyworkspacev2" => Message::WorkspaceDestroyed { workspace_id },
+                    _ => Message::ActiveWorkspaceChanged {
+                        workspace_id,
+                        monitor_name: None,
+                    },
+                }
+            }
+            "activespecial" => {
+                let (name, monitor_name) = data.rsplit_once(",").unwrap_or((data, ""));
+                Message::SpecialWorkspaceChanged {
+                    monitor_name: monitor_name.to_string(),
+                    name: special_workspace_name(name),
+                }
+            }
             "focusedmonv2" => {
                 let (monitor_name, workspace_id) = data.split_once(",").unwrap();
                 // focusedmonv2 includes the output name, which is critical for old encoded fallback
@@ -141,6 +665,30 @@ pub fn event_reader(tx: mpsc::Sender<Message>) -> Result<()> {
                     monitor_name: Some(monitor_name.to_string()),
                 }
             }
+            "openwindow" => {
+                // Window titles may contain commas, so only split off the leading fields.
+                let mut fields = data.splitn(4, ',');
+                let address = fields.next().unwrap_or_default().to_string();
+                let _workspace_name = fields.next();
+                let class = fields.next().unwrap_or_default().to_string();
+                Message::WindowOpened { address, class }
+            }
+            "activewindowv2" if !data.is_empty() => Message::ActiveWindowChanged {
+                address: data.to_string(),
+            },
+            "closewindow" => Message::WindowClosed {
+                address: data.to_string(),
+            },
+            "movewindowv2" => {
+                let mut fields = data.splitn(3, ',');
+                let address = fields.next().unwrap_or_default().to_string();
+                // Special workspaces have negative IDs, which hywoma does not track.
+                let workspace_id = fields.next().and_then(|id| id.parse().ok());
+                Message::WindowMoved {
+                    address,
+                    workspace_id,
+                }
+            }
             "monitoradded" | "monitoraddedv2" | "monitorremoved" | "monitorremovedv2" => {
                 // Topology events are intentionally coarse. The app layer re-reads monitors and the
                 // active workspace outside the hot path to recover from Hyprland's transient events
@@ -149,26 +697,146 @@ pub fn event_reader(tx: mpsc::Sender<Message>) -> Result<()> {
             }
             _ => continue,
         };
+        match &msg {
+            Message::ActiveWorkspaceChanged {
+                workspace_id,
+                monitor_name,
+            } if !active_workspaces.is_new(*workspace_id, monitor_name.as_deref()) => continue,
+            Message::WorkspaceDestroyed { workspace_id } => active_workspaces.forget(*workspace_id),
+            _ => {}
+        }
         tx.send(msg)?;
     }
+    Err(anyhow!("Hyprland closed its event socket"))
+}
+
+// The name `togglespecialworkspace` takes, from the workspace name Hyprland reports. Nothing is
+// shown when the name is empty.
+pub fn special_workspace_name(workspace: &str) -> Option<String> {
+    if workspace.is_empty() {
+        return None;
+    }
+    Some(
+        workspace
+            .strip_prefix("special:")
+            .unwrap_or(workspace)
+            .to_string(),
+    )
+}
+
+pub fn subscribe_raw_events(mut stream: UnixStream) -> Result<()> {
+    if cfg!(feature = "hyprland-rs") && !simulate::is_active() {
+        // hyprland-rs parses events itself and never hands out the raw lines.
+        stream.write_all(b"Raw events are not available with the hyprland-rs backend\n")?;
+        return Ok(());
+    }
+    // Non-blocking for the same reason as snapshot subscribers: a stalled debugging client must
+    // not hold up the Hyprland event reader.
+    stream.set_nonblocking(true)?;
+    RAW_SUBSCRIBERS.lock().unwrap().push(stream);
     Ok(())
 }
 
+pub fn relay_raw_event(line: &str) {
+    let mut subscribers = RAW_SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let stamped = format!(
+        "{} {line}\n",
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f")
+    );
+    subscribers.retain_mut(|stream| match stream.write_all(stamped.as_bytes()) {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::warn!("Dropping raw event subscriber after write failure: {err:?}");
+            false
+        }
+    });
+}
+
 pub fn hyprctl(command: &str) -> Result<String> {
+    let span = tracing::debug_span!("hyprctl", command, duration_ms = tracing::field::Empty);
+    let _entered = span.enter();
+    let started = Instant::now();
+    let response = hyprctl_request(command);
+    let elapsed = started.elapsed();
+    span.record("duration_ms", elapsed.as_millis() as u64);
+
+    if elapsed >= SLOW_HYPRCTL_THRESHOLD {
+        tracing::warn!(
+            command,
+            duration_ms = elapsed.as_millis() as u64,
+            "slow hyprctl call"
+        );
+    } else {
+        tracing::debug!(
+            command,
+            duration_ms = elapsed.as_millis() as u64,
+            "hyprctl call"
+        );
+    }
+
+    response
+}
+
+fn hyprctl_request(command: &str) -> Result<String> {
+    if let Some(response) = simulate::hyprctl(command) {
+        return Ok(response);
+    }
+    // With the hyprland-rs backend, dispatches use its typed dispatcher; raw queries without a
+    // typed equivalent keep using the socket below.
+    #[cfg(feature = "hyprland-rs")]
+    if let Some(dispatch) = command.strip_prefix("dispatch ") {
+        return crate::hyprland_rs::dispatch(dispatch).map(|()| "ok".to_string());
+    }
+
     let path = get_socket_path(HyprlandSocketKind::Command)?;
     let mut stream = UnixStream::connect(path)?;
+    stream.set_write_timeout(Some(HYPRCTL_TIMEOUT))?;
+    stream.set_read_timeout(Some(HYPRCTL_TIMEOUT))?;
 
     stream.write_all(command.as_bytes())?;
     stream.flush()?;
 
     let mut reader = BufReader::new(stream);
     let mut response = String::new();
-    reader.read_to_string(&mut response)?;
+    reader
+        .read_to_string(&mut response)
+        .map_err(|err| match err.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                anyhow!("Hyprland did not answer `{command}` within {HYPRCTL_TIMEOUT:?}")
+            }
+            _ => err.into(),
+        })?;
 
     Ok(response)
 }
 
-pub fn hyprctl_dispatch(command: &str) -> Result<String> {
+// `{args}` in a template stands for the arguments hywoma passes, which are appended when the
+// template does not mention them.
+fn apply_dispatch_template(templates: &BTreeMap<String, String>, command: &str) -> String {
+    let Some(dispatch) = command.strip_prefix("dispatch ") else {
+        return command.to_string();
+    };
+    let (dispatcher, args) = dispatch.split_once(' ').unwrap_or((dispatch, ""));
+    let Some(template) = templates.get(dispatcher) else {
+        return command.to_string();
+    };
+    if template.contains("{args}") {
+        format!("dispatch {}", template.replace("{args}", args))
+    } else if args.is_empty() {
+        format!("dispatch {template}")
+    } else {
+        format!("dispatch {template} {args}")
+    }
+}
+
+// Dispatches go through the `dispatchers` table of the config, any other request as it is.
+pub fn hyprctl_dispatch(config: &Config, command: &str) -> Result<String> {
+    let command = &apply_dispatch_template(&config.dispatchers, command);
+    DISPATCHED.set(true);
+    thumbnails::start_pending();
     let response = hyprctl(command)?;
     let trimmed = response.trim();
     let lower = trimmed.to_ascii_lowercase();
@@ -183,9 +851,123 @@ pub fn hyprctl_dispatch(command: &str) -> Result<String> {
     Ok(response)
 }
 
+// A cheap request with a deadline, unlike hyprctl which waits as long as Hyprland takes.
+pub fn ping(timeout: Duration) -> Result<()> {
+    if simulate::is_active() {
+        return Ok(());
+    }
+    let mut stream = UnixStream::connect(get_socket_path(HyprlandSocketKind::Command)?)?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.write_all(b"version")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(())
+}
+
+// Runs several commands in one request, so Hyprland applies them without a redraw in between.
+// Long batches go out in chunks with a pause between them, reporting to the client of the command
+// after each one. A failed chunk stops the rest, like a failed command stops a batch.
+pub fn hyprctl_batch(config: &Config, commands: &[String]) -> Result<()> {
+    let bulk = config.bulk;
+    let chunk_size = bulk.chunk_size.max(1);
+    let requests = batch_requests(&config.dispatchers, commands, chunk_size);
+    for (index, request) in requests.iter().enumerate() {
+        if index > 0 {
+            thread::sleep(Duration::from_millis(bulk.pause_ms));
+        }
+        hyprctl_dispatch(config, request)?;
+        if requests.len() > 1 {
+            progress::report(commands.len().min((index + 1) * chunk_size), commands.len());
+        }
+    }
+    Ok(())
+}
+
+fn batch_requests(
+    templates: &BTreeMap<String, String>,
+    commands: &[String],
+    chunk_size: usize,
+) -> Vec<String> {
+    commands
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let chunk: Vec<String> = chunk
+                .iter()
+                .map(|command| apply_dispatch_template(templates, command))
+                .collect();
+            format!("[[BATCH]]{}", chunk.join(";"))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Workspace;
+    use super::{
+        ActiveWorkspaceFilter, IdLayout, Orientation, Workspace, apply_dispatch_template,
+        batch_requests, newest_instance, parse_workspace_ids,
+    };
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn drops_repeated_active_workspace_reports() {
+        let mut filter = ActiveWorkspaceFilter::default();
+
+        assert!(filter.is_new(1001, Some("DP-1")));
+        assert!(!filter.is_new(1001, None));
+        assert!(!filter.is_new(1001, Some("DP-1")));
+        // The same workspace on another monitor means it was moved there.
+        assert!(filter.is_new(1001, Some("DP-2")));
+        assert!(filter.is_new(1010, None));
+        assert!(filter.is_new(1010, Some("DP-2")));
+        filter.forget(1010);
+        assert!(filter.is_new(1010, None));
+    }
+
+    #[test]
+    fn workspace_ids_leave_out_special_workspaces() {
+        let workspaces = r#"[
+            {"id": 1001, "name": "1001"},
+            {"id": -98, "name": "special:hywoma-archive-3"},
+            {"id": 1010, "name": "1010"}
+        ]"#;
+
+        assert_eq!(parse_workspace_ids(workspaces).unwrap(), [1001, 1010]);
+    }
+
+    #[test]
+    fn long_batches_are_split_into_chunks() {
+        let commands: Vec<String> = (1..=5)
+            .map(|id| format!("dispatch workspace {id}"))
+            .collect();
+
+        assert_eq!(
+            batch_requests(&BTreeMap::new(), &commands, 2),
+            [
+                "[[BATCH]]dispatch workspace 1;dispatch workspace 2",
+                "[[BATCH]]dispatch workspace 3;dispatch workspace 4",
+                "[[BATCH]]dispatch workspace 5",
+            ]
+        );
+        assert_eq!(batch_requests(&BTreeMap::new(), &commands, 20).len(), 1);
+        assert!(batch_requests(&BTreeMap::new(), &[], 20).is_empty());
+    }
+
+    #[test]
+    fn rotated_monitors_are_portrait() {
+        assert_eq!(
+            Orientation::from_mode(1920, 1080, 0),
+            Orientation::Landscape
+        );
+        assert_eq!(Orientation::from_mode(1920, 1080, 1), Orientation::Portrait);
+        assert_eq!(
+            Orientation::from_mode(1920, 1080, 6),
+            Orientation::Landscape
+        );
+        assert_eq!(Orientation::from_mode(1080, 1920, 0), Orientation::Portrait);
+    }
 
     #[test]
     fn workspace_id_roundtrip_preserves_single_digit_group() {
@@ -195,7 +977,10 @@ mod tests {
             group: 9,
         };
 
-        assert_eq!(Workspace::from_id(workspace.to_id()), workspace);
+        assert_eq!(
+            Workspace::from_id(workspace.to_id(IdLayout::default()), IdLayout::default()),
+            workspace
+        );
     }
 
     #[test]
@@ -206,6 +991,96 @@ mod tests {
             group: 10,
         };
 
-        assert_eq!(Workspace::from_id(workspace.to_id()), workspace);
+        assert_eq!(
+            Workspace::from_id(workspace.to_id(IdLayout::default()), IdLayout::default()),
+            workspace
+        );
+    }
+
+    #[test]
+    fn workspace_ids_roundtrip_in_configured_radices() {
+        let layout = IdLayout {
+            workspaces: 20,
+            monitors: 4,
+            groups: 50,
+        };
+        let workspace = Workspace {
+            workspace: 20,
+            monitor: 4,
+            group: 49,
+        };
+
+        assert_eq!(workspace.to_id(layout), 4000);
+        assert_eq!(
+            Workspace::from_id(workspace.to_id(layout), layout),
+            workspace
+        );
+        assert_eq!(layout.first_internal_id(), 4001);
+        assert_eq!(IdLayout::default().first_internal_id(), 1001);
+        assert_eq!(
+            IdLayout {
+                workspaces: 5,
+                monitors: 2,
+                groups: 3
+            }
+            .first_internal_id(),
+            1000
+        );
+    }
+
+    #[test]
+    fn dispatch_templates_replace_dispatchers_and_keep_their_arguments() {
+        let templates = BTreeMap::from([
+            (
+                "movetoworkspacesilent".to_string(),
+                "movetoworkspace".to_string(),
+            ),
+            (
+                "workspace".to_string(),
+                "focusworkspaceoncurrentmonitor {args}".to_string(),
+            ),
+        ]);
+
+        assert_eq!(
+            apply_dispatch_template(
+                &templates,
+                "dispatch movetoworkspacesilent 1005,address:0xa"
+            ),
+            "dispatch movetoworkspace 1005,address:0xa"
+        );
+        assert_eq!(
+            apply_dispatch_template(&templates, "dispatch workspace 1001"),
+            "dispatch focusworkspaceoncurrentmonitor 1001"
+        );
+        assert_eq!(
+            apply_dispatch_template(&templates, "dispatch focusmonitor 1"),
+            "dispatch focusmonitor 1"
+        );
+        assert_eq!(
+            apply_dispatch_template(&templates, "-j/monitors"),
+            "-j/monitors"
+        );
+    }
+
+    #[test]
+    fn the_newest_instance_with_an_event_socket_is_followed() {
+        let dir =
+            std::env::temp_dir().join(format!("hywoma-instances-test-{}", std::process::id()));
+        let now = SystemTime::now();
+        for (signature, age) in [("old", 60), ("restarted", 1)] {
+            fs::create_dir_all(dir.join(signature)).unwrap();
+            fs::File::create(dir.join(signature).join(".socket2.sock"))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+        // Starting up, without its sockets yet.
+        fs::create_dir_all(dir.join("starting")).unwrap();
+
+        let newest = newest_instance(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(newest.as_deref(), Some("restarted"));
+        assert_eq!(newest_instance(&dir), None);
     }
 }
@@ -0,0 +1,222 @@
+use anyhow::{Result, anyhow};
+use hyprland::data::{Client, Clients, Monitors, Workspace, Workspaces};
+use hyprland::dispatch::{Dispatch, DispatchType};
+use hyprland::event_listener::EventListener;
+use hyprland::shared::{Address, HyprData, HyprDataActive, HyprDataActiveOptional};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, mpsc};
+
+use crate::app::Message;
+use crate::hyprland::{
+    ActiveWorkspaceFilter, ClientInfo, MonitorInfo, WorkspaceInfo, special_workspace_name,
+};
+use crate::systemd;
+
+// Typed backend on top of hyprland-rs, enabled with the `hyprland-rs` feature. It covers the
+// queries, dispatches and events the daemon depends on; raw requests without a typed equivalent
+// still go through the socket code in hyprland.rs.
+
+pub fn get_monitors() -> Result<Vec<MonitorInfo>> {
+    Ok(Monitors::get()?
+        .into_iter()
+        .map(|monitor| MonitorInfo {
+            id: monitor.id as u64,
+            name: monitor.name,
+            x: monitor.x.into(),
+        })
+        .collect())
+}
+
+pub fn get_monitor_workspace_ids() -> Result<Vec<(u64, u64)>> {
+    Ok(Monitors::get()?
+        .into_iter()
+        .filter_map(|monitor| {
+            let workspace_id = u64::try_from(monitor.active_workspace.id).ok()?;
+            Some((monitor.id as u64, workspace_id))
+        })
+        .collect())
+}
+
+pub fn get_active_workspace() -> Result<(u64, Option<u64>)> {
+    let workspace = Workspace::get_active()?;
+    let id = u64::try_from(workspace.id)
+        .map_err(|_| anyhow!("Active workspace {} is a special workspace", workspace.id))?;
+    Ok((id, workspace.monitor_id.map(|monitor| monitor as u64)))
+}
+
+pub fn get_workspace_ids() -> Result<Vec<u64>> {
+    // Special workspaces have negative IDs and are never managed by hywoma.
+    Ok(Workspaces::get()?
+        .into_iter()
+        .filter_map(|workspace| u64::try_from(workspace.id).ok())
+        .collect())
+}
+
+pub fn get_workspaces() -> Result<Vec<WorkspaceInfo>> {
+    Ok(Workspaces::get()?
+        .into_iter()
+        .filter_map(|workspace| {
+            Some(WorkspaceInfo {
+                id: u64::try_from(workspace.id).ok()?,
+                name: workspace.name,
+                windows: u64::from(workspace.windows),
+            })
+        })
+        .collect())
+}
+
+pub fn get_workspace_window_counts() -> Result<HashMap<u64, u64>> {
+    Ok(Workspaces::get()?
+        .into_iter()
+        .filter_map(|workspace| {
+            let id = u64::try_from(workspace.id).ok()?;
+            Some((id, workspace.windows.into()))
+        })
+        .collect())
+}
+
+pub fn get_active_window_address() -> Result<Option<String>> {
+    Ok(Client::get_active()?.map(|client| raw_address(&client.address)))
+}
+
+pub fn get_clients() -> Result<Vec<ClientInfo>> {
+    Ok(Clients::get()?
+        .into_iter()
+        .filter_map(|client| {
+            Some(ClientInfo {
+                address: raw_address(&client.address),
+                class: client.class,
+                title: client.title,
+                workspace_id: u64::try_from(client.workspace.id).ok()?,
+                pid: client.pid,
+                floating: client.floating,
+            })
+        })
+        .collect())
+}
+
+// Takes the part of a `dispatch <name> <args>` command after "dispatch ".
+pub fn dispatch(command: &str) -> Result<()> {
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    Dispatch::call(DispatchType::Custom(name, args))?;
+    Ok(())
+}
+
+// Hyprland's socket events carry addresses without the 0x prefix; keep that form everywhere.
+fn raw_address(address: &Address) -> String {
+    address.to_string().trim_start_matches("0x").to_string()
+}
+
+// hyprland-rs connects inside start_listener, so a resync is requested up front. The events that
+// follow are handled after it either way.
+pub fn event_reader(tx: mpsc::Sender<Message>, resync: bool) -> Result<()> {
+    if resync {
+        tracing::info!("Reconnecting to the Hyprland event socket");
+        tx.send(Message::Sync)?;
+    } else {
+        systemd::notify_ready();
+    }
+    let mut listener = EventListener::new();
+    let active_workspaces = Arc::new(Mutex::new(ActiveWorkspaceFilter::default()));
+
+    // Handlers cannot return errors. A failed send means the main loop is gone, and the process is
+    // about to exit anyway, so it is ignored.
+    let sender = tx.clone();
+    listener.add_workspace_added_handler(move |data| {
+        if let Ok(workspace_id) = u64::try_from(data.id) {
+            let _ = sender.send(Message::WorkspaceCreated { workspace_id });
+        }
+    });
+    let sender = tx.clone();
+    let filter = active_workspaces.clone();
+    listener.add_workspace_deleted_handler(move |data| {
+        if let Ok(workspace_id) = u64::try_from(data.id) {
+            filter.lock().unwrap().forget(workspace_id);
+            let _ = sender.send(Message::WorkspaceDestroyed { workspace_id });
+        }
+    });
+    let sender = tx.clone();
+    let filter = active_workspaces.clone();
+    listener.add_workspace_changed_handler(move |data| {
+        if let Ok(workspace_id) = u64::try_from(data.id)
+            && filter.lock().unwrap().is_new(workspace_id, None)
+        {
+            let _ = sender.send(Message::ActiveWorkspaceChanged {
+                workspace_id,
+                monitor_name: None,
+            });
+        }
+    });
+    let sender = tx.clone();
+    listener.add_active_monitor_changed_handler(move |data| {
+        // hyprland-rs only reports the workspace name for focusedmon, so ask for the ID.
+        match get_active_workspace() {
+            Ok((workspace_id, _))
+                if !active_workspaces
+                    .lock()
+                    .unwrap()
+                    .is_new(workspace_id, Some(&data.monitor_name)) => {}
+            Ok((workspace_id, _)) => {
+                let _ = sender.send(Message::ActiveWorkspaceChanged {
+                    workspace_id,
+                    monitor_name: Some(data.monitor_name),
+                });
+            }
+            Err(err) => {
+                tracing::warn!("Failed to query active workspace after focusedmon: {err:?}")
+            }
+        }
+    });
+    let sender = tx.clone();
+    listener.add_window_opened_handler(move |data| {
+        let _ = sender.send(Message::WindowOpened {
+            address: raw_address(&data.window_address),
+            class: data.window_class,
+        });
+    });
+    let sender = tx.clone();
+    listener.add_active_window_changed_handler(move |data| {
+        if let Some(data) = data {
+            let _ = sender.send(Message::ActiveWindowChanged {
+                address: raw_address(&data.address),
+            });
+        }
+    });
+    let sender = tx.clone();
+    listener.add_window_closed_handler(move |address| {
+        let _ = sender.send(Message::WindowClosed {
+            address: raw_address(&address),
+        });
+    });
+    let sender = tx.clone();
+    listener.add_window_moved_handler(move |data| {
+        let _ = sender.send(Message::WindowMoved {
+            address: raw_address(&data.window_address),
+            workspace_id: u64::try_from(data.workspace_id).ok(),
+        });
+    });
+    let sender = tx.clone();
+    listener.add_changed_special_handler(move |data| {
+        let _ = sender.send(Message::SpecialWorkspaceChanged {
+            monitor_name: data.monitor_name,
+            name: special_workspace_name(&data.workspace_name),
+        });
+    });
+    let sender = tx.clone();
+    listener.add_special_removed_handler(move |monitor_name| {
+        let _ = sender.send(Message::SpecialWorkspaceChanged {
+            monitor_name,
+            name: None,
+        });
+    });
+    let sender = tx.clone();
+    listener.add_monitor_added_handler(move |_| {
+        let _ = sender.send(Message::MonitorTopologyChanged);
+    });
+    listener.add_monitor_removed_handler(move |_| {
+        let _ = tx.send(Message::MonitorTopologyChanged);
+    });
+
+    listener.start_listener()?;
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::hyprland::hyprctl_dispatch as hyprctl;
+use crate::state::{GroupId, WorkspaceKey};
+
+// Layout tweaks per logical workspace. Hyprland forgets them when it destroys an empty workspace,
+// which happens on every round trip through another group, so they are applied again whenever the
+// workspace is recreated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct WorkspaceLayout {
+    // Set with `hywoma layout <name>`, as a `layout:` workspace rule.
+    layout: Option<String>,
+    // Absolute `layoutmsg` settings, at most one per kind, in the order they were made.
+    messages: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct LayoutMemory {
+    workspaces: HashMap<WorkspaceKey, WorkspaceLayout>,
+}
+
+// Only messages that set something absolutely are remembered: replaying `addmaster` or
+// `mfact +0.05` would compound on every return. A later message of the same kind replaces the
+// earlier one.
+fn message_kind(message: &str) -> Option<&str> {
+    let mut words = message.split_whitespace();
+    match (words.next()?, words.next()) {
+        (
+            "orientationleft" | "orientationright" | "orientationtop" | "orientationbottom"
+            | "orientationcenter",
+            _,
+        ) => Some("orientation"),
+        ("mfact", Some("exact")) => Some("mfact"),
+        _ => None,
+    }
+}
+
+impl LayoutMemory {
+    pub fn set_layout(
+        &mut self,
+        config: &Config,
+        key: WorkspaceKey,
+        workspace_id: u64,
+        layout: &str,
+    ) {
+        let rule = format!("keyword workspace {workspace_id}, layout:{layout}");
+        if let Err(err) = hyprctl(config, &rule) {
+            tracing::warn!("Failed to set layout {layout:?} on workspace {workspace_id}: {err:?}");
+            return;
+        }
+        self.workspaces.entry(key).or_default().layout = Some(layout.to_string());
+    }
+
+    // Sends a layout message to the focused workspace and remembers it if it is absolute.
+    pub fn send_message(&mut self, config: &Config, key: Option<WorkspaceKey>, message: &str) {
+        if let Err(err) = hyprctl(config, &format!("dispatch layoutmsg {message}")) {
+            tracing::warn!("Failed to send layout message {message:?}: {err:?}");
+            return;
+        }
+        let (Some(key), Some(kind)) = (key, message_kind(message)) else {
+            return;
+        };
+        let messages = &mut self.workspaces.entry(key).or_default().messages;
+        messages.retain(|earlier| message_kind(earlier) != Some(kind));
+        messages.push(message.to_string());
+    }
+
+    // Called once a recreated workspace is focused: layout messages act on the focused workspace.
+    pub fn reapply(&self, config: &Config, key: WorkspaceKey, workspace_id: u64) {
+        let Some(layout) = self.workspaces.get(&key) else {
+            return;
+        };
+        if let Some(name) = &layout.layout
+            && let Err(err) = hyprctl(
+                config,
+                &format!("keyword workspace {workspace_id}, layout:{name}"),
+            )
+        {
+            tracing::warn!("Failed to restore layout of workspace {workspace_id}: {err:?}");
+        }
+        for message in &layout.messages {
+            if let Err(err) = hyprctl(config, &format!("dispatch layoutmsg {message}")) {
+                tracing::warn!("Failed to restore layout message {message:?}: {err:?}");
+            }
+        }
+    }
+
+    pub fn forget_group(&mut self, group: GroupId) {
+        self.workspaces.retain(|key, _| key.group != group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::message_kind;
+
+    #[test]
+    fn remembers_only_absolute_layout_messages() {
+        assert_eq!(message_kind("orientationleft"), Some("orientation"));
+        assert_eq!(message_kind("orientationcenter"), Some("orientation"));
+        assert_eq!(message_kind("mfact exact 0.6"), Some("mfact"));
+        assert_eq!(message_kind("mfact +0.05"), None);
+        assert_eq!(message_kind("orientationnext"), None);
+        assert_eq!(message_kind("orientationprev"), None);
+        assert_eq!(message_kind("orientationcycle left top"), None);
+        assert_eq!(message_kind("addmaster"), None);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Result, anyhow};
+use clap::Parser;
+use std::io::{IsTerminal, Write};
+use tracing_subscriber::EnvFilter;
+
+use crate::cli::{Action, Cli, LogFormat};
+
+mod app;
+mod archive;
+mod audit;
+mod autostart;
+mod badges;
+mod cli;
+mod client;
+mod config;
+mod dim;
+mod edges;
+mod fifo;
+mod focus;
+mod hooks;
+mod hyprland;
+#[cfg(feature = "hyprland-rs")]
+mod hyprland_rs;
+mod layouts;
+mod progress;
+mod protocol;
+mod reconcile;
+mod rules;
+mod schedule;
+mod session;
+mod simulate;
+mod sockets;
+mod state;
+mod systemd;
+mod templates;
+mod thumbnails;
+mod watchdog;
+
+// For programs controlling the daemon without the `hywoma` binary. Commands are sent as the same
+// typed `Command` the CLI parses its arguments into.
+pub use app::{FocusEvent, SlotTarget, WindowTarget};
+pub use client::{Client, FocusEvents};
+pub use protocol::{Command, Progress};
+pub use rules::{Rule, RuleRequest};
+pub use state::{GroupId, SlotId, VisibleWorkspace};
+
+// `--log-level debug`, or any RUST_LOG-style directive like `hywoma=debug`. It wins over RUST_LOG.
+fn log_filter(level: Option<&str>) -> Result<EnvFilter> {
+    match level {
+        Some(level) => {
+            EnvFilter::try_new(level).map_err(|err| anyhow!("Invalid log level {level:?}: {err}"))
+        }
+        None => Ok(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))),
+    }
+}
+
+fn init_logging(format: LogFormat, filter: EnvFilter) {
+    // Diagnostics go to stderr so client commands like `status` keep a clean stdout. RUST_LOG or
+    // --log-level can raise the level, e.g. hywoma=debug to see every hyprctl call with its
+    // duration.
+    // The daemon's stderr is mostly a journal or a log file, which should not get color codes.
+    let builder = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.init(),
+        // One JSON object per line with timestamp, level, target and fields, for log shippers.
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
+
+// The `hywoma` binary: the daemon and the CLI talking to it.
+pub fn run() -> Result<()> {
+    let cli = Cli::parse();
+    init_logging(cli.log_format, log_filter(cli.log_level.as_deref())?);
+
+    match cli.action {
+        Action::Server {
+            simulate,
+            command_fd,
+        } => {
+            if let Some(layout) = simulate {
+                simulate::install(layout);
+            }
+            app::server(command_fd)
+        }
+        Action::InstallAutostart { systemd, .. } => {
+            if systemd {
+                autostart::install_systemd()
+            } else {
+                println!("{}", autostart::exec_once_line()?);
+                Ok(())
+            }
+        }
+        Action::Watch => app::watch(),
+        Action::Waybar { format } => {
+            app::waybar(format.as_deref().unwrap_or(app::DEFAULT_WAYBAR_FORMAT))
+        }
+        Action::Events { raw: false } => app::stream_events(),
+        Action::Events { raw: true } => app::stream_raw_events(),
+        Action::Completions { shell } => {
+            // Written at once, a closed pipe is an error here instead of a panic in clap_complete.
+            std::io::stdout().write_all(&cli::completions(shell))?;
+            Ok(())
+        }
+        Action::Client(command) => send(
+            cli.token,
+            command.into_command(cli.force, cli.json),
+            cli.json,
+        ),
+        Action::Unknown(args) => send(cli.token, cli::unknown_command(args, cli.force), cli.json),
+    }
+}
+
+fn send(token: Option<String>, command: Result<Command>, json: bool) -> Result<()> {
+    let outcome = command.and_then(|command| app::send_command(token, command));
+    if json {
+        println!("{}", json_outcome(&outcome));
+        if outcome.is_err() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    match outcome? {
+        Some(response) => print!("{response}"),
+        None => println!("Ok"),
+    }
+    Ok(())
+}
+
+// `--json` output for wrappers: failures are reported on stdout as well, with the exit status
+// still telling them apart. Answers that already are JSON, like `status`, are embedded as they are.
+fn json_outcome(outcome: &Result<Option<String>>) -> serde_json::Value {
+    match outcome {
+        Ok(response) => serde_json::json!({
+            "ok": true,
+            "data": response.as_deref().map(|response| {
+                serde_json::from_str(response)
+                    .unwrap_or_else(|_| serde_json::Value::from(response.trim_end()))
+            }),
+            "error": null,
+        }),
+        Err(err) => serde_json::json!({
+            "ok": false,
+            "data": null,
+            "error": format!("{err:#}"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_outcome, log_filter};
+    use crate::protocol;
+    use anyhow::anyhow;
+
+    #[test]
+    fn takes_log_level_as_a_filter() {
+        assert!(log_filter(Some("hywoma=debug")).is_ok());
+        assert!(log_filter(Some("hywoma=loud")).is_err());
+    }
+
+    #[test]
+    fn reports_outcomes_as_json() {
+        assert_eq!(
+            json_outcome(&Ok(None)),
+            serde_json::json!({"ok": true, "data": null, "error": null})
+        );
+        assert_eq!(
+            json_outcome(&Ok(Some("{\"active_group\": 2}\n".to_string())))["data"],
+            serde_json::json!({"active_group": 2})
+        );
+        assert_eq!(
+            json_outcome(&Ok(Some("No rules\n".to_string())))["data"],
+            "No rules"
+        );
+        assert_eq!(
+            json_outcome(&Err(anyhow!("Cannot switch to unknown workspace group 7"))),
+            serde_json::json!({
+                "ok": false,
+                "data": null,
+                "error": "Cannot switch to unknown workspace group 7",
+            })
+        );
+        let failed_answer = protocol::parse_response("Err: Unknown profile nope\n").map(Some);
+        assert_eq!(
+            json_outcome(&failed_answer),
+            serde_json::json!({"ok": false, "data": null, "error": "Unknown profile nope"})
+        );
+    }
+}
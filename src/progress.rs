@@ -0,0 +1,31 @@
+use std::cell::RefCell;
+use tokio::sync::mpsc;
+
+use crate::protocol::Progress;
+
+// The connection of a client waiting on a command, for the lines telling it how far a bulk
+// operation got before the reply.
+pub type Reporter = mpsc::UnboundedSender<Progress>;
+
+thread_local! {
+    // The client of the message being handled. Set by the main loop around each message, so the
+    // dispatch batches deep in a command report without the reporter being passed down to them.
+    static REPORTER: RefCell<Option<Reporter>> = const { RefCell::new(None) };
+}
+
+pub fn channel() -> (Reporter, mpsc::UnboundedReceiver<Progress>) {
+    mpsc::unbounded_channel()
+}
+
+pub fn report_to(reporter: Option<Reporter>) {
+    REPORTER.set(reporter);
+}
+
+pub fn report(done: usize, total: usize) {
+    REPORTER.with_borrow(|reporter| {
+        // A client that hung up only misses the progress, the operation goes on.
+        if let Some(reporter) = reporter {
+            let _ = reporter.send(Progress { done, total });
+        }
+    });
+}
@@ -0,0 +1,401 @@
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::app::{Message, SlotTarget, WindowTarget};
+use crate::cli;
+use crate::rules::RuleRequest;
+use crate::state::{GroupId, SlotId, VisibleWorkspace};
+
+// Bumped whenever `Command` changes incompatibly. The version sits in a fixed header ahead of the
+// bincode body, so a daemon can still tell an old or new client what went wrong after an upgrade.
+// Version 2 clients read `Progress:` lines ahead of the reply.
+pub const PROTOCOL_VERSION: u32 = 2;
+// Version 1 commands are encoded the same way, those clients only predate progress lines.
+const OLDEST_PROTOCOL_VERSION: u32 = 1;
+const MAGIC: &[u8; 4] = b"HYWM";
+// Longer than a dispatch normally takes, shorter than a supervisor's patience.
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub version: u32,
+    pub token: Option<String>,
+    pub command: Command,
+}
+
+impl Request {
+    // Older clients would take progress lines for part of the reply.
+    pub fn reads_progress(&self) -> bool {
+        self.version >= 2
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Body {
+    token: Option<String>,
+    command: Command,
+}
+
+// Everything a client can ask of the daemon. Parsed from argv by `cli` on the client, so the daemon
+// never re-parses strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    // Answered over the same connection.
+    Status,
+    TmpSlots,
+    TmpSwapWithSlot(SlotId),
+    RawEvents,
+    Watch,
+    RestartServer,
+    PlanGroupSwitch(GroupId),
+    // Whether to retile every group, not just the active one.
+    Retile(bool),
+    // Pattern, whether to focus the first match.
+    Where(String, bool),
+    SaveSession,
+    RestoreSession,
+    ArchiveGroup(GroupId),
+    RestoreGroup(GroupId),
+    SwitchProfile(String),
+    ListProfiles,
+    Rule(RuleRequest),
+    // Fire and forget, each to the `Message` of the same name.
+    SelectWorkspace(VisibleWorkspace, Option<SlotTarget>),
+    SelectWorkspaceDelta(i64),
+    SelectWorkspaceRelative(i64),
+    MoveToWorkspace(VisibleWorkspace, Option<SlotTarget>, bool),
+    MoveToWorkspaceRelative(i64, bool),
+    ToggleWorkspace,
+    MoveToEmpty,
+    Compact,
+    Sync,
+    SetLayout(String),
+    LayoutMsg(String),
+    SwitchGroup(GroupId),
+    SwitchGroupRelative(i64),
+    SwitchGroupOccupiedOnly(GroupId),
+    CreateGroup(String, Option<String>),
+    RenameGroup(GroupId, String),
+    DeleteGroup(GroupId),
+    EnterSubgroup(Option<String>),
+    LeaveSubgroup,
+    MoveToGroup(GroupId),
+    SelectSlot(SlotTarget),
+    MoveToSlot(SlotTarget, bool),
+    SwapSlot(SlotTarget),
+    SwapMonitors(Option<(SlotTarget, SlotTarget)>),
+    SwapWithWorkspace(VisibleWorkspace),
+    Snooze(Option<u64>),
+    Idle,
+    Resume,
+    LockWindow(WindowTarget),
+    UnlockWindow(WindowTarget),
+    MarkWindow(WindowTarget),
+    ClearMarks,
+    MoveMarkedToWorkspace(VisibleWorkspace),
+    // Seconds. New variants go last, so the bincode encoding of the others stays the same.
+    FocusStart(u64),
+    FocusStop,
+    // With `--force`, a group switch that goes through during a focus session.
+    Forced(Box<Command>),
+    CycleWindow(bool),
+    // Whether to include monitors, workspaces and windows, and whether to answer in JSON.
+    ListGroups(bool, bool),
+    RenameWorkspace(Option<String>),
+    MirrorGroup(GroupId, Option<SlotTarget>),
+    StopMirror,
+    // A subcommand hywoma does not know, word by word.
+    Unknown(Vec<String>),
+    Ping,
+    // `hywoma hyprctl` arguments, and whether to skip the safety checks.
+    Hyprctl(Vec<String>, bool),
+    // A group command given a group name instead of a number. The daemon looks the name up and
+    // puts that group in the wrapped command, which is sent with group 0.
+    NamedGroup(String, Box<Command>),
+    ToggleSpecial(Option<String>),
+    MoveToSpecial(Option<String>),
+    MoveWorkspaceToSlot(SlotTarget, bool),
+    Gather,
+}
+
+impl Command {
+    // Whether the client waits for a reply. Streams are not replies, they have clients of their own.
+    pub fn expects_response(&self) -> bool {
+        if let Command::NamedGroup(_, command) = self {
+            return command.expects_response();
+        }
+        matches!(
+            self,
+            Command::Status
+                | Command::TmpSlots
+                | Command::TmpSwapWithSlot(_)
+                | Command::RestartServer
+                | Command::PlanGroupSwitch(_)
+                | Command::Retile(_)
+                | Command::Where(..)
+                | Command::SaveSession
+                | Command::RestoreSession
+                | Command::ArchiveGroup(_)
+                | Command::RestoreGroup(_)
+                | Command::SwitchProfile(_)
+                | Command::ListProfiles
+                | Command::Rule(_)
+                | Command::ListGroups(..)
+                | Command::Ping
+                | Command::Hyprctl(..)
+        )
+    }
+
+    // How long the client waits for the answer, for commands used as health checks that must not
+    // hang along with the daemon.
+    pub fn response_timeout(&self) -> Option<Duration> {
+        matches!(self, Command::Ping).then_some(PING_TIMEOUT)
+    }
+
+    // The main loop message for a fire-and-forget command, or the command back when it needs the
+    // connection.
+    pub fn into_message(self) -> Result<Message, Command> {
+        let message = match self {
+            Command::SelectWorkspace(workspace, target) => {
+                Message::SelectWorkspace(workspace, target)
+            }
+            Command::SelectWorkspaceDelta(delta) => Message::SelectWorkspaceDelta(delta),
+            Command::SelectWorkspaceRelative(delta) => Message::SelectWorkspaceRelative(delta),
+            Command::MoveToWorkspace(workspace, target, follow) => {
+                Message::MoveToWorkspace(workspace, target, follow)
+            }
+            Command::MoveToWorkspaceRelative(delta, follow) => {
+                Message::MoveToWorkspaceRelative(delta, follow)
+            }
+            Command::ToggleWorkspace => Message::ToggleWorkspace,
+            Command::MoveToEmpty => Message::MoveToEmpty,
+            Command::Compact => Message::Compact,
+            Command::Sync => Message::Sync,
+            Command::SetLayout(layout) => Message::SetLayout(layout),
+            Command::LayoutMsg(message) => Message::LayoutMsg(message),
+            Command::SwitchGroup(group) => Message::SwitchGroup(group),
+            Command::SwitchGroupRelative(delta) => Message::SwitchGroupRelative(delta),
+            Command::SwitchGroupOccupiedOnly(group) => Message::SwitchGroupOccupiedOnly(group),
+            Command::CreateGroup(name, template) => Message::CreateGroup(name, template),
+            Command::RenameGroup(group, name) => Message::RenameGroup(group, name),
+            Command::DeleteGroup(group) => Message::DeleteGroup(group),
+            Command::EnterSubgroup(name) => Message::EnterSubgroup(name),
+            Command::LeaveSubgroup => Message::LeaveSubgroup,
+            Command::MoveToGroup(group) => Message::MoveToGroup(group),
+            Command::SelectSlot(target) => Message::SelectSlot(target),
+            Command::MoveToSlot(target, follow) => Message::MoveToSlot(target, follow),
+            Command::SwapSlot(target) => Message::SwapSlot(target),
+            Command::SwapMonitors(targets) => Message::SwapMonitors(targets),
+            Command::SwapWithWorkspace(workspace) => Message::SwapWithWorkspace(workspace),
+            Command::Snooze(minutes) => Message::Snooze(minutes),
+            Command::Idle => Message::Idle,
+            Command::Resume => Message::Resume,
+            Command::LockWindow(window) => Message::LockWindow(window),
+            Command::UnlockWindow(window) => Message::UnlockWindow(window),
+            Command::MarkWindow(window) => Message::MarkWindow(window),
+            Command::ClearMarks => Message::ClearMarks,
+            Command::MoveMarkedToWorkspace(workspace) => Message::MoveMarkedToWorkspace(workspace),
+            Command::FocusStart(secs) => Message::FocusStart(secs),
+            Command::FocusStop => Message::FocusStop,
+            Command::CycleWindow(reverse) => Message::CycleWindow(reverse),
+            Command::RenameWorkspace(label) => Message::RenameWorkspace(label),
+            Command::MirrorGroup(group, target) => Message::MirrorGroup(group, target),
+            Command::StopMirror => Message::StopMirror,
+            Command::Unknown(args) => Message::Unknown(args),
+            Command::ToggleSpecial(name) => Message::ToggleSpecial(name),
+            Command::MoveToSpecial(name) => Message::MoveToSpecial(name),
+            Command::MoveWorkspaceToSlot(target, follow) => {
+                Message::MoveWorkspaceToSlot(target, follow)
+            }
+            Command::Gather => Message::Gather,
+            Command::Forced(command) => match command.into_message() {
+                Ok(message) => Message::Forced(Box::new(message)),
+                Err(command) => return Err(Command::Forced(Box::new(command))),
+            },
+            Command::NamedGroup(name, command) => match command.into_message() {
+                Ok(message) => Message::NamedGroup(name, Box::new(message)),
+                Err(command) => return Err(Command::NamedGroup(name, Box::new(command))),
+            },
+            command => return Err(command),
+        };
+        Ok(message)
+    }
+}
+
+// Fire-and-forget commands are answered with one line once handled: `Ok`, or `Err: ` and why the
+// command did nothing.
+pub fn format_reply(reply: &Result<(), String>) -> String {
+    match reply {
+        Ok(()) => "Ok".to_string(),
+        Err(reason) => format!("Err: {reason}"),
+    }
+}
+
+pub fn parse_reply(reply: &str) -> Result<()> {
+    match reply.trim_end() {
+        "Ok" => Ok(()),
+        "" => bail!("The daemon closed the connection without replying"),
+        reply => bail!("{}", reply.strip_prefix("Err: ").unwrap_or(reply)),
+    }
+}
+
+// Commands with an answer send it as it is, so `status` stays plain JSON for scripts and older
+// clients, and a failure as `Err: ` and why, like the replies above.
+pub fn format_response(response: &Result<String, String>) -> String {
+    match response {
+        Ok(response) => response.clone(),
+        Err(reason) => format_reply(&Err(reason.clone())),
+    }
+}
+
+pub fn parse_response(response: &str) -> Result<String> {
+    match response.strip_prefix("Err: ") {
+        Some(reason) => bail!("{}", reason.trim_end()),
+        None if response.is_empty() => bail!("The daemon closed the connection without answering"),
+        None => Ok(response.to_string()),
+    }
+}
+
+// How far a bulk operation got, in dispatches. Sent to the waiting client as `Progress: 20/64`
+// lines ahead of the reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+pub fn format_progress(progress: Progress) -> String {
+    format!("Progress: {}/{}", progress.done, progress.total)
+}
+
+pub fn parse_progress(line: &str) -> Option<Progress> {
+    let (done, total) = line
+        .trim_end()
+        .strip_prefix("Progress: ")?
+        .split_once('/')?;
+    Some(Progress {
+        done: done.parse().ok()?,
+        total: total.parse().ok()?,
+    })
+}
+
+pub fn encode(token: Option<String>, command: Command) -> Result<Vec<u8>> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(PROTOCOL_VERSION.to_le_bytes());
+    bytes.extend(bincode::serialize(&Body { token, command })?);
+    Ok(bytes)
+}
+
+// The body is only decoded for this daemon's own version; any other version is reported as such so
+// the daemon can answer with something better than a decoding error.
+pub fn decode(bytes: &[u8]) -> Result<Request> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        // Clients from before the header shipped their argv as it was.
+        let args: Vec<String> = bincode::deserialize(bytes)?;
+        return Ok(Request {
+            version: 0,
+            token: None,
+            command: cli::parse_command(&args)?,
+        });
+    };
+    let (version, body) = rest
+        .split_first_chunk::<4>()
+        .ok_or_else(|| anyhow!("Truncated command request"))?;
+    let version = u32::from_le_bytes(*version);
+    if !(OLDEST_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+        bail!(
+            "Client speaks protocol version {version}, this daemon speaks {PROTOCOL_VERSION}. Run `hywoma restart-server` after upgrading."
+        );
+    }
+    let Body { token, command } = bincode::deserialize(body)?;
+    Ok(Request {
+        version,
+        token,
+        command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rule;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn requests_round_trip_and_old_clients_still_work() {
+        let bytes = encode(Some("abc".to_string()), Command::SwitchGroup(2)).unwrap();
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            Request {
+                version: PROTOCOL_VERSION,
+                token: Some("abc".to_string()),
+                command: Command::SwitchGroup(2),
+            }
+        );
+
+        let legacy = bincode::serialize(&args("select_workspace 4")).unwrap();
+        assert_eq!(
+            decode(&legacy).unwrap().command,
+            Command::SelectWorkspace(4, None)
+        );
+
+        assert!(parse_reply(&format_reply(&Ok(()))).is_ok());
+        let err = parse_reply(&format_reply(&Err(
+            "Cannot select detached slot 3".to_string()
+        )));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Cannot select detached slot 3"
+        );
+        let answer = format_response(&Ok("{\"active_group\": 2}\n".to_string()));
+        assert_eq!(parse_response(&answer).unwrap(), answer);
+        let refused = format_response(&Err("Unknown profile nope".to_string()));
+        assert_eq!(
+            parse_response(&format!("{refused}\n"))
+                .unwrap_err()
+                .to_string(),
+            "Unknown profile nope"
+        );
+
+        let progress = Progress {
+            done: 20,
+            total: 64,
+        };
+        assert_eq!(
+            parse_progress(&format!("{}\n", format_progress(progress))),
+            Some(progress)
+        );
+        assert_eq!(parse_progress("Ok"), None);
+        assert_eq!(parse_progress("Progress: lots"), None);
+
+        assert!(decode(&bytes).unwrap().reads_progress());
+        assert!(!decode(&legacy).unwrap().reads_progress());
+        let mut before_progress = bytes.clone();
+        before_progress[4..8].copy_from_slice(&1u32.to_le_bytes());
+        let before_progress = decode(&before_progress).unwrap();
+        assert_eq!(before_progress.command, Command::SwitchGroup(2));
+        assert!(!before_progress.reads_progress());
+
+        let mut future = bytes.clone();
+        future[4..8].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+        assert!(decode(&future).is_err());
+    }
+
+    #[test]
+    fn rules_without_a_slot_round_trip() {
+        let command = Command::Rule(RuleRequest::Add(Rule {
+            class: "firefox".to_string(),
+            group: 1,
+            workspace: 2,
+            slot: None,
+        }));
+
+        let bytes = encode(None, command.clone()).unwrap();
+
+        assert_eq!(decode(&bytes).unwrap().command, command);
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::hyprland::{self, ClientInfo, hyprctl_dispatch as hyprctl};
+use crate::state::{SlotId, State, VisibleWorkspace, WorkspaceKey};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconcileConfig {
+    // Check Hyprland's active workspace after every command and reconcile when it differs from
+    // what the command should have produced.
+    pub verify_dispatches: bool,
+    // Seconds between reconciliation passes. Unset means only hotplug and failed verifications
+    // reconcile.
+    pub interval_secs: Option<u64>,
+    // What to do at startup about windows on workspaces outside hywoma's scheme, e.g. opened before
+    // the daemon ran: "observe" reports them, "adopt" moves them onto the active group's workspaces
+    // and "strict" refuses to start.
+    pub startup: StartupCheck,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StartupCheck {
+    #[default]
+    Observe,
+    Adopt,
+    Strict,
+}
+
+// A window outside the scheme and where adopting would put it: the same workspace number on the
+// slot of the monitor that showed it, or the slot's active workspace when the number is out of
+// range there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stray {
+    pub address: String,
+    pub class: String,
+    pub workspace_id: u64,
+    pub target: WorkspaceKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Correction {
+    // The monitor shows another visible workspace of its own slot in the active group, usually
+    // after a manual `hyprctl dispatch workspace`. Reality wins and the model follows.
+    Adopt {
+        slot: SlotId,
+        visible: VisibleWorkspace,
+    },
+    // The monitor shows some other group's or slot's workspace. Group changes must come from
+    // hywoma commands, so the expected workspace is put back.
+    Restore {
+        monitor_id: u64,
+        workspace_id: u64,
+    },
+}
+
+// `shown` is (runtime monitor ID, workspace ID) for every monitor. Monitors without a slot and
+// workspaces hywoma never allocated are left alone: they are not part of the model.
+pub fn plan(state: &mut State, shown: &[(u64, u64)]) -> Vec<Correction> {
+    let mut corrections = Vec::new();
+    for &(monitor_id, workspace_id) in shown {
+        let Some(slot) = state.slot_for_monitor_id(monitor_id) else {
+            continue;
+        };
+        if state.is_unmanaged(workspace_id) {
+            continue;
+        }
+        let group = state.active_group;
+        let expected = state.known_workspace_id(group, slot, state.active_visible(slot));
+        if expected == Some(workspace_id) {
+            continue;
+        }
+        match state.key_for_workspace_id(workspace_id) {
+            Some(key) if key.group == group && key.slot == slot => {
+                corrections.push(Correction::Adopt {
+                    slot,
+                    visible: key.visible,
+                });
+            }
+            Some(_) => corrections.push(Correction::Restore {
+                monitor_id,
+                workspace_id: state.workspace_id_for(group, slot, state.active_visible(slot)),
+            }),
+            None => {}
+        }
+    }
+    corrections
+}
+
+pub fn apply(
+    config: &Config,
+    state: &mut State,
+    corrections: &[Correction],
+    focused_slot: SlotId,
+) -> Result<()> {
+    let mut restored = false;
+    for correction in corrections {
+        match *correction {
+            Correction::Adopt { slot, visible } => state.set_active_visible(slot, visible),
+            Correction::Restore {
+                monitor_id,
+                workspace_id,
+            } => {
+                hyprctl(config, &format!("dispatch focusmonitor {monitor_id}"))?;
+                hyprctl(config, &format!("dispatch workspace {workspace_id}"))?;
+                restored = true;
+            }
+        }
+    }
+    // Restoring moves focus around; hand it back to the slot the user was on.
+    if restored && let Some(monitor_id) = state.runtime_monitor_id_for_slot(focused_slot) {
+        hyprctl(config, &format!("dispatch focusmonitor {monitor_id}"))?;
+    }
+    Ok(())
+}
+
+// `shown` is (runtime monitor ID, workspace ID) for every monitor before startup moved them onto
+// hywoma's workspaces.
+pub fn strays(
+    state: &State,
+    clients: &[ClientInfo],
+    shown: &[(u64, u64)],
+    focused_slot: SlotId,
+) -> Vec<Stray> {
+    clients
+        .iter()
+        .filter(|client| {
+            !state.is_unmanaged(client.workspace_id)
+                && state.key_for_workspace_id(client.workspace_id).is_none()
+                && state
+                    .default_key_for_workspace_id(client.workspace_id)
+                    .is_none()
+        })
+        .map(|client| {
+            let slot = shown
+                .iter()
+                .find(|(_, workspace_id)| *workspace_id == client.workspace_id)
+                .and_then(|(monitor_id, _)| state.slot_for_monitor_id(*monitor_id))
+                .unwrap_or(focused_slot);
+            let visible = if (1..=state.workspace_count(slot)).contains(&client.workspace_id) {
+                client.workspace_id
+            } else {
+                state.active_visible(slot)
+            };
+            Stray {
+                address: client.address.clone(),
+                class: client.class.clone(),
+                workspace_id: client.workspace_id,
+                target: WorkspaceKey {
+                    group: state.active_group,
+                    slot,
+                    visible,
+                },
+            }
+        })
+        .collect()
+}
+
+pub fn check_startup(
+    config: &Config,
+    mode: StartupCheck,
+    state: &mut State,
+    shown: &[(u64, u64)],
+    focused_slot: SlotId,
+) -> Result<()> {
+    let strays = strays(state, &hyprland::get_clients()?, shown, focused_slot);
+    if strays.is_empty() {
+        return Ok(());
+    }
+    let report: Vec<String> = strays
+        .iter()
+        .map(|stray| {
+            format!(
+                "  {} on workspace {} (slot {} workspace {})",
+                stray.class, stray.workspace_id, stray.target.slot, stray.target.visible
+            )
+        })
+        .collect();
+    let report = format!(
+        "{} window(s) outside hywoma's workspaces:\n{}",
+        strays.len(),
+        report.join("\n")
+    );
+    match mode {
+        StartupCheck::Observe => tracing::info!("{report}"),
+        StartupCheck::Strict => bail!("{report}\nNot starting: reconcile.startup is \"strict\""),
+        StartupCheck::Adopt => {
+            let dispatches: Vec<String> = strays
+                .iter()
+                .map(|stray| {
+                    let target = stray.target;
+                    let workspace_id =
+                        state.workspace_id_for(target.group, target.slot, target.visible);
+                    format!(
+                        "dispatch movetoworkspacesilent {workspace_id},address:0x{}",
+                        stray.address
+                    )
+                })
+                .collect();
+            hyprland::hyprctl_batch(config, &dispatches)?;
+            tracing::info!("Adopting {report}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Slot;
+    use std::collections::HashSet;
+
+    fn client(address: &str, workspace_id: u64) -> ClientInfo {
+        ClientInfo {
+            address: address.to_string(),
+            class: "kitty".to_string(),
+            title: String::new(),
+            workspace_id,
+            pid: 1,
+            floating: false,
+        }
+    }
+
+    #[test]
+    fn strays_keep_their_workspace_number_on_the_monitor_that_showed_them() {
+        let mut state = state();
+        let managed = state.workspace_id_for(0, 1, 1);
+        let clients = [client("a", managed), client("b", 3), client("c", 42)];
+
+        let targets: Vec<_> = strays(&state, &clients, &[(20, 3)], 1)
+            .into_iter()
+            .map(|stray| (stray.address, stray.target.slot, stray.target.visible))
+            .collect();
+        assert_eq!(
+            targets,
+            vec![("b".to_string(), 2, 3), ("c".to_string(), 1, 1)]
+        );
+
+        state.set_unmanaged_workspaces(HashSet::from([3]));
+        let targets: Vec<_> = strays(&state, &clients, &[(20, 3)], 1)
+            .into_iter()
+            .map(|stray| stray.address)
+            .collect();
+        assert_eq!(targets, ["c"]);
+    }
+
+    fn state() -> State {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")]);
+        state.attach_output(1, "DP-1", 10);
+        state.attach_output(2, "DP-2", 20);
+        state
+    }
+
+    #[test]
+    fn matching_monitors_need_no_correction() {
+        let mut state = state();
+        let left = state.workspace_id_for(0, 1, 1);
+        let middle = state.workspace_id_for(0, 2, 1);
+
+        assert_eq!(plan(&mut state, &[(10, left), (20, middle)]), vec![]);
+    }
+
+    #[test]
+    fn adopts_manual_switches_and_restores_foreign_workspaces() {
+        let mut state = state();
+        let work = state.create_group("Work");
+        let left = state.workspace_id_for(0, 1, 1);
+        let left_third = state.workspace_id_for(0, 1, 3);
+        let middle = state.workspace_id_for(0, 2, 1);
+        let work_middle = state.workspace_id_for(work, 2, 1);
+
+        assert_eq!(
+            plan(
+                &mut state,
+                &[(10, left_third), (20, work_middle), (30, left)]
+            ),
+            vec![
+                Correction::Adopt {
+                    slot: 1,
+                    visible: 3
+                },
+                Correction::Restore {
+                    monitor_id: 20,
+                    workspace_id: middle
+                },
+            ]
+        );
+    }
+}
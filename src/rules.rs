@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+
+use crate::state::{GroupId, SlotId, State, VisibleWorkspace};
+
+// Placement rule: new windows with this class are moved to a visible workspace of a group. Without
+// an explicit slot the window stays on the slot that is focused when it opens, which matches how
+// `move_to_group` keeps windows on their logical monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub class: String,
+    pub group: GroupId,
+    pub workspace: VisibleWorkspace,
+    #[serde(default)]
+    pub slot: Option<SlotId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleRequest {
+    Add(Rule),
+    List,
+    Remove(usize),
+    Export,
+}
+
+pub fn matching<'a>(rules: &'a [Rule], class: &str) -> Option<&'a Rule> {
+    // First match wins, so more specific rules can be listed (or added live) before general ones.
+    rules.iter().find(|rule| rule.class == class)
+}
+
+// Why a rule could never place a window. Without a slot the window opens on the focused one, so the
+// workspace only has to exist on some slot until `apply_rule` knows which.
+pub fn validate(rule: &Rule, state: &State) -> Result<(), String> {
+    match rule.slot {
+        Some(slot) => validate_placement(state, slot, rule.workspace),
+        None if state
+            .slot_ids()
+            .into_iter()
+            .any(|slot| (1..=state.workspace_count(slot)).contains(&rule.workspace)) =>
+        {
+            Ok(())
+        }
+        None => Err(format!(
+            "No slot has a visible workspace {}",
+            rule.workspace
+        )),
+    }
+}
+
+pub fn validate_placement(
+    state: &State,
+    slot: SlotId,
+    workspace: VisibleWorkspace,
+) -> Result<(), String> {
+    if !state.has_slot(slot) {
+        return Err(format!("No slot {slot}"));
+    }
+    let workspace_count = state.workspace_count(slot);
+    if !(1..=workspace_count).contains(&workspace) {
+        return Err(format!(
+            "Cannot use workspace {workspace} on slot {slot}: visible workspaces are 1..={workspace_count}"
+        ));
+    }
+    Ok(())
+}
+
+// The configured rules that can place windows, warning about the others.
+pub fn valid(rules: &[Rule], state: &State) -> Vec<Rule> {
+    rules
+        .iter()
+        .filter(|rule| match validate(rule, state) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("Ignoring configured rule {}: {err}", describe(rule));
+                false
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn handle_request(
+    rules: &mut Vec<Rule>,
+    request: RuleRequest,
+    state: &State,
+) -> Result<String, String> {
+    match request {
+        RuleRequest::Add(rule) => {
+            if let Err(err) = validate(&rule, state) {
+                return Err(format!("Cannot add rule {}: {err}", describe(&rule)));
+            }
+            rules.push(rule);
+            Ok(format!(
+                "Added rule {}: {}",
+                rules.len(),
+                describe(&rules[rules.len() - 1])
+            ))
+        }
+        RuleRequest::List => {
+            if rules.is_empty() {
+                return Ok("No rules".to_string());
+            }
+            Ok(rules
+                .iter()
+                .enumerate()
+                .map(|(index, rule)| format!("{}: {}", index + 1, describe(rule)))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+        RuleRequest::Remove(index) => {
+            // Indexes are 1-based to match `rule list` output.
+            if index == 0 || index > rules.len() {
+                return Err(format!(
+                    "No rule {index}, there are {} rule(s)",
+                    rules.len()
+                ));
+            }
+            let rule = rules.remove(index - 1);
+            Ok(format!("Removed rule {index}: {}", describe(&rule)))
+        }
+        RuleRequest::Export => export(rules),
+    }
+}
+
+fn describe(rule: &Rule) -> String {
+    let slot = match rule.slot {
+        Some(slot) => format!("slot {slot}"),
+        None => "focused slot".to_string(),
+    };
+    format!(
+        "class {:?} -> group {} workspace {} on {slot}",
+        rule.class, rule.group, rule.workspace
+    )
+}
+
+// Prints the rules as a config.toml fragment, ready to be pasted into the config file.
+fn export(rules: &[Rule]) -> Result<String, String> {
+    #[derive(Serialize)]
+    struct Exported<'a> {
+        rules: &'a [Rule],
+    }
+
+    toml::to_string(&Exported { rules })
+        .map(|exported| exported.trim_end().to_string())
+        .map_err(|err| format!("Failed to export rules: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::Slot;
+
+    fn state() -> State {
+        State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "right")])
+    }
+
+    fn rule(class: &str, workspace: VisibleWorkspace) -> Rule {
+        Rule {
+            class: class.to_string(),
+            group: 1,
+            workspace,
+            slot: None,
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [rule("firefox", 2), rule("firefox", 3), rule("kitty", 1)];
+
+        assert_eq!(matching(&rules, "firefox"), Some(&rules[0]));
+        assert_eq!(matching(&rules, "Slack"), None);
+    }
+
+    #[test]
+    fn removes_rules_by_listed_index() {
+        let mut rules = vec![rule("firefox", 2), rule("kitty", 1)];
+
+        handle_request(&mut rules, RuleRequest::Remove(1), &state()).unwrap();
+        let missing = handle_request(&mut rules, RuleRequest::Remove(5), &state());
+
+        assert_eq!(rules, vec![rule("kitty", 1)]);
+        assert!(missing.unwrap_err().starts_with("No rule 5"));
+    }
+
+    #[test]
+    fn rules_that_cannot_place_windows_are_not_added() {
+        let state = state();
+        let mut rules = Vec::new();
+
+        let zero = handle_request(&mut rules, RuleRequest::Add(rule("firefox", 0)), &state);
+        let beyond = handle_request(&mut rules, RuleRequest::Add(rule("firefox", 11)), &state);
+        let detached = handle_request(
+            &mut rules,
+            RuleRequest::Add(Rule {
+                slot: Some(3),
+                ..rule("firefox", 2)
+            }),
+            &state,
+        );
+        handle_request(&mut rules, RuleRequest::Add(rule("kitty", 10)), &state).unwrap();
+
+        assert!(zero.unwrap_err().starts_with("Cannot add rule"));
+        assert!(beyond.unwrap_err().starts_with("Cannot add rule"));
+        assert!(detached.unwrap_err().ends_with("No slot 3"));
+        assert_eq!(rules, vec![rule("kitty", 10)]);
+    }
+
+    #[test]
+    fn configured_rules_outside_the_workspaces_are_dropped() {
+        let mut state = state();
+        state.set_slot_defaults(2, None, Some(3));
+        let rules = [
+            Rule {
+                slot: Some(2),
+                ..rule("firefox", 4)
+            },
+            Rule {
+                slot: Some(1),
+                ..rule("kitty", 4)
+            },
+            rule("slack", 0),
+        ];
+
+        assert_eq!(valid(&rules, &state), [rules[1].clone()]);
+    }
+
+    #[test]
+    fn exported_rules_load_back_as_config() {
+        let rules = vec![
+            rule("firefox", 2),
+            Rule {
+                slot: Some(1),
+                ..rule("kitty", 4)
+            },
+        ];
+
+        let exported = export(&rules);
+
+        assert_eq!(Config::parse(&exported.unwrap()).unwrap().rules, rules);
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::{Duration, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::hooks;
+use crate::state::GroupId;
+
+// Seconds between scheduler ticks. Switches happen within this much of their time.
+pub const TICK_SECS: u64 = 15;
+
+// Gentle context-switching nudges: at each entry's time a notification announces the switch, which
+// happens `warning_secs` later unless `hywoma snooze` postpones it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    // e.g. `entries = [{ at = "09:00", group = 1 }, { at = "18:00", group = 2 }]`, local time.
+    pub entries: Vec<ScheduleEntry>,
+    pub warning_secs: u64,
+    // Default for `hywoma snooze` without an argument.
+    pub snooze_minutes: u64,
+    // Gets the notification text as its only argument.
+    pub notify_command: String,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            entries: Vec::new(),
+            warning_secs: 60,
+            snooze_minutes: 10,
+            notify_command: "notify-send".to_string(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    // Run when the config is loaded, so a typo in `at` is reported once rather than on every tick.
+    pub fn drop_invalid_entries(&mut self) {
+        self.entries.retain(|entry| match entry.time() {
+            Ok(_) => true,
+            Err(err) => {
+                tracing::warn!("Ignoring schedule entry at {:?}: {err}", entry.at);
+                false
+            }
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleEntry {
+    pub at: String,
+    pub group: GroupId,
+}
+
+impl ScheduleEntry {
+    fn time(&self) -> chrono::ParseResult<NaiveTime> {
+        NaiveTime::parse_from_str(&self.at, "%H:%M")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PendingSwitch {
+    group: GroupId,
+    at: NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    last_tick: Option<NaiveDateTime>,
+    pending: Option<PendingSwitch>,
+}
+
+impl Scheduler {
+    // Returns the group to switch to now, if a scheduled switch is due.
+    pub fn tick(&mut self, config: &ScheduleConfig, now: NaiveDateTime) -> Option<GroupId> {
+        // Only times passed since the previous tick count. That skips everything before startup
+        // and after a suspend announces just the latest missed entry, not each one.
+        if let Some(last_tick) = self.last_tick
+            && let Some(group) = latest_entry_between(config, last_tick, now)
+        {
+            self.pending = Some(PendingSwitch {
+                group,
+                at: now + Duration::seconds(config.warning_secs as i64),
+            });
+            notify(
+                config,
+                &format!(
+                    "Switching to group {group} in {} seconds. Run `hywoma snooze` to postpone.",
+                    config.warning_secs
+                ),
+            );
+        }
+        self.last_tick = Some(now);
+
+        let pending = self.pending?;
+        if now < pending.at {
+            return None;
+        }
+        self.pending = None;
+        Some(pending.group)
+    }
+
+    pub fn snooze(&mut self, config: &ScheduleConfig, now: NaiveDateTime, minutes: Option<u64>) {
+        let minutes = minutes.unwrap_or(config.snooze_minutes);
+        match &mut self.pending {
+            Some(pending) => {
+                pending.at = now + Duration::minutes(minutes as i64);
+                notify(
+                    config,
+                    &format!(
+                        "Switch to group {} snoozed for {minutes} minutes",
+                        pending.group
+                    ),
+                );
+            }
+            None => tracing::warn!("No scheduled group switch to snooze"),
+        }
+    }
+}
+
+fn latest_entry_between(
+    config: &ScheduleConfig,
+    after: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Option<GroupId> {
+    let mut latest: Option<(NaiveDateTime, GroupId)> = None;
+    for entry in &config.entries {
+        // Reported when the config was loaded.
+        let Ok(time) = entry.time() else {
+            continue;
+        };
+        // Yesterday's occurrence covers ticks that straddle midnight.
+        let today = until.date().and_time(time);
+        for occurrence in [today - Duration::days(1), today] {
+            if after < occurrence
+                && occurrence <= until
+                && latest.is_none_or(|(latest, _)| occurrence > latest)
+            {
+                latest = Some((occurrence, entry.group));
+            }
+        }
+    }
+    latest.map(|(_, group)| group)
+}
+
+fn notify(config: &ScheduleConfig, text: &str) {
+    tracing::info!("{text}");
+    hooks::spawn(&config.notify_command, &["hywoma", text]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2026-03-02 {value}"), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn config() -> ScheduleConfig {
+        ScheduleConfig {
+            entries: vec![
+                ScheduleEntry {
+                    at: "09:00".to_string(),
+                    group: 1,
+                },
+                ScheduleEntry {
+                    at: "18:00".to_string(),
+                    group: 2,
+                },
+            ],
+            // Keep the test from spawning anything meaningful.
+            notify_command: "true".to_string(),
+            ..ScheduleConfig::default()
+        }
+    }
+
+    #[test]
+    fn switches_after_the_warning_unless_snoozed() {
+        let config = config();
+        let mut scheduler = Scheduler::default();
+
+        assert_eq!(scheduler.tick(&config, time("08:59:50")), None);
+        assert_eq!(scheduler.tick(&config, time("09:00:05")), None);
+        assert_eq!(scheduler.tick(&config, time("09:01:05")), Some(1));
+        assert_eq!(scheduler.tick(&config, time("09:01:20")), None);
+
+        assert_eq!(scheduler.tick(&config, time("18:00:10")), None);
+        scheduler.snooze(&config, time("18:00:30"), Some(5));
+        assert_eq!(scheduler.tick(&config, time("18:01:20")), None);
+        assert_eq!(scheduler.tick(&config, time("18:05:30")), Some(2));
+    }
+
+    #[test]
+    fn ignores_entries_before_the_first_tick() {
+        let config = config();
+        let mut scheduler = Scheduler::default();
+
+        assert_eq!(scheduler.tick(&config, time("09:00:00")), None);
+        assert_eq!(scheduler.tick(&config, time("09:30:00")), None);
+    }
+
+    #[test]
+    fn drops_entries_without_a_valid_time() {
+        let mut config = config();
+        config.entries.push(ScheduleEntry {
+            at: "25:00".to_string(),
+            group: 3,
+        });
+        config.entries.push(ScheduleEntry {
+            at: "noon".to_string(),
+            group: 4,
+        });
+
+        config.drop_invalid_entries();
+
+        assert_eq!(config, self::config());
+    }
+}
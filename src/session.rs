@@ -0,0 +1,176 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::hyprland::{self, ClientInfo, hyprctl_dispatch as hyprctl};
+use crate::state::{GroupId, SlotId, State, VisibleWorkspace};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    // Relaunch the recorded apps when the daemon starts in a fresh Hyprland session.
+    pub restore: bool,
+}
+
+// One window of the session, by logical workspace: the internal IDs of the last session mean
+// nothing after a reboot, the group/slot/visible label does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionWindow {
+    pub group: GroupId,
+    pub group_name: String,
+    pub slot: SlotId,
+    pub visible: VisibleWorkspace,
+    pub class: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub windows: Vec<SessionWindow>,
+}
+
+// Unlike the runtime state this has to survive a reboot, so it lives under XDG_STATE_HOME.
+fn session_path() -> Result<PathBuf> {
+    let state_home = env::var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map_err(|_| anyhow!("Neither XDG_STATE_HOME nor HOME is set"))?;
+    Ok(state_home.join("hywoma").join("session.json"))
+}
+
+// Quotes an argument for `exec`, which hands the command to a shell.
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+pub fn command_line(pid: i32) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|byte| *byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| shell_quote(&String::from_utf8_lossy(arg)))
+        .collect();
+    (!args.is_empty()).then(|| args.join(" "))
+}
+
+pub fn record(state: &State) -> Result<Session> {
+    Ok(windows(state, hyprland::get_clients()?, command_line))
+}
+
+fn windows(
+    state: &State,
+    clients: Vec<ClientInfo>,
+    command_line: impl Fn(i32) -> Option<String>,
+) -> Session {
+    let mut windows = Vec::new();
+    let mut recorded_pids = HashSet::new();
+    for client in clients {
+        let Some(key) = state.key_for_workspace_id(client.workspace_id) else {
+            continue;
+        };
+        // A process with several windows (e.g. two browser windows) is launched only once. Separate
+        // processes of the same program each get their own launch.
+        if recorded_pids.contains(&client.pid) {
+            continue;
+        }
+        let Some(command) = command_line(client.pid) else {
+            continue;
+        };
+        recorded_pids.insert(client.pid);
+        windows.push(SessionWindow {
+            group: key.group,
+            group_name: state
+                .groups
+                .get(&key.group)
+                .map(|group| group.name.clone())
+                .unwrap_or_default(),
+            slot: key.slot,
+            visible: key.visible,
+            class: client.class,
+            command,
+        });
+    }
+    Session { windows }
+}
+
+pub fn save(state: &State) -> Result<usize> {
+    let session = record(state)?;
+    let path = session_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(&session)?)?;
+    fs::rename(tmp_path, path)?;
+    Ok(session.windows.len())
+}
+
+// Launches every recorded window straight onto its workspace with an exec rule, so nothing has to
+// be moved once it appears.
+pub fn restore(config: &Config, state: &mut State) -> Result<usize> {
+    let path = session_path()?;
+    let session: Session = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    for window in &session.windows {
+        state.ensure_group(window.group, window.group_name.clone());
+        let workspace_id = state.workspace_id_for(window.group, window.slot, window.visible);
+        let dispatch = format!(
+            "dispatch exec [workspace {workspace_id} silent] {}",
+            window.command
+        );
+        if let Err(err) = hyprctl(config, &dispatch) {
+            tracing::warn!("Failed to relaunch {:?}: {err:?}", window.class);
+        }
+    }
+    Ok(session.windows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shell_quote, windows};
+    use crate::hyprland::ClientInfo;
+    use crate::state::{Slot, State};
+
+    #[test]
+    fn quotes_only_arguments_that_need_it() {
+        assert_eq!(shell_quote("--new-window"), "--new-window");
+        assert_eq!(shell_quote("/home/me/My Notes"), "'/home/me/My Notes'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn records_each_process_once_but_every_process() {
+        let mut state = State::new([Slot::new(1, "u", "left"), Slot::new(2, "i", "right")]);
+        let kitty = |pid, workspace_id| ClientInfo {
+            address: format!("{pid}{workspace_id}"),
+            class: "kitty".to_string(),
+            title: String::new(),
+            workspace_id,
+            pid,
+            floating: false,
+        };
+        let clients = vec![
+            kitty(10, state.workspace_id_for(0, 1, 1)),
+            kitty(10, state.workspace_id_for(0, 1, 2)),
+            kitty(11, state.workspace_id_for(0, 2, 1)),
+        ];
+
+        let session = windows(&state, clients, |_| Some("kitty".to_string()));
+
+        let placed: Vec<_> = session
+            .windows
+            .iter()
+            .map(|window| (window.slot, window.visible))
+            .collect();
+        assert_eq!(placed, [(1, 1), (2, 1)]);
+    }
+}
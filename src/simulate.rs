@@ -0,0 +1,513 @@
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::sync::{Mutex, OnceLock, mpsc};
+
+use crate::app::Message;
+use crate::hyprland;
+
+const MONITOR_WIDTH: i64 = 1920;
+const MONITOR_HEIGHT: i64 = 1080;
+
+// `hywoma server --simulate` swaps the Hyprland sockets for this in-memory compositor. It is a
+// process-wide switch on purpose: hyprctl and the event reader are free functions, and routing them
+// here keeps the rest of the daemon byte-for-byte the same code path as a live session.
+static SIMULATOR: OnceLock<Mutex<FakeCompositor>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: u64,
+    pub rows: u64,
+}
+
+impl Layout {
+    pub fn parse(value: &str) -> Result<Self> {
+        // `3x1` is three monitors side by side. A bare count is accepted as a single row.
+        let (columns, rows) = value.split_once('x').unwrap_or((value, "1"));
+        let layout = Layout {
+            columns: columns
+                .parse()
+                .map_err(|_| anyhow!("Invalid simulated monitor layout {value:?}"))?,
+            rows: rows
+                .parse()
+                .map_err(|_| anyhow!("Invalid simulated monitor layout {value:?}"))?,
+        };
+        if layout.columns == 0 || layout.rows == 0 {
+            return Err(anyhow!(
+                "Simulated monitor layout {value:?} must contain at least one monitor"
+            ));
+        }
+        Ok(layout)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FakeMonitor {
+    id: u64,
+    name: String,
+    x: i64,
+    y: i64,
+    active_workspace: u64,
+    special_workspace: Option<String>,
+}
+
+#[derive(Debug)]
+struct FakeCompositor {
+    monitors: Vec<FakeMonitor>,
+    focused: usize,
+    // Present workspaces with their window counts. Like Hyprland, an empty workspace disappears as
+    // soon as no monitor shows it, so present workspace tracking gets exercised as well.
+    windows: BTreeMap<u64, usize>,
+    // From `renameworkspace`, as long as the workspace exists.
+    names: BTreeMap<u64, String>,
+    events: Option<mpsc::Sender<Message>>,
+}
+
+pub fn install(layout: Layout) {
+    if SIMULATOR
+        .set(Mutex::new(FakeCompositor::new(layout)))
+        .is_err()
+    {
+        panic!("simulated compositor installed twice");
+    }
+    tracing::info!(
+        "Simulating {}x{} virtual monitors, runtime state is not loaded or saved",
+        layout.columns,
+        layout.rows
+    );
+}
+
+pub fn is_active() -> bool {
+    SIMULATOR.get().is_some()
+}
+
+pub fn hyprctl(command: &str) -> Option<String> {
+    let simulator = SIMULATOR.get()?;
+    let mut simulator = simulator
+        .lock()
+        .expect("simulated compositor lock poisoned");
+    Some(simulator.request(command))
+}
+
+pub fn subscribe(tx: mpsc::Sender<Message>) {
+    if let Some(simulator) = SIMULATOR.get() {
+        simulator
+            .lock()
+            .expect("simulated compositor lock poisoned")
+            .events = Some(tx);
+    }
+}
+
+impl FakeCompositor {
+    fn new(layout: Layout) -> Self {
+        let mut monitors = Vec::new();
+        for row in 0..layout.rows {
+            for column in 0..layout.columns {
+                let id = monitors.len() as u64;
+                monitors.push(FakeMonitor {
+                    id,
+                    name: format!("SIM-{}", id + 1),
+                    x: column as i64 * MONITOR_WIDTH,
+                    y: row as i64 * MONITOR_HEIGHT,
+                    // Hyprland without workspace rules starts monitors on 1, 2, 3, ...; that also
+                    // exercises hywoma's legacy startup path.
+                    active_workspace: id + 1,
+                    special_workspace: None,
+                });
+            }
+        }
+        // Give every initial workspace a window so movetoworkspacesilent has something to move.
+        let windows = monitors
+            .iter()
+            .map(|monitor| (monitor.active_workspace, 1))
+            .collect();
+
+        FakeCompositor {
+            monitors,
+            focused: 0,
+            windows,
+            names: BTreeMap::new(),
+            events: None,
+        }
+    }
+
+    fn request(&mut self, command: &str) -> String {
+        match command {
+            "-j/monitors" => self.monitors_json(),
+            "-j/activeworkspace" => {
+                let monitor = &self.monitors[self.focused];
+                json!({
+                    "id": monitor.active_workspace,
+                    "monitorID": monitor.id,
+                    "monitor": monitor.name,
+                })
+                .to_string()
+            }
+            "-j/workspaces" => self.workspaces_json(),
+            // The simulated pointer rests in the middle of the focused monitor.
+            "-j/cursorpos" => {
+                let monitor = &self.monitors[self.focused];
+                json!({
+                    "x": monitor.x + MONITOR_WIDTH / 2,
+                    "y": monitor.y + MONITOR_HEIGHT / 2,
+                })
+                .to_string()
+            }
+            // The simulated compositor has no windows.
+            "-j/activewindow" => "{}".to_string(),
+            "-j/clients" => "[]".to_string(),
+            _ if command.starts_with("[[BATCH]]") => command["[[BATCH]]".len()..]
+                .split(';')
+                .map(|command| self.request(command))
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            // Config keywords have no effect on the simulated layout.
+            _ if command.starts_with("keyword ") => "ok".to_string(),
+            _ => match command.strip_prefix("dispatch ") {
+                Some(dispatch) => {
+                    let response = self.dispatch(dispatch);
+                    tracing::info!("Simulated compositor: {}", self.describe());
+                    response
+                }
+                None => "unknown request".to_string(),
+            },
+        }
+    }
+
+    fn monitors_json(&self) -> String {
+        let monitors: Vec<serde_json::Value> = self
+            .monitors
+            .iter()
+            .enumerate()
+            .map(|(index, monitor)| {
+                json!({
+                    "id": monitor.id,
+                    "name": monitor.name,
+                    "x": monitor.x,
+                    "y": monitor.y,
+                    "width": MONITOR_WIDTH,
+                    "height": MONITOR_HEIGHT,
+                    "activeWorkspace": { "id": monitor.active_workspace },
+                    "focused": index == self.focused,
+                })
+            })
+            .collect();
+        serde_json::Value::Array(monitors).to_string()
+    }
+
+    fn workspaces_json(&self) -> String {
+        let mut workspaces: Vec<serde_json::Value> = self
+            .windows
+            .iter()
+            .map(|(id, windows)| {
+                json!({
+                    "id": id,
+                    "name": self.names.get(id).cloned().unwrap_or_else(|| id.to_string()),
+                    "monitorID": self.monitor_showing(*id).map(|index| self.monitors[index].id),
+                    "windows": windows,
+                })
+            })
+            .collect();
+        // Shown special workspaces are listed too, with negative IDs like Hyprland's.
+        workspaces.extend(self.monitors.iter().zip(-99..).filter_map(|(monitor, id)| {
+            let name = monitor.special_workspace.as_ref()?;
+            Some(json!({
+                "id": id,
+                "name": format!("special:{name}"),
+                "monitorID": monitor.id,
+                "windows": 0,
+            }))
+        }));
+        serde_json::Value::Array(workspaces).to_string()
+    }
+
+    fn dispatch(&mut self, dispatch: &str) -> String {
+        let (dispatcher, args) = dispatch.split_once(' ').unwrap_or((dispatch, ""));
+        // Window selectors (`1005,address:0x...`) target windows the simulation does not model.
+        if args.contains(',') {
+            tracing::info!("Simulated compositor ignores window dispatch {dispatch:?}");
+            return "ok".to_string();
+        }
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let result = match (dispatcher, args.as_slice()) {
+            ("workspace", [workspace]) => workspace
+                .parse()
+                .map(|workspace| self.focus_workspace(workspace)),
+            ("focusmonitor", [monitor]) => {
+                monitor.parse().map(|monitor| self.focus_monitor(monitor))
+            }
+            ("movetoworkspacesilent", [workspace]) if workspace.starts_with("special") => {
+                tracing::info!("Simulated compositor ignores window dispatch {dispatch:?}");
+                return "ok".to_string();
+            }
+            ("movetoworkspacesilent", [workspace]) => workspace
+                .parse()
+                .map(|workspace| self.move_window_silent(workspace)),
+            ("movetoworkspace", [workspace]) => workspace.parse().map(|workspace| {
+                self.move_window_silent(workspace);
+                self.focus_workspace(workspace);
+            }),
+            ("renameworkspace", [workspace, name @ ..]) => workspace.parse().map(|workspace| {
+                self.names.insert(workspace, name.join(" "));
+            }),
+            ("togglespecialworkspace", [] | [_]) => {
+                self.toggle_special(args.first().copied().unwrap_or("special"));
+                Ok(())
+            }
+            ("swapactiveworkspaces", [source, target]) => match (source.parse(), target.parse()) {
+                (Ok(source), Ok(target)) => {
+                    self.swap_active_workspaces(source, target);
+                    Ok(())
+                }
+                (Err(err), _) | (_, Err(err)) => Err(err),
+            },
+            _ => {
+                // Unknown dispatchers are accepted as no-ops so scripts written against a live
+                // session can still be exercised; only the hywoma-visible effects are modelled.
+                tracing::info!("Simulated compositor ignores dispatch {dispatch:?}");
+                return "ok".to_string();
+            }
+        };
+
+        match result {
+            Ok(()) => "ok".to_string(),
+            Err(err) => format!("error: invalid argument for {dispatcher}: {err}"),
+        }
+    }
+
+    // Special workspaces hold no windows here, only whether one is shown matters to hywoma.
+    fn toggle_special(&mut self, name: &str) {
+        let monitor = &mut self.monitors[self.focused];
+        let name = (monitor.special_workspace.as_deref() != Some(name)).then(|| name.to_string());
+        monitor.special_workspace = name.clone();
+        let monitor_name = monitor.name.clone();
+        self.emit(Message::SpecialWorkspaceChanged { monitor_name, name });
+    }
+
+    fn focus_workspace(&mut self, workspace_id: u64) {
+        // Hyprland focuses the monitor that already shows a workspace instead of stealing it.
+        if let Some(index) = self.monitor_showing(workspace_id) {
+            self.focused = index;
+            self.emit_active(index);
+            return;
+        }
+
+        self.ensure_workspace(workspace_id);
+        let previous = self.monitors[self.focused].active_workspace;
+        self.monitors[self.focused].active_workspace = workspace_id;
+        self.emit_active(self.focused);
+        self.destroy_if_unused(previous);
+    }
+
+    fn focus_monitor(&mut self, monitor_id: u64) {
+        if let Some(index) = self
+            .monitors
+            .iter()
+            .position(|monitor| monitor.id == monitor_id)
+        {
+            self.focused = index;
+            self.emit_active(index);
+        }
+    }
+
+    fn move_window_silent(&mut self, workspace_id: u64) {
+        let source = self.monitors[self.focused].active_workspace;
+        if source == workspace_id {
+            return;
+        }
+        let Some(count) = self.windows.get_mut(&source).filter(|count| **count > 0) else {
+            return;
+        };
+        *count -= 1;
+
+        self.ensure_workspace(workspace_id);
+        *self.windows.entry(workspace_id).or_default() += 1;
+    }
+
+    fn ensure_workspace(&mut self, workspace_id: u64) {
+        if let Entry::Vacant(entry) = self.windows.entry(workspace_id) {
+            entry.insert(0);
+            self.emit(Message::WorkspaceCreated { workspace_id });
+        }
+    }
+
+    fn swap_active_workspaces(&mut self, source_id: u64, target_id: u64) {
+        let source = self.monitors.iter().position(|m| m.id == source_id);
+        let target = self.monitors.iter().position(|m| m.id == target_id);
+        let (Some(source), Some(target)) = (source, target) else {
+            return;
+        };
+
+        let source_workspace = self.monitors[source].active_workspace;
+        self.monitors[source].active_workspace = self.monitors[target].active_workspace;
+        self.monitors[target].active_workspace = source_workspace;
+        if self.focused == source || self.focused == target {
+            self.emit_active(self.focused);
+        }
+    }
+
+    fn destroy_if_unused(&mut self, workspace_id: u64) {
+        if self.monitor_showing(workspace_id).is_none()
+            && self.windows.get(&workspace_id) == Some(&0)
+        {
+            self.windows.remove(&workspace_id);
+            self.names.remove(&workspace_id);
+            self.emit(Message::WorkspaceDestroyed { workspace_id });
+        }
+    }
+
+    fn monitor_showing(&self, workspace_id: u64) -> Option<usize> {
+        self.monitors
+            .iter()
+            .position(|monitor| monitor.active_workspace == workspace_id)
+    }
+
+    fn emit_active(&self, index: usize) {
+        let monitor = &self.monitors[index];
+        self.emit(Message::ActiveWorkspaceChanged {
+            workspace_id: monitor.active_workspace,
+            monitor_name: Some(monitor.name.clone()),
+        });
+    }
+
+    fn emit(&self, message: Message) {
+        // Mirror what Hyprland would have written to socket2, so `events --raw` works here too.
+        let raw = match &message {
+            Message::ActiveWorkspaceChanged {
+                workspace_id,
+                monitor_name: Some(monitor_name),
+            } => Some(format!("focusedmonv2>>{monitor_name},{workspace_id}")),
+            Message::ActiveWorkspaceChanged { workspace_id, .. } => {
+                Some(format!("workspacev2>>{workspace_id},{workspace_id}"))
+            }
+            Message::WorkspaceCreated { workspace_id } => {
+                Some(format!("createworkspacev2>>{workspace_id},{workspace_id}"))
+            }
+            Message::WorkspaceDestroyed { workspace_id } => {
+                Some(format!("destroyworkspacev2>>{workspace_id},{workspace_id}"))
+            }
+            Message::SpecialWorkspaceChanged { monitor_name, name } => Some(format!(
+                "activespecial>>{},{monitor_name}",
+                name.as_ref()
+                    .map(|name| format!("special:{name}"))
+                    .unwrap_or_default()
+            )),
+            _ => None,
+        };
+        if let Some(raw) = raw {
+            hyprland::relay_raw_event(&raw);
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(message);
+        }
+    }
+
+    fn describe(&self) -> String {
+        self.monitors
+            .iter()
+            .enumerate()
+            .map(|(index, monitor)| {
+                let focused = if index == self.focused { "*" } else { "" };
+                let windows = self.windows.get(&monitor.active_workspace).unwrap_or(&0);
+                format!(
+                    "{}={}{focused} ({windows} window(s))",
+                    monitor.name, monitor.active_workspace
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(rx: &mpsc::Receiver<Message>) -> Vec<String> {
+        rx.try_iter()
+            .map(|message| format!("{message:?}"))
+            .collect()
+    }
+
+    #[test]
+    fn parses_grid_and_single_row_layouts() {
+        assert_eq!(
+            Layout::parse("3x1").unwrap(),
+            Layout {
+                columns: 3,
+                rows: 1,
+            }
+        );
+        assert_eq!(
+            Layout::parse("2").unwrap(),
+            Layout {
+                columns: 2,
+                rows: 1,
+            }
+        );
+        assert!(Layout::parse("0x1").is_err());
+        assert!(Layout::parse("ax1").is_err());
+    }
+
+    #[test]
+    fn switching_away_from_empty_workspace_destroys_it() {
+        let mut compositor = FakeCompositor::new(Layout::parse("2x1").unwrap());
+        let (tx, rx) = mpsc::channel();
+        compositor.events = Some(tx);
+
+        compositor.request("dispatch workspace 1000");
+        compositor.request("dispatch workspace 1001");
+
+        assert_eq!(compositor.monitors[0].active_workspace, 1001);
+        assert!(compositor.windows.contains_key(&1));
+        assert!(!compositor.windows.contains_key(&1000));
+        let events = messages(&rx);
+        assert!(events.contains(&"WorkspaceCreated { workspace_id: 1000 }".to_string()));
+        assert!(events.contains(&"WorkspaceDestroyed { workspace_id: 1000 }".to_string()));
+    }
+
+    #[test]
+    fn selecting_workspace_shown_elsewhere_focuses_that_monitor() {
+        let mut compositor = FakeCompositor::new(Layout::parse("2x1").unwrap());
+
+        compositor.request("dispatch workspace 2");
+
+        assert_eq!(compositor.focused, 1);
+        assert_eq!(compositor.monitors[0].active_workspace, 1);
+    }
+
+    #[test]
+    fn swaps_active_workspaces_between_monitors() {
+        let mut compositor = FakeCompositor::new(Layout::parse("3x1").unwrap());
+
+        compositor.request("dispatch swapactiveworkspaces 0 2");
+
+        assert_eq!(compositor.monitors[0].active_workspace, 3);
+        assert_eq!(compositor.monitors[2].active_workspace, 1);
+    }
+
+    #[test]
+    fn shown_special_workspaces_do_not_break_workspace_queries() {
+        let mut compositor = FakeCompositor::new(Layout::parse("2x1").unwrap());
+
+        compositor.request("dispatch togglespecialworkspace term");
+
+        assert!(compositor.request("-j/workspaces").contains("special:term"));
+        assert_eq!(
+            hyprland::parse_workspace_ids(&compositor.request("-j/workspaces")).unwrap(),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn moving_silently_keeps_focus_and_creates_target() {
+        let mut compositor = FakeCompositor::new(Layout::parse("1x1").unwrap());
+
+        compositor.request("dispatch movetoworkspacesilent 1005");
+
+        assert_eq!(compositor.monitors[0].active_workspace, 1);
+        assert_eq!(compositor.windows.get(&1), Some(&0));
+        assert_eq!(compositor.windows.get(&1005), Some(&1));
+    }
+}
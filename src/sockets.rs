@@ -0,0 +1,519 @@
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{Notify, broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::{MissedTickBehavior, timeout};
+
+use crate::app::{Message, ShutdownReason};
+use crate::audit;
+use crate::hyprland;
+use crate::progress::{self, Reporter};
+use crate::protocol::{self, Command, Progress};
+
+// How long a command token is remembered. A client retrying within it cannot run a command twice.
+const TOKEN_WINDOW: Duration = Duration::from_secs(300);
+// Clients write their whole request at once. One that stalls is dropped rather than holding up the
+// commands queued behind it.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+// Lines a follower may fall behind by before it skips ahead to the newest ones.
+const FOLLOWER_BACKLOG: usize = 16;
+
+// Connections still waiting for their reply or following the daemon, so a shutdown can let them
+// see the end instead of cutting them off.
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+static CONNECTIONS_CLOSED: Notify = Notify::const_new();
+
+struct Connection;
+
+impl Connection {
+    fn open() -> Self {
+        CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+        Connection
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if CONNECTIONS.fetch_sub(1, Ordering::Relaxed) == 1 {
+            CONNECTIONS_CLOSED.notify_waiters();
+        }
+    }
+}
+
+// Lines for every connection following the daemon, `hywoma events` subscribers and `hywoma
+// watch` clients. Each connection is a task of its own, so the main loop only hands lines over and
+// a stalled client never holds up workspace switching.
+pub struct Broadcast(broadcast::Sender<Arc<str>>);
+
+// What a follower starts from, taken by the main loop together with the subscription so no line
+// falls between them.
+#[derive(Debug)]
+pub struct Subscription {
+    initial: String,
+    updates: broadcast::Receiver<Arc<str>>,
+}
+
+impl Default for Broadcast {
+    fn default() -> Self {
+        Broadcast(broadcast::channel(FOLLOWER_BACKLOG).0)
+    }
+}
+
+impl Broadcast {
+    pub fn is_followed(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    pub fn send(&self, value: &impl Serialize) -> Result<()> {
+        // Nobody following is not an error, the next subscriber starts from a fresh snapshot.
+        let _ = self.0.send(line(value)?.into());
+        Ok(())
+    }
+
+    pub fn subscribe(&self, initial: &impl Serialize) -> Result<Subscription> {
+        Ok(Subscription {
+            initial: line(initial)?,
+            updates: self.0.subscribe(),
+        })
+    }
+}
+
+// Followers get the same full snapshot as `hywoma status`, but compact and newline delimited.
+// Full snapshots keep AGS simple and avoid ordering dependencies between fine grained events.
+fn line(value: &impl Serialize) -> Result<String> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    Ok(line)
+}
+
+#[derive(Debug, Default)]
+struct RecentTokens {
+    seen: HashMap<String, Instant>,
+}
+
+impl RecentTokens {
+    fn is_duplicate(&mut self, token: &str, now: Instant) -> bool {
+        self.seen
+            .retain(|_, seen| now.duration_since(*seen) < TOKEN_WINDOW);
+        if self.seen.contains_key(token) {
+            return true;
+        }
+        self.seen.insert(token.to_string(), now);
+        false
+    }
+}
+
+// A listener that fails takes the daemon down through the main loop, so state is saved and sockets
+// are cleaned up. The handle stops it once the main loop is gone.
+pub fn spawn_listener<F>(
+    tx: &mpsc::Sender<Message>,
+    reason: ShutdownReason,
+    listener: F,
+) -> JoinHandle<()>
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        if let Err(err) = listener.await {
+            tracing::error!("{}: {err:?}", reason.describe());
+            if tx.send(Message::Shutdown(reason)).is_err() {
+                exit(reason.exit_code());
+            }
+        }
+    })
+}
+
+// SIGTERM from systemd or the session and SIGINT from a terminal end the daemon through the main
+// loop like any other shutdown. A second signal exits at once, for a main loop stuck on Hyprland.
+pub fn spawn_signal_handler(tx: &mpsc::Sender<Message>) -> Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let mut received = false;
+        loop {
+            let name = tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = interrupt.recv() => "SIGINT",
+            };
+            if received {
+                tracing::warn!("Received {name} again, exiting without cleaning up");
+                exit(ShutdownReason::Signal.exit_code());
+            }
+            tracing::info!("Received {name}");
+            received = true;
+            if tx.send(Message::Shutdown(ShutdownReason::Signal)).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+// Once the main loop is gone, pending replies fail and followers reach the end of their stream.
+// Waits for them to be written, but not for clients that stopped reading.
+pub async fn close_connections(grace: Duration) {
+    let all_closed = async {
+        loop {
+            // Created before the check, so a connection closing in between still wakes it.
+            let closed = CONNECTIONS_CLOSED.notified();
+            if CONNECTIONS.load(Ordering::Relaxed) == 0 {
+                return;
+            }
+            closed.await;
+        }
+    };
+    if timeout(grace, all_closed).await.is_err() {
+        tracing::warn!("Closing connections that did not finish within {grace:?}");
+    }
+}
+
+// Sends `message` every `period`, starting right away, until the main loop is gone.
+pub fn spawn_ticker(tx: &mpsc::Sender<Message>, period: Duration, message: fn() -> Message) {
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if tx.send(message()).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+pub async fn serve_commands(
+    tx: mpsc::Sender<Message>,
+    listener: UnixListener,
+    audit_log: Option<PathBuf>,
+) -> Result<()> {
+    let mut recent_tokens = RecentTokens::default();
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let peer = audit::peer(&stream);
+        // Read here rather than in the connection's task, so commands reach the main loop in the
+        // order they were sent.
+        let mut buf = Vec::new();
+        match timeout(REQUEST_TIMEOUT, stream.read_to_end(&mut buf)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => {
+                tracing::warn!("Failed to read command: {err:?}");
+                continue;
+            }
+            Err(_) => {
+                tracing::warn!("Dropping a client that sent no command within {REQUEST_TIMEOUT:?}");
+                continue;
+            }
+        }
+        let request = match protocol::decode(&buf) {
+            Ok(request) => request,
+            Err(err) => {
+                tracing::warn!("Rejected command: {err:?}");
+                respond_later(stream, async move {
+                    protocol::format_reply(&Err(err.to_string()))
+                });
+                continue;
+            }
+        };
+        let reporting = request.reads_progress();
+        let command = request.command;
+        let (reporter, progress_rx) = progress::channel();
+        match &peer {
+            Some(peer) => tracing::info!("Received command: {command:?} from {peer}"),
+            None => tracing::info!("Received command: {command:?}"),
+        }
+        if let Some(path) = &audit_log {
+            audit::record(path, peer.as_ref(), &command);
+        }
+        if let Some(token) = &request.token
+            && recent_tokens.is_duplicate(token, Instant::now())
+        {
+            tracing::info!("Ignoring command with already used token {token:?}");
+            // A retry of a command that already ran has succeeded as far as its client is
+            // concerned.
+            let response = if command.expects_response() {
+                protocol::format_response(&Ok(format!("Ignored: token {token:?} was already used")))
+            } else {
+                protocol::format_reply(&Ok(()))
+            };
+            respond_later(stream, async move { response });
+            continue;
+        }
+        let command = match command.into_message() {
+            Ok(message) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let message = Message::Replying(Box::new(message), reply_tx, Instant::now());
+                tx.send(reported(message, reporting.then_some(reporter)))?;
+                // The reply may take as long as Hyprland hangs and the command sits in the paused
+                // queue, other clients go on meanwhile.
+                respond_with_progress(stream, progress_rx, async move {
+                    let reply = reply_rx.await.unwrap_or_else(|_| {
+                        Err("The daemon stopped before handling the command".to_string())
+                    });
+                    protocol::format_reply(&reply)
+                });
+                continue;
+            }
+            Err(command) => command,
+        };
+        let (response_tx, response_rx) = oneshot::channel();
+        let message = match command {
+            Command::RawEvents => {
+                // A client gone before it was subscribed is its own problem, not the daemon's.
+                if let Err(err) = stream
+                    .into_std()
+                    .map_err(Into::into)
+                    .and_then(hyprland::subscribe_raw_events)
+                {
+                    tracing::warn!("Failed to subscribe to raw events: {err:?}");
+                }
+                continue;
+            }
+            Command::Watch => {
+                follow_later(&tx, stream, Message::Watch)?;
+                continue;
+            }
+            Command::RestartServer => {
+                let (restart_tx, restart_rx) = oneshot::channel();
+                tx.send(Message::RestartServer(listener.as_raw_fd(), restart_tx))?;
+                let Ok(restarted) = restart_rx.await else {
+                    tracing::warn!("The main loop stopped before restarting the server");
+                    let response = Err("The daemon stopped before restarting".to_string());
+                    respond(stream, &protocol::format_response(&response)).await;
+                    continue;
+                };
+                match restarted {
+                    Ok(pid) => {
+                        respond(stream, &format!("Handed over to hywoma server {pid}")).await;
+                        // Stop accepting here; queued and new clients go to the successor.
+                        exit(0);
+                    }
+                    Err(err) => {
+                        let response = Err(format!("Failed to restart hywoma server: {err:?}"));
+                        respond(stream, &protocol::format_response(&response)).await
+                    }
+                }
+                continue;
+            }
+            command => match response_message(command, response_tx) {
+                Ok(message) => message,
+                Err(command) => {
+                    tracing::warn!("Command {command:?} has no handler");
+                    continue;
+                }
+            },
+        };
+        tx.send(reported(message, reporting.then_some(reporter)))?;
+        respond_with_progress(stream, progress_rx, async move {
+            let response = response_rx
+                .await
+                .unwrap_or_else(|_| Err("The daemon stopped before answering".to_string()));
+            protocol::format_response(&response)
+        });
+    }
+}
+
+fn reported(message: Message, reporter: Option<Reporter>) -> Message {
+    match reporter {
+        Some(reporter) => Message::Reporting(Box::new(message), reporter),
+        None => message,
+    }
+}
+
+// The main loop message for a command answered over the connection, or the command back when it
+// is not one.
+fn response_message(
+    command: Command,
+    response_tx: oneshot::Sender<Result<String, String>>,
+) -> Result<Message, Command> {
+    let message = match command {
+        Command::Status => Message::Status(response_tx),
+        Command::Ping => Message::Ping(response_tx),
+        Command::Hyprctl(args, unchecked) => Message::Hyprctl(args, unchecked, response_tx),
+        Command::TmpSlots => Message::TmpSlots(response_tx),
+        Command::TmpSwapWithSlot(slot) => Message::TmpSwapWithSlot(slot, response_tx),
+        Command::PlanGroupSwitch(group) => Message::PlanGroupSwitch(group, response_tx),
+        Command::Retile(all) => Message::Retile(all, response_tx),
+        Command::Where(pattern, focus) => Message::Where(pattern, focus, response_tx),
+        Command::SaveSession => Message::SaveSession(response_tx),
+        Command::RestoreSession => Message::RestoreSession(response_tx),
+        Command::ArchiveGroup(group) => Message::ArchiveGroup(group, response_tx),
+        Command::RestoreGroup(group) => Message::RestoreGroup(group, response_tx),
+        Command::SwitchProfile(name) => Message::SwitchProfile(name, response_tx),
+        Command::ListProfiles => Message::ListProfiles(response_tx),
+        Command::ListGroups(tree, json) => Message::ListGroups(tree, json, response_tx),
+        Command::Rule(request) => Message::Rule(request, response_tx),
+        Command::NamedGroup(name, command) => match response_message(*command, response_tx) {
+            Ok(message) => Message::NamedGroup(name, Box::new(message)),
+            Err(command) => return Err(Command::NamedGroup(name, Box::new(command))),
+        },
+        command => return Err(command),
+    };
+    Ok(message)
+}
+
+pub async fn serve_events(tx: mpsc::Sender<Message>, listener: UnixListener) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        follow_later(&tx, stream, Message::SubscribeEvents)?;
+    }
+}
+
+// Dumps the status JSON to every client and hangs up, no request needed: `socat -
+// UNIX-CONNECT:$XDG_RUNTIME_DIR/.hywoma-state.sock` works from any script or debugger.
+pub async fn serve_state(tx: mpsc::Sender<Message>, listener: UnixListener) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let (response_tx, response_rx) = oneshot::channel();
+        tx.send(Message::Status(response_tx))?;
+        respond_later(stream, async move {
+            response_rx
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .unwrap_or_default()
+        });
+    }
+}
+
+fn respond_later(stream: UnixStream, response: impl Future<Output = String> + Send + 'static) {
+    let connection = Connection::open();
+    tokio::spawn(async move {
+        respond(stream, &response.await).await;
+        drop(connection);
+    });
+}
+
+// Progress lines as the main loop reports them, then the reply. Progress reported before the reply
+// is written ahead of it.
+fn respond_with_progress(
+    mut stream: UnixStream,
+    mut progress_rx: tokio::sync::mpsc::UnboundedReceiver<Progress>,
+    response: impl Future<Output = String> + Send + 'static,
+) {
+    let connection = Connection::open();
+    tokio::spawn(async move {
+        tokio::pin!(response);
+        let response = loop {
+            tokio::select! {
+                biased;
+                Some(progress) = progress_rx.recv() => {
+                    let line = format!("{}\n", protocol::format_progress(progress));
+                    if let Err(err) = stream.write_all(line.as_bytes()).await {
+                        tracing::debug!("Failed to write progress: {err:?}");
+                    }
+                }
+                response = &mut response => break response,
+            }
+        };
+        respond(stream, &response).await;
+        drop(connection);
+    });
+}
+
+async fn respond(mut stream: UnixStream, response: &str) {
+    if let Err(err) = stream.write_all(format!("{response}\n").as_bytes()).await {
+        tracing::warn!("Failed to write status response: {err:?}");
+    }
+}
+
+// Subscribes in the order of connections, then writes on the connection's own task until the
+// client or the daemon goes away.
+fn follow_later(
+    tx: &mpsc::Sender<Message>,
+    mut stream: UnixStream,
+    request: fn(oneshot::Sender<Subscription>) -> Message,
+) -> Result<()> {
+    let (subscription_tx, subscription_rx) = oneshot::channel();
+    tx.send(request(subscription_tx))?;
+    let connection = Connection::open();
+    tokio::spawn(async move {
+        let _connection = connection;
+        let Ok(subscription) = subscription_rx.await else {
+            return;
+        };
+        if let Err(err) = follow(&mut stream, subscription).await {
+            tracing::debug!("Dropping follower after write failure: {err:?}");
+        }
+    });
+    Ok(())
+}
+
+async fn follow(stream: &mut UnixStream, subscription: Subscription) -> Result<()> {
+    let Subscription {
+        initial,
+        mut updates,
+    } = subscription;
+    stream.write_all(initial.as_bytes()).await?;
+    loop {
+        match updates.recv().await {
+            Ok(line) => stream.write_all(line.as_bytes()).await?,
+            // Every line describes the whole state, so a client that fell behind only needs the
+            // newest ones.
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::debug!("A slow follower skipped {skipped} line(s)");
+            }
+            // Closing the stream tells the client the daemon is gone, instead of leaving it
+            // waiting for lines that never come.
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+// For listeners inherited through `restart-server` or from systemd.
+pub fn from_std(listener: std::os::unix::net::UnixListener) -> Result<UnixListener> {
+    listener.set_nonblocking(true)?;
+    UnixListener::from_std(listener).map_err(|err| anyhow!("Failed to adopt listener: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_tokens_are_ignored_within_the_window() {
+        let start = Instant::now();
+        let mut tokens = RecentTokens::default();
+        assert!(!tokens.is_duplicate("abc", start));
+        assert!(tokens.is_duplicate("abc", start + Duration::from_secs(1)));
+        assert!(!tokens.is_duplicate("def", start + Duration::from_secs(1)));
+        assert!(!tokens.is_duplicate("abc", start + TOKEN_WINDOW));
+    }
+
+    #[test]
+    fn followers_start_from_the_snapshot_they_subscribed_with() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let broadcast = Broadcast::default();
+            assert!(!broadcast.is_followed());
+            let subscription = broadcast.subscribe(&1).unwrap();
+            assert!(broadcast.is_followed());
+            broadcast.send(&2).unwrap();
+            drop(broadcast);
+
+            let (mut client, mut server) = UnixStream::pair().unwrap();
+            follow(&mut server, subscription).await.unwrap();
+            drop(server);
+            let mut lines = String::new();
+            client.read_to_string(&mut lines).await.unwrap();
+            assert_eq!(lines, "1\n2\n");
+        });
+    }
+}
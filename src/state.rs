@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
@@ -14,10 +14,55 @@ pub const FIRST_INTERNAL_WORKSPACE_ID: InternalWorkspaceId = 1000;
 pub const VISIBLE_WORKSPACES_PER_SLOT: u64 = 10;
 pub const PERSISTED_STATE_VERSION: u64 = 2;
 
+// Room in the workspace numbering: visible workspaces per slot, slots and groups. Hyprland IDs below
+// `first_internal_id` are read as legacy group, monitor and workspace digits in these radices, and
+// hywoma's own IDs start past them, so groups beyond the width still get workspaces. Read at
+// startup only: changing it renumbers workspaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdLayout {
+    pub workspaces: VisibleWorkspace,
+    pub monitors: SlotId,
+    pub groups: GroupId,
+}
+
+const DEFAULT_ID_LAYOUT: IdLayout = IdLayout {
+    workspaces: VISIBLE_WORKSPACES_PER_SLOT,
+    monitors: 10,
+    groups: 10,
+};
+
+impl Default for IdLayout {
+    fn default() -> Self {
+        DEFAULT_ID_LAYOUT
+    }
+}
+
+impl IdLayout {
+    // Just past the largest legacy ID, w·m·g for workspace w on monitor m of the last group, so the
+    // two never collide. Never below 1000, where hywoma's IDs started before the layout was
+    // configurable.
+    pub fn first_internal_id(self) -> InternalWorkspaceId {
+        self.workspaces
+            .saturating_mul(self.monitors)
+            .saturating_mul(self.groups)
+            .saturating_add(1)
+            .max(FIRST_INTERNAL_WORKSPACE_ID)
+    }
+
+    fn normalized(self) -> Self {
+        IdLayout {
+            workspaces: self.workspaces.max(1),
+            monitors: self.monitors.max(1),
+            groups: self.groups.max(1),
+        }
+    }
+}
+
 // Logical identity for a visible workspace. This must stay separate from Hyprland's workspace ID so
 // a visible label can remain attached to a slot while `swapactiveworkspaces` swaps the internal IDs
 // underneath it.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
 pub struct WorkspaceKey {
     pub group: GroupId,
     pub slot: SlotId,
@@ -30,6 +75,8 @@ pub struct WorkspaceEntry {
     pub slot: SlotId,
     pub visible: VisibleWorkspace,
     pub internal_id: InternalWorkspaceId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -37,6 +84,8 @@ pub struct GroupSnapshot {
     pub id: GroupId,
     pub name: String,
     pub active_visible_by_slot: Vec<(SlotId, VisibleWorkspace)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<GroupId>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -46,6 +95,8 @@ pub struct SlotSnapshot {
     pub label: String,
     pub attached_output: Option<String>,
     pub runtime_monitor_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special_workspace: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -62,6 +113,26 @@ pub struct PersistedGroup {
     pub id: GroupId,
     pub name: String,
     pub active_visible_by_slot: Vec<(SlotId, VisibleWorkspace)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub furnished: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archived: Vec<ArchivedWindow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<GroupId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspace_labels: Vec<(SlotId, VisibleWorkspace, String)>,
+}
+
+// A window parked by `group archive`, with the logical workspace it goes back to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedWindow {
+    pub address: String,
+    pub class: String,
+    pub title: String,
+    pub slot: SlotId,
+    pub visible: VisibleWorkspace,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -87,6 +158,18 @@ pub struct Group {
     pub id: GroupId,
     pub name: String,
     active_visible_by_slot: HashMap<SlotId, VisibleWorkspace>,
+    // Template chosen with `create_group --template`. Groups from the config get theirs from
+    // there instead, so editing the config still affects groups that were not furnished yet.
+    pub template: Option<String>,
+    // Set once the template was applied, so it runs on the first activation only.
+    pub furnished: bool,
+    // Windows parked by `group archive` until `group restore`.
+    pub archived: Vec<ArchivedWindow>,
+    // Set for sub-groups. A sub-group has workspaces of its own but shares its parent's rules and
+    // `[[groups]]` settings. Sub-groups do not nest.
+    pub parent: Option<GroupId>,
+    // From `rename_workspace`, per (slot, visible workspace) like the active visible workspaces.
+    workspace_labels: HashMap<(SlotId, VisibleWorkspace), String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -96,6 +179,12 @@ pub struct Slot {
     pub label: String,
     pub attached_output: Option<String>,
     pub runtime_monitor_id: Option<u64>,
+    // The special workspace shown over the slot's workspace, from Hyprland's activespecial.
+    pub special_workspace: Option<String>,
+    // Visible workspaces reachable on this slot. Orientation defaults can lower it, e.g. for a
+    // rotated side monitor that only ever holds a few workspaces.
+    pub workspace_count: VisibleWorkspace,
+    default_label: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -106,6 +195,8 @@ pub struct State {
     pub slots: HashMap<SlotId, Slot>,
     workspace_ids: HashMap<WorkspaceKey, InternalWorkspaceId>,
     next_workspace_id: InternalWorkspaceId,
+    unmanaged_workspaces: HashSet<u64>,
+    id_layout: IdLayout,
 }
 
 impl Group {
@@ -119,6 +210,11 @@ impl Group {
             id,
             name: name.into(),
             active_visible_by_slot,
+            template: None,
+            furnished: false,
+            archived: Vec::new(),
+            parent: None,
+            workspace_labels: HashMap::new(),
         }
     }
 
@@ -132,31 +228,74 @@ impl Group {
     pub fn set_active_visible(&mut self, slot: SlotId, visible: VisibleWorkspace) {
         self.active_visible_by_slot.insert(slot, visible);
     }
+
+    pub fn workspace_label(&self, slot: SlotId, visible: VisibleWorkspace) -> Option<&str> {
+        self.workspace_labels
+            .get(&(slot, visible))
+            .map(String::as_str)
+    }
+
+    // `None` removes the label.
+    pub fn set_workspace_label(
+        &mut self,
+        slot: SlotId,
+        visible: VisibleWorkspace,
+        label: Option<String>,
+    ) {
+        match label {
+            Some(label) => self.workspace_labels.insert((slot, visible), label),
+            None => self.workspace_labels.remove(&(slot, visible)),
+        };
+    }
 }
 
 impl Slot {
     pub fn new(id: SlotId, key: impl Into<String>, label: impl Into<String>) -> Self {
+        let label = label.into();
         Slot {
             id,
             key: key.into(),
-            label: label.into(),
+            label: label.clone(),
             attached_output: None,
             runtime_monitor_id: None,
+            special_workspace: None,
+            // Widened or narrowed to the layout of the state it goes into.
+            workspace_count: VISIBLE_WORKSPACES_PER_SLOT,
+            default_label: label,
         }
     }
 }
 
+// Slots start out with every workspace the layout has room for.
+fn slots_for_layout(
+    slots: impl IntoIterator<Item = Slot>,
+    layout: IdLayout,
+) -> HashMap<SlotId, Slot> {
+    slots
+        .into_iter()
+        .map(|mut slot| {
+            slot.workspace_count = layout.workspaces;
+            (slot.id, slot)
+        })
+        .collect()
+}
+
 impl State {
+    #[cfg(test)]
     pub fn new(slots: impl IntoIterator<Item = Slot>) -> Self {
-        let slots: HashMap<SlotId, Slot> = slots.into_iter().map(|slot| (slot.id, slot)).collect();
+        State::with_id_layout(IdLayout::default(), slots)
+    }
+
+    pub fn with_id_layout(layout: IdLayout, slots: impl IntoIterator<Item = Slot>) -> Self {
+        let layout = layout.normalized();
+        let slots = slots_for_layout(slots, layout);
         let mut groups = HashMap::new();
         groups.insert(
             DEFAULT_GROUP_ID,
             Group::new(DEFAULT_GROUP_ID, DEFAULT_GROUP_NAME, slots.keys().copied()),
         );
 
-        let next_workspace_id =
-            FIRST_INTERNAL_WORKSPACE_ID + slots.len() as u64 * VISIBLE_WORKSPACES_PER_SLOT;
+        let next_workspace_id = layout.first_internal_id() + slots.len() as u64 * layout.workspaces;
 
         State {
             active_group: DEFAULT_GROUP_ID,
@@ -165,10 +304,13 @@ impl State {
             slots,
             workspace_ids: HashMap::new(),
             next_workspace_id,
+            unmanaged_workspaces: HashSet::new(),
+            id_layout: layout,
         }
     }
 
     pub fn from_persisted(
+        layout: IdLayout,
         slots: impl IntoIterator<Item = Slot>,
         persisted: PersistedState,
     ) -> Option<Self> {
@@ -178,7 +320,8 @@ impl State {
             return None;
         }
 
-        let slots: HashMap<SlotId, Slot> = slots.into_iter().map(|slot| (slot.id, slot)).collect();
+        let layout = layout.normalized();
+        let slots = slots_for_layout(slots, layout);
         let slot_ids: Vec<SlotId> = slots.keys().copied().collect();
         let mut groups: HashMap<GroupId, Group> = persisted
             .groups
@@ -199,6 +342,15 @@ impl State {
                         id: group.id,
                         name: group.name,
                         active_visible_by_slot,
+                        template: group.template,
+                        furnished: group.furnished,
+                        archived: group.archived,
+                        parent: group.parent,
+                        workspace_labels: group
+                            .workspace_labels
+                            .into_iter()
+                            .map(|(slot, visible, label)| ((slot, visible), label))
+                            .collect(),
                     },
                 )
             })
@@ -249,8 +401,7 @@ impl State {
         let max_workspace_id = workspace_ids.values().copied().max().unwrap_or(0) + 1;
         let next_workspace_id = persisted
             .next_workspace_id
-            .max(FIRST_INTERNAL_WORKSPACE_ID)
-            .max(FIRST_INTERNAL_WORKSPACE_ID + slot_ids.len() as u64 * VISIBLE_WORKSPACES_PER_SLOT)
+            .max(layout.first_internal_id() + slot_ids.len() as u64 * layout.workspaces)
             .max(max_workspace_id);
 
         Some(State {
@@ -260,9 +411,15 @@ impl State {
             slots,
             workspace_ids,
             next_workspace_id,
+            unmanaged_workspaces: HashSet::new(),
+            id_layout: layout,
         })
     }
 
+    pub fn id_layout(&self) -> IdLayout {
+        self.id_layout
+    }
+
     pub fn persisted(&self) -> PersistedState {
         // Persist only logical state. Runtime monitor IDs and present workspace IDs are intentionally
         // excluded because Hyprland regenerates them per session/hotplug event.
@@ -276,11 +433,22 @@ impl State {
                     .map(|(slot, visible)| (*slot, *visible))
                     .collect();
                 active_visible_by_slot.sort_unstable_by_key(|(slot, _)| *slot);
+                let mut workspace_labels: Vec<(SlotId, VisibleWorkspace, String)> = group
+                    .workspace_labels
+                    .iter()
+                    .map(|((slot, visible), label)| (*slot, *visible, label.clone()))
+                    .collect();
+                workspace_labels.sort_unstable();
 
                 PersistedGroup {
                     id: group.id,
                     name: group.name.clone(),
                     active_visible_by_slot,
+                    template: group.template.clone(),
+                    furnished: group.furnished,
+                    archived: group.archived.clone(),
+                    parent: group.parent,
+                    workspace_labels,
                 }
             })
             .collect();
@@ -366,24 +534,22 @@ impl State {
         if group != DEFAULT_GROUP_ID || !self.slots.contains_key(&slot) {
             return None;
         }
-        if !(1..=VISIBLE_WORKSPACES_PER_SLOT).contains(&visible) {
+        let layout = self.id_layout;
+        if !(1..=layout.workspaces).contains(&visible) {
             return None;
         }
 
-        Some(FIRST_INTERNAL_WORKSPACE_ID + (slot - 1) * VISIBLE_WORKSPACES_PER_SLOT + visible - 1)
+        Some(layout.first_internal_id() + (slot - 1) * layout.workspaces + visible - 1)
     }
 
     pub fn default_key_for_workspace_id(
         &self,
         workspace_id: InternalWorkspaceId,
     ) -> Option<WorkspaceKey> {
-        if workspace_id < FIRST_INTERNAL_WORKSPACE_ID {
-            return None;
-        }
-
-        let offset = workspace_id - FIRST_INTERNAL_WORKSPACE_ID;
-        let slot = offset / VISIBLE_WORKSPACES_PER_SLOT + 1;
-        let visible = offset % VISIBLE_WORKSPACES_PER_SLOT + 1;
+        let layout = self.id_layout;
+        let offset = workspace_id.checked_sub(layout.first_internal_id())?;
+        let slot = offset / layout.workspaces + 1;
+        let visible = offset % layout.workspaces + 1;
         if !self.slots.contains_key(&slot) {
             return None;
         }
@@ -400,6 +566,7 @@ impl State {
             .workspace_ids
             .values()
             .any(|id| *id == self.next_workspace_id)
+            || self.unmanaged_workspaces.contains(&self.next_workspace_id)
         {
             self.next_workspace_id += 1;
         }
@@ -409,6 +576,21 @@ impl State {
         id
     }
 
+    // `unmanaged_workspaces`, resolved to IDs by the main loop.
+    pub fn set_unmanaged_workspaces(&mut self, workspace_ids: HashSet<u64>) {
+        self.unmanaged_workspaces = workspace_ids;
+    }
+
+    pub fn is_unmanaged(&self, workspace_id: u64) -> bool {
+        self.unmanaged_workspaces.contains(&workspace_id)
+    }
+
+    pub fn workspace_label(&self, key: WorkspaceKey) -> Option<&str> {
+        self.groups
+            .get(&key.group)?
+            .workspace_label(key.slot, key.visible)
+    }
+
     pub fn known_workspace_id(
         &self,
         group: GroupId,
@@ -445,6 +627,24 @@ impl State {
         self.next_workspace_id = self.next_workspace_id.max(internal_id + 1);
     }
 
+    // Workspaces past a slot's visible range, allocated by `move_to_empty` when every regular one is
+    // taken. They get IDs like any other workspace; only their visible number marks them.
+    pub fn overflow_keys(&self) -> Vec<WorkspaceKey> {
+        let mut keys: Vec<WorkspaceKey> = self
+            .workspace_ids
+            .keys()
+            .filter(|key| key.visible > self.workspace_count(key.slot))
+            .copied()
+            .collect();
+        keys.sort_unstable_by_key(|key| (key.group, key.slot, key.visible));
+        keys
+    }
+
+    // Drops the mapping of a workspace that is gone for good, so its visible number can be reused.
+    pub fn forget_workspace(&mut self, key: WorkspaceKey) {
+        self.workspace_ids.remove(&key);
+    }
+
     pub fn key_for_workspace_id(&self, workspace_id: InternalWorkspaceId) -> Option<WorkspaceKey> {
         self.workspace_ids
             .iter()
@@ -481,6 +681,29 @@ impl State {
         (source_id, target_id)
     }
 
+    // Swaps the IDs behind two visible labels of one slot in the active group, so their windows
+    // trade places without a single window being moved.
+    pub fn swap_visible_workspace_ids(
+        &mut self,
+        slot: SlotId,
+        source_visible: VisibleWorkspace,
+        target_visible: VisibleWorkspace,
+    ) -> (InternalWorkspaceId, InternalWorkspaceId) {
+        let group = self.active_group;
+        let source_id = self.workspace_id_for(group, slot, source_visible);
+        let target_id = self.workspace_id_for(group, slot, target_visible);
+        let key = |visible| WorkspaceKey {
+            group,
+            slot,
+            visible,
+        };
+
+        self.workspace_ids.insert(key(source_visible), target_id);
+        self.workspace_ids.insert(key(target_visible), source_id);
+
+        (source_id, target_id)
+    }
+
     pub fn swap_slot_workspace_mappings(&mut self, source_slot: SlotId, target_slot: SlotId) {
         if source_slot == target_slot {
             return;
@@ -518,6 +741,45 @@ impl State {
         id
     }
 
+    // Sub-groups of a sub-group go to its parent instead. A sub-group of that name is entered again
+    // rather than created twice. Without a name they are numbered after their parent, e.g. 2.1, 2.2.
+    pub fn find_or_create_subgroup(&mut self, parent: GroupId, name: Option<String>) -> GroupId {
+        let parent = self.settings_group(parent);
+        if let Some(name) = &name
+            && let Some(existing) = self
+                .groups
+                .values()
+                .filter(|group| group.parent == Some(parent) && group.name == *name)
+                .map(|group| group.id)
+                .min()
+        {
+            return existing;
+        }
+        let name = name.unwrap_or_else(|| {
+            let names: Vec<&str> = self
+                .groups
+                .values()
+                .map(|group| group.name.as_str())
+                .collect();
+            (1..)
+                .map(|index| format!("{parent}.{index}"))
+                .find(|name| !names.contains(&name.as_str()))
+                .expect("unbounded range")
+        });
+        let id = self.create_group(name);
+        self.group_mut(id).parent = Some(parent);
+        id
+    }
+
+    pub fn parent_of(&self, group: GroupId) -> Option<GroupId> {
+        self.groups.get(&group)?.parent
+    }
+
+    // The group whose rules and settings apply to `group`.
+    pub fn settings_group(&self, group: GroupId) -> GroupId {
+        self.parent_of(group).unwrap_or(group)
+    }
+
     pub fn rename_group(&mut self, group: GroupId, name: impl Into<String>) {
         self.group_mut(group).name = name.into();
     }
@@ -531,6 +793,12 @@ impl State {
             self.previous_group = None;
         }
         self.workspace_ids.retain(|key, _| key.group != group);
+        // Orphaned sub-groups carry on as regular groups.
+        for child in self.groups.values_mut() {
+            if child.parent == Some(group) {
+                child.parent = None;
+            }
+        }
     }
 
     pub fn ensure_group(&mut self, group: GroupId, name: impl Into<String>) {
@@ -539,10 +807,30 @@ impl State {
             .or_insert_with(|| Group::new(group, name, self.slots.keys().copied()));
     }
 
+    // Exact names first, then ignoring case, so `select_group work` finds "Work". Of groups sharing
+    // a name, the lowest ID wins.
+    pub fn group_named(&self, name: &str) -> Option<GroupId> {
+        let mut groups: Vec<&Group> = self.groups.values().collect();
+        groups.sort_by_key(|group| group.id);
+        groups
+            .iter()
+            .find(|group| group.name == name)
+            .or_else(|| {
+                groups
+                    .iter()
+                    .find(|group| group.name.eq_ignore_ascii_case(name))
+            })
+            .map(|group| group.id)
+    }
+
     pub fn has_group(&self, group: GroupId) -> bool {
         self.groups.contains_key(&group)
     }
 
+    pub fn has_slot(&self, slot: SlotId) -> bool {
+        self.slots.contains_key(&slot)
+    }
+
     pub fn switch_group(&mut self, group: GroupId) {
         self.group(group);
         // Track previous only for real group changes; repeated switch_group(active) should not break
@@ -571,10 +859,45 @@ impl State {
         slot.runtime_monitor_id = Some(runtime_monitor_id);
     }
 
+    // Overrides the label and workspace count of a slot for the monitor currently attached to it.
+    // `None` goes back to the slot's own label and the full workspace range.
+    pub fn set_slot_defaults(
+        &mut self,
+        slot: SlotId,
+        label: Option<&str>,
+        workspace_count: Option<VisibleWorkspace>,
+    ) {
+        let max = self.id_layout.workspaces;
+        let slot = self.slot_mut(slot);
+        slot.label = label.map_or_else(|| slot.default_label.clone(), str::to_string);
+        slot.workspace_count = workspace_count.unwrap_or(max).clamp(1, max);
+    }
+
+    pub fn workspace_count(&self, slot: SlotId) -> VisibleWorkspace {
+        self.slots
+            .get(&slot)
+            .map_or(self.id_layout.workspaces, |slot| slot.workspace_count)
+    }
+
     pub fn detach_slot(&mut self, slot: SlotId) {
         let slot = self.slot_mut(slot);
         slot.attached_output = None;
         slot.runtime_monitor_id = None;
+        slot.special_workspace = None;
+    }
+
+    // Whether anything changed. Outputs without a slot have nothing to track.
+    pub fn set_special_workspace(&mut self, output: &str, name: Option<String>) -> bool {
+        let Some(slot) = self
+            .slots
+            .values_mut()
+            .find(|slot| slot.attached_output.as_deref() == Some(output))
+        else {
+            return false;
+        };
+        let changed = slot.special_workspace != name;
+        slot.special_workspace = name;
+        changed
     }
 
     pub fn attach_monitors_in_order(&mut self, monitors: &[crate::hyprland::MonitorInfo]) {
@@ -640,14 +963,12 @@ impl State {
                 .slots
                 .get(slot_id)
                 .and_then(|slot| slot.attached_output.as_deref())
-            {
-                if let Some(monitor) = external_monitors
+                && let Some(monitor) = external_monitors
                     .iter()
                     .find(|monitor| monitor.name == attached_output)
-                {
-                    planned.push((*slot_id, monitor.clone()));
-                    used_external_names.push(monitor.name.clone());
-                }
+            {
+                planned.push((*slot_id, monitor.clone()));
+                used_external_names.push(monitor.name.clone());
             }
         }
 
@@ -712,6 +1033,7 @@ impl State {
                     id: group.id,
                     name: group.name.clone(),
                     active_visible_by_slot,
+                    parent: group.parent,
                 }
             })
             .collect();
@@ -726,6 +1048,7 @@ impl State {
                 label: slot.label.clone(),
                 attached_output: slot.attached_output.clone(),
                 runtime_monitor_id: slot.runtime_monitor_id,
+                special_workspace: slot.special_workspace.clone(),
             })
             .collect();
         slots.sort_unstable_by_key(|slot| slot.id);
@@ -738,6 +1061,7 @@ impl State {
                 slot: key.slot,
                 visible: key.visible,
                 internal_id: *internal_id,
+                label: self.workspace_label(*key).map(str::to_string),
             })
             .collect();
         workspaces
@@ -778,7 +1102,7 @@ impl State {
             .unwrap_or_else(|| panic!("unknown slot {slot}"))
     }
 
-    fn slot_ids(&self) -> Vec<SlotId> {
+    pub fn slot_ids(&self) -> Vec<SlotId> {
         let mut slot_ids: Vec<SlotId> = self.slots.keys().copied().collect();
         slot_ids.sort_unstable();
         slot_ids
@@ -825,7 +1149,7 @@ mod tests {
         let different_visible = state.workspace_id_for(0, 1, 2);
         let different_slot = state.workspace_id_for(0, 2, 1);
 
-        assert_eq!(first, FIRST_INTERNAL_WORKSPACE_ID);
+        assert_eq!(first, IdLayout::default().first_internal_id());
         assert_eq!(same, first);
         assert_ne!(different_visible, first);
         assert_ne!(different_slot, first);
@@ -836,11 +1160,11 @@ mod tests {
     fn default_group_uses_seeded_workspace_ids() {
         let mut state = test_state();
 
-        assert_eq!(state.workspace_id_for(0, 1, 1), 1000);
-        assert_eq!(state.workspace_id_for(0, 2, 1), 1010);
-        assert_eq!(state.workspace_id_for(0, 3, 10), 1029);
+        assert_eq!(state.workspace_id_for(0, 1, 1), 1001);
+        assert_eq!(state.workspace_id_for(0, 2, 1), 1011);
+        assert_eq!(state.workspace_id_for(0, 3, 10), 1030);
         assert_eq!(
-            state.default_key_for_workspace_id(1010),
+            state.default_key_for_workspace_id(1011),
             Some(WorkspaceKey {
                 group: 0,
                 slot: 2,
@@ -849,13 +1173,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn seeded_workspace_ids_follow_the_configured_layout() {
+        let layout = IdLayout {
+            workspaces: 20,
+            monitors: 4,
+            groups: 50,
+        };
+        let mut state = State::with_id_layout(
+            layout,
+            [Slot::new(1, "u", "left"), Slot::new(2, "i", "middle")],
+        );
+
+        assert_eq!(state.workspace_count(1), 20);
+        assert_eq!(state.workspace_id_for(0, 1, 20), 4020);
+        assert_eq!(state.workspace_id_for(0, 2, 1), 4021);
+        assert_eq!(state.workspace_id_for(1, 1, 1), 4041);
+    }
+
     #[test]
     fn selecting_workspace_updates_active_visible_for_slot() {
         let mut state = test_state();
 
         let workspace_id = state.select_workspace(2, 7);
 
-        assert_eq!(workspace_id, 1016);
+        assert_eq!(workspace_id, 1017);
         assert_eq!(state.active_visible(1), 1);
         assert_eq!(state.active_visible(2), 7);
         assert_eq!(state.active_visible(3), 1);
@@ -936,6 +1278,9 @@ mod tests {
         state.rename_group(group, "Renamed");
 
         assert_eq!(state.groups[&group].name, "Renamed");
+        assert_eq!(state.group_named("Renamed"), Some(group));
+        assert_eq!(state.group_named("renamed"), Some(group));
+        assert_eq!(state.group_named("Temp"), None);
     }
 
     #[test]
@@ -953,6 +1298,33 @@ mod tests {
         assert_eq!(state.key_for_workspace_id(workspace_id), None);
     }
 
+    #[test]
+    fn subgroups_are_numbered_after_their_parent_and_do_not_nest() {
+        let mut state = test_state();
+        let project = state.create_group("Project");
+        let first = state.find_or_create_subgroup(project, None);
+        let second = state.find_or_create_subgroup(first, None);
+
+        assert_eq!(state.groups[&first].name, format!("{project}.1"));
+        assert_eq!(state.groups[&second].name, format!("{project}.2"));
+        assert_eq!(state.parent_of(second), Some(project));
+        assert_eq!(state.settings_group(second), project);
+
+        let ticket = state.find_or_create_subgroup(project, Some("ticket-1".to_string()));
+        assert_eq!(
+            state.find_or_create_subgroup(second, Some("ticket-1".to_string())),
+            ticket
+        );
+        assert_ne!(
+            state.find_or_create_subgroup(0, Some("ticket-1".to_string())),
+            ticket
+        );
+
+        state.delete_group(project);
+        assert_eq!(state.parent_of(first), None);
+        assert_eq!(state.settings_group(first), first);
+    }
+
     #[test]
     fn persisted_state_roundtrips_logical_state_without_runtime_slots() {
         let mut state = test_state();
@@ -961,8 +1333,17 @@ mod tests {
         state.set_active_visible(1, 3);
         let workspace_id = state.workspace_id_for(group, 1, 3);
         state.attach_output(1, "eDP-1", 42);
+        let key = WorkspaceKey {
+            group,
+            slot: 1,
+            visible: 3,
+        };
+        state
+            .group_mut(group)
+            .set_workspace_label(1, 3, Some("pr-review".to_string()));
 
         let restored = State::from_persisted(
+            IdLayout::default(),
             [
                 Slot::new(1, "u", "left"),
                 Slot::new(2, "i", "middle"),
@@ -977,6 +1358,7 @@ mod tests {
         assert_eq!(restored.groups[&group].name, "Other");
         assert_eq!(restored.active_visible(1), 3);
         assert_eq!(restored.known_workspace_id(group, 1, 3), Some(workspace_id));
+        assert_eq!(restored.workspace_label(key), Some("pr-review"));
         assert_eq!(restored.runtime_monitor_id_for_slot(1), None);
     }
 
@@ -995,6 +1377,20 @@ mod tests {
         assert_eq!(state.slots[&2].attached_output, None);
     }
 
+    #[test]
+    fn tracks_special_workspaces_per_attached_output() {
+        let mut state = test_state();
+        state.attach_output(2, "DP-1", 7);
+
+        assert!(state.set_special_workspace("DP-1", Some("term".to_string())));
+        assert!(!state.set_special_workspace("DP-1", Some("term".to_string())));
+        assert!(!state.set_special_workspace("HDMI-A-1", Some("term".to_string())));
+        assert_eq!(state.slots[&2].special_workspace.as_deref(), Some("term"));
+
+        state.detach_slot(2);
+        assert_eq!(state.slots[&2].special_workspace, None);
+    }
+
     #[test]
     fn fixed_output_policy_ignores_monitor_position_order() {
         let mut state = test_state();
@@ -1109,12 +1505,24 @@ mod tests {
 
         let (source_id, target_id) = state.swap_active_workspace_ids(1, 2);
 
-        assert_eq!(source_id, 1002);
-        assert_eq!(target_id, 1014);
+        assert_eq!(source_id, 1003);
+        assert_eq!(target_id, 1015);
         assert_eq!(state.known_workspace_id(0, 1, 3), Some(target_id));
         assert_eq!(state.known_workspace_id(0, 2, 5), Some(source_id));
     }
 
+    #[test]
+    fn swap_visible_workspace_ids_keeps_labels_on_the_slot() {
+        let mut state = test_state();
+        let first = state.workspace_id_for(0, 1, 1);
+        let fourth = state.workspace_id_for(0, 1, 4);
+
+        assert_eq!(state.swap_visible_workspace_ids(1, 1, 4), (first, fourth));
+        assert_eq!(state.known_workspace_id(0, 1, 1), Some(fourth));
+        assert_eq!(state.known_workspace_id(0, 1, 4), Some(first));
+        assert_eq!(state.active_visible(1), 1);
+    }
+
     #[test]
     fn snapshot_lists_state_in_stable_order() {
         let mut state = test_state();
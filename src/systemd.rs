@@ -0,0 +1,76 @@
+use std::env;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process;
+
+// sd_listen_fds(3): passed sockets start right after stdio.
+const LISTEN_FDS_START: RawFd = 3;
+
+// The command listener handed over by `hywoma.socket`. LISTEN_PID guards against variables inherited
+// from a parent that was activated itself. Must run before any thread is spawned.
+pub fn listen_fd() -> Option<RawFd> {
+    let pid: u32 = env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: u32 = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != process::id() || fds == 0 {
+        return None;
+    }
+    if fds > 1 {
+        tracing::warn!("systemd passed {fds} sockets, using the first one");
+    }
+    // SAFETY: called from server() before it spawns threads, so nothing reads the environment
+    // concurrently. Hooks and a `restart-server` successor must not mistake the FD for their own.
+    unsafe {
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+        libc::fcntl(LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+    Some(LISTEN_FDS_START)
+}
+
+// Lets a `Type=notify` unit report the daemon as started only once it follows Hyprland, so units
+// ordered after it (bars reading the state socket) do not start against an empty state.
+pub fn notify_ready() {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.to_string_lossy();
+    if let Err(err) = notify(&path, "READY=1") {
+        tracing::warn!("Failed to notify systemd at {path:?}: {err:?}");
+    }
+}
+
+// A leading `@` names a socket in the abstract namespace.
+fn notify(path: &str, state: &str) -> io::Result<()> {
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_path_and_abstract_sockets() {
+        let path = env::temp_dir().join(format!("hywoma-notify-test-{}", process::id()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        notify(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+
+        let name = format!("hywoma-notify-test-{}", process::id());
+        let socket =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+        notify(&format!("@{name}"), "READY=1").unwrap();
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::hyprland::hyprctl_dispatch as hyprctl;
+use crate::rules::Rule;
+use crate::state::{GroupId, SlotId, State, VisibleWorkspace};
+
+// Furnishing for a new group, from `[templates.<name>]`. A group gets its template from
+// `create_group <name> --template <template>` or from `template` in its `[[groups]]` entry, and
+// the template is applied the first time the group becomes active.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupTemplate {
+    pub workspaces: Vec<TemplateWorkspace>,
+    pub scratchpads: Vec<Scratchpad>,
+    // Placement rules for the group while it is active; the group is implied.
+    pub rules: Vec<TemplateRule>,
+    pub autostart: Vec<TemplateCommand>,
+}
+
+// Made persistent in Hyprland, so it exists (and shows up in bars) before any window opens there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateWorkspace {
+    pub slot: SlotId,
+    pub workspace: VisibleWorkspace,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+// Started on the special workspace of the same name, to be toggled with Hyprland's
+// `togglespecialworkspace <name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scratchpad {
+    pub name: String,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateRule {
+    pub class: String,
+    pub workspace: VisibleWorkspace,
+    #[serde(default)]
+    pub slot: Option<SlotId>,
+}
+
+// Without a slot the command starts on the focused slot's workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemplateCommand {
+    pub command: String,
+    pub workspace: VisibleWorkspace,
+    #[serde(default)]
+    pub slot: Option<SlotId>,
+}
+
+fn template_name<'a>(config: &'a Config, state: &'a State, group: GroupId) -> Option<&'a str> {
+    let from_state = state.groups.get(&group)?.template.as_deref();
+    from_state.or_else(|| {
+        config
+            .groups
+            .iter()
+            .find(|entry| entry.id == group)?
+            .template
+            .as_deref()
+    })
+}
+
+fn template_for<'a>(
+    config: &'a Config,
+    state: &State,
+    group: GroupId,
+) -> Option<&'a GroupTemplate> {
+    let name = template_name(config, state, group)?;
+    let template = config.templates.get(name);
+    if template.is_none() {
+        tracing::warn!("Group {group} uses unknown template {name:?}");
+    }
+    template
+}
+
+// Applies the active group's template if it has one and has not been furnished yet. Failures are
+// logged and do not undo the furnishing: retrying would start the autostart commands again.
+pub fn furnish_active_group(config: &Config, state: &mut State, focused_slot: SlotId) -> bool {
+    let group = state.active_group;
+    if state.groups.get(&group).is_none_or(|entry| entry.furnished) {
+        return false;
+    }
+    let Some(template) = template_for(config, state, group) else {
+        return false;
+    };
+    tracing::info!("Furnishing group {group} from its template");
+    if let Some(entry) = state.groups.get_mut(&group) {
+        entry.furnished = true;
+    }
+
+    for workspace in &template.workspaces {
+        let workspace_id = state.workspace_id_for(group, workspace.slot, workspace.workspace);
+        let mut rule = format!("keyword workspace {workspace_id}, persistent:true");
+        if let Some(output) = state
+            .slots
+            .get(&workspace.slot)
+            .and_then(|slot| slot.attached_output.as_deref())
+        {
+            rule.push_str(&format!(", monitor:{output}"));
+        }
+        if let Some(label) = &workspace.label {
+            rule.push_str(&format!(", defaultName:{label}"));
+        }
+        if let Err(err) = hyprctl(config, &rule) {
+            tracing::warn!("Failed to create templated workspace {workspace_id}: {err:?}");
+        }
+    }
+    for scratchpad in &template.scratchpads {
+        let dispatch = format!(
+            "dispatch exec [workspace special:{} silent] {}",
+            scratchpad.name, scratchpad.command
+        );
+        if let Err(err) = hyprctl(config, &dispatch) {
+            tracing::warn!("Failed to start scratchpad {:?}: {err:?}", scratchpad.name);
+        }
+    }
+    for autostart in &template.autostart {
+        let slot = autostart.slot.unwrap_or(focused_slot);
+        let workspace_id = state.workspace_id_for(group, slot, autostart.workspace);
+        let dispatch = format!(
+            "dispatch exec [workspace {workspace_id} silent] {}",
+            autostart.command
+        );
+        if let Err(err) = hyprctl(config, &dispatch) {
+            tracing::warn!("Failed to autostart {:?}: {err:?}", autostart.command);
+        }
+    }
+    true
+}
+
+// The active group's template rule for a class, as a regular rule for that group. Sub-groups use
+// their parent's template rules.
+pub fn matching_rule(config: &Config, state: &State, class: &str) -> Option<Rule> {
+    let group = state.active_group;
+    template_for(config, state, state.settings_group(group))?
+        .rules
+        .iter()
+        .find(|rule| rule.class == class)
+        .map(|rule| Rule {
+            class: rule.class.clone(),
+            group,
+            workspace: rule.workspace,
+            slot: rule.slot,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GroupConfig;
+    use crate::state::Slot;
+
+    #[test]
+    fn template_rules_follow_the_active_group() {
+        let mut config = Config::default();
+        config.templates.insert(
+            "project".to_string(),
+            GroupTemplate {
+                rules: vec![TemplateRule {
+                    class: "kitty".to_string(),
+                    workspace: 2,
+                    slot: None,
+                }],
+                ..GroupTemplate::default()
+            },
+        );
+        config.groups.push(GroupConfig {
+            id: 1,
+            template: Some("project".to_string()),
+            ..GroupConfig::default()
+        });
+        let mut state = State::new([Slot::new(1, "u", "left")]);
+        state.ensure_group(1, "Work");
+
+        assert_eq!(matching_rule(&config, &state, "kitty"), None);
+        state.switch_group(1);
+        assert_eq!(
+            matching_rule(&config, &state, "kitty"),
+            Some(Rule {
+                class: "kitty".to_string(),
+                group: 1,
+                workspace: 2,
+                slot: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::simulate;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailsConfig {
+    // Capture a small screenshot of every workspace the user leaves, for pickers that show
+    // previews. Off by default: every switch runs grim for each monitor it changes.
+    pub enabled: bool,
+    // Defaults to $XDG_CACHE_HOME/hywoma/thumbnails.
+    pub dir: Option<PathBuf>,
+    // Shell command run instead of grim, with HYWOMA_OUTPUT and HYWOMA_THUMBNAIL set to the output
+    // to capture and the file to write.
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Thumbnail {
+    pub workspace_id: u64,
+    pub path: PathBuf,
+    // Unix time of the capture, so pickers can tell a fresh preview from one taken hours ago.
+    pub captured_at: i64,
+}
+
+// Thumbnails taken by this daemon, keyed by workspace ID. Files left over from an earlier run are
+// not listed: workspace IDs are reused, so they may show something else entirely.
+#[derive(Debug, Default)]
+pub struct Thumbnails {
+    entries: Arc<Mutex<Entries>>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    taken: BTreeMap<u64, Thumbnail>,
+    // Workspaces with a capture running. One forgotten meanwhile does not get its stale picture.
+    capturing: HashSet<u64>,
+}
+
+thread_local! {
+    // What the message being handled would replace. Taken by its first dispatch, so a command that
+    // turns out to be a no-op or is refused captures nothing.
+    static PENDING: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+struct Capture {
+    config: ThumbnailsConfig,
+    leaving: Vec<(u64, String)>,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl Thumbnails {
+    // `leaving` is (workspace ID, output name) for every workspace the message would replace on
+    // its monitor. Set by the main loop before each message.
+    pub fn capture_on_dispatch(&self, config: &ThumbnailsConfig, leaving: Vec<(u64, String)>) {
+        // A simulated session has no outputs to capture.
+        let capture =
+            (config.enabled && !leaving.is_empty() && !simulate::is_active()).then(|| Capture {
+                config: config.clone(),
+                leaving,
+                entries: Arc::clone(&self.entries),
+            });
+        PENDING.set(capture);
+    }
+
+    // Called when Hyprland destroys a workspace. Its ID comes back for the next workspace of the
+    // same key, which starts out empty.
+    pub fn forget(&mut self, workspace_id: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.capturing.remove(&workspace_id);
+        if let Some(thumbnail) = entries.taken.remove(&workspace_id) {
+            let _ = fs::remove_file(thumbnail.path);
+        }
+    }
+
+    pub fn list(&self) -> Vec<Thumbnail> {
+        self.entries
+            .lock()
+            .unwrap()
+            .taken
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+// Called by the main loop once the message is handled, whether or not it dispatched anything.
+pub fn drop_pending() {
+    PENDING.take();
+}
+
+// Starts the captures of the message being handled, right before its switch is dispatched. They
+// run on their own thread alongside the switch, so a slow capture may catch the start of the switch
+// animation rather than hold up the main loop.
+pub fn start_pending() {
+    let Some(capture) = PENDING.take() else {
+        return;
+    };
+    capture.entries.lock().unwrap().capturing.extend(
+        capture
+            .leaving
+            .iter()
+            .map(|(workspace_id, _)| *workspace_id),
+    );
+    thread::spawn(move || capture.run());
+}
+
+impl Capture {
+    fn run(self) {
+        let dir = match thumbnail_dir(&self.config) {
+            Ok(dir) => dir,
+            Err(err) => {
+                tracing::warn!("Failed to resolve thumbnail directory: {err:?}");
+                return;
+            }
+        };
+        if let Err(err) = fs::create_dir_all(&dir) {
+            tracing::warn!("Failed to create thumbnail directory {dir:?}: {err:?}");
+            return;
+        }
+
+        let config = &self.config;
+        let captured: Vec<(u64, PathBuf, Result<()>)> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .leaving
+                .iter()
+                .map(|(workspace_id, output)| {
+                    let path = dir.join(format!("{workspace_id}.png"));
+                    scope.spawn(move || {
+                        let result = run_capture(config, output, &path);
+                        (*workspace_id, path, result)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+
+        let captured_at = chrono::Local::now().timestamp();
+        let mut entries = self.entries.lock().unwrap();
+        for (workspace_id, path, result) in captured {
+            if !entries.capturing.remove(&workspace_id) {
+                continue;
+            }
+            match result {
+                Ok(()) => {
+                    entries.taken.insert(
+                        workspace_id,
+                        Thumbnail {
+                            workspace_id,
+                            path,
+                            captured_at,
+                        },
+                    );
+                }
+                Err(err) => tracing::warn!("Failed to capture workspace {workspace_id}: {err:?}"),
+            }
+        }
+    }
+}
+
+fn thumbnail_dir(config: &ThumbnailsConfig) -> Result<PathBuf> {
+    if let Some(dir) = &config.dir {
+        return Ok(dir.clone());
+    }
+    let cache_home = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map_err(|_| anyhow!("Neither XDG_CACHE_HOME nor HOME is set"))?;
+    Ok(cache_home.join("hywoma").join("thumbnails"))
+}
+
+fn run_capture(config: &ThumbnailsConfig, output: &str, path: &Path) -> Result<()> {
+    let mut child = match &config.command {
+        Some(command) => {
+            let mut child = Command::new("sh");
+            child
+                .arg("-c")
+                .arg(command)
+                .env("HYWOMA_OUTPUT", output)
+                .env("HYWOMA_THUMBNAIL", path);
+            child
+        }
+        None => {
+            let mut child = Command::new("grim");
+            child.args(["-s", "0.2", "-o", output]).arg(path);
+            child
+        }
+    };
+    let status = child.stdin(Stdio::null()).status()?;
+    if !status.success() {
+        bail!("Capture of output {output} exited with {status}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn captures_only_once_the_switch_is_dispatched() {
+        let dir = env::temp_dir().join(format!("hywoma-thumbnails-test-{}", std::process::id()));
+        let config = ThumbnailsConfig {
+            enabled: true,
+            dir: Some(dir.clone()),
+            command: Some("touch \"$HYWOMA_THUMBNAIL\"".to_string()),
+        };
+        let thumbnails = Thumbnails::default();
+
+        thumbnails.capture_on_dispatch(&config, vec![(3, "DP-1".to_string())]);
+        drop_pending();
+        start_pending();
+        assert!(thumbnails.list().is_empty());
+
+        thumbnails.capture_on_dispatch(&config, vec![(3, "DP-1".to_string())]);
+        start_pending();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while thumbnails.list().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let listed = thumbnails.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].path, dir.join("3.png"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::app::Message;
+use crate::hyprland;
+
+// A hung compositor otherwise blocks the main loop inside a dispatch, and every keybind pressed
+// meanwhile fails without a trace. The watchdog notices first, so commands are queued instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    // Seconds between pings of Hyprland's command socket. Unset disables the watchdog.
+    pub interval_secs: Option<u64>,
+    // How long Hyprland may go without answering before it is considered unhealthy.
+    pub unhealthy_after_secs: u64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            interval_secs: None,
+            unhealthy_after_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Health {
+    last_answer: Instant,
+    healthy: bool,
+}
+
+impl Health {
+    fn new(now: Instant) -> Self {
+        Health {
+            last_answer: now,
+            healthy: true,
+        }
+    }
+
+    // Returns the new health when it changed.
+    fn observe(&mut self, answered: bool, now: Instant, unhealthy_after: Duration) -> Option<bool> {
+        if answered {
+            self.last_answer = now;
+        }
+        let healthy = now.duration_since(self.last_answer) < unhealthy_after;
+        if healthy == self.healthy {
+            return None;
+        }
+        self.healthy = healthy;
+        Some(healthy)
+    }
+}
+
+pub fn spawn(config: &WatchdogConfig, tx: mpsc::Sender<Message>) {
+    let Some(interval) = config.interval_secs.filter(|interval| *interval > 0) else {
+        return;
+    };
+    let interval = Duration::from_secs(interval);
+    let unhealthy_after = Duration::from_secs(config.unhealthy_after_secs);
+    thread::spawn(move || {
+        let mut health = Health::new(Instant::now());
+        loop {
+            let answered = match hyprland::ping(unhealthy_after) {
+                Ok(()) => true,
+                Err(err) => {
+                    tracing::warn!("Hyprland did not answer the watchdog: {err:?}");
+                    false
+                }
+            };
+            if let Some(healthy) = health.observe(answered, Instant::now(), unhealthy_after)
+                && tx.send(Message::BackendHealth(healthy)).is_err()
+            {
+                return;
+            }
+            thread::sleep(interval);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_changes_after_the_grace_period() {
+        let start = Instant::now();
+        let after = |secs| start + Duration::from_secs(secs);
+        let unhealthy_after = Duration::from_secs(10);
+        let mut health = Health::new(start);
+
+        assert_eq!(health.observe(false, after(5), unhealthy_after), None);
+        assert_eq!(
+            health.observe(false, after(10), unhealthy_after),
+            Some(false)
+        );
+        assert_eq!(health.observe(false, after(15), unhealthy_after), None);
+        assert_eq!(health.observe(true, after(20), unhealthy_after), Some(true));
+        assert_eq!(health.observe(true, after(25), unhealthy_after), None);
+    }
+}